@@ -0,0 +1,108 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// 嵌套构建启动器时设置的环境变量，防止递归
+const NESTED_ENV: &str = "SEKAIPACK_LAUNCHER_BUILD";
+
+// 额外构建并嵌入的其他平台启动器的目标三元组（逗号分隔），供 `--target-os` 使用
+const CROSS_TARGETS_ENV: &str = "SEKAIPACK_CROSS_TARGETS";
+
+// `--target-os` 可选的平台，其他平台的启动器写入 `sekai-launcher-<平台>`，未构建的为空文件
+const TARGET_OSES: [&str; 3] = ["linux", "windows", "macos"];
+
+fn main() {
+    println!("cargo:rerun-if-changed=src");
+    println!("cargo:rerun-if-changed=Cargo.toml");
+    println!("cargo:rerun-if-env-changed={}", NESTED_ENV);
+    println!("cargo:rerun-if-env-changed={}", CROSS_TARGETS_ENV);
+
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    let stub_path = out_dir.join("sekai-launcher");
+    for os in TARGET_OSES {
+        fs::write(out_dir.join(format!("sekai-launcher-{}", os)), b"").unwrap();
+    }
+
+    // 正在构建启动器本身，写入空占位文件即可
+    if env::var_os(NESTED_ENV).is_some() {
+        fs::write(&stub_path, b"").unwrap();
+        return;
+    }
+
+    let target = env::var("TARGET").unwrap();
+    fs::copy(build_launcher(&target, &out_dir), &stub_path).unwrap();
+
+    let cross = env::var(CROSS_TARGETS_ENV).unwrap_or_default();
+    let mut built_oses = Vec::new();
+    for cross_target in cross.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+        let os = target_os(cross_target).unwrap_or_else(|| {
+            panic!(
+                "{}: unsupported launcher target '{}'",
+                CROSS_TARGETS_ENV, cross_target
+            )
+        });
+        if target_os(&target) == Some(os) || built_oses.contains(&os) {
+            panic!("{}: more than one launcher for {}", CROSS_TARGETS_ENV, os);
+        }
+        built_oses.push(os);
+        let built = build_launcher(cross_target, &out_dir);
+        fs::copy(&built, out_dir.join(format!("sekai-launcher-{}", os))).unwrap();
+    }
+}
+
+// 目标三元组所属的平台
+fn target_os(target: &str) -> Option<&'static str> {
+    if target.contains("windows") {
+        Some("windows")
+    } else if target.contains("apple") {
+        Some("macos")
+    } else if target.contains("linux") {
+        Some("linux")
+    } else {
+        None
+    }
+}
+
+// 为 `target` 构建启动器，返回其路径
+fn build_launcher(target: &str, out_dir: &Path) -> PathBuf {
+    let manifest = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.toml");
+    let target_dir = out_dir.join("launcher-target");
+
+    // 静态链接，保证启动器不依赖目标机器上的动态库
+    let rustflags = if target.ends_with("linux-gnu") || target.ends_with("windows-msvc") {
+        "-Ctarget-feature=+crt-static"
+    } else {
+        ""
+    };
+
+    let status = Command::new(env::var("CARGO").unwrap())
+        .args([
+            "build",
+            "--release",
+            "--bin",
+            "sekai-launcher",
+            "--target",
+            target,
+        ])
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(&target_dir)
+        .env(NESTED_ENV, "1")
+        .env("CARGO_ENCODED_RUSTFLAGS", rustflags)
+        .env("CARGO_PROFILE_RELEASE_STRIP", "true")
+        .env_remove("RUSTC_WORKSPACE_WRAPPER")
+        .status()
+        .expect("failed to run cargo for sekai-launcher");
+    if !status.success() {
+        panic!("failed to build sekai-launcher for {}", target);
+    }
+
+    let exe_name = if target.contains("windows") {
+        "sekai-launcher.exe"
+    } else {
+        "sekai-launcher"
+    };
+    target_dir.join(target).join("release").join(exe_name)
+}
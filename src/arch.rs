@@ -0,0 +1,121 @@
+// 可执行文件的目标架构：从ELF、PE或Mach-O头中识别，名称与 `std::env::consts::ARCH` 一致
+//
+// 打包时记录主程序的架构，启动器在解压前与自身的架构比对，避免在不兼容的机器上运行时崩溃。
+
+/// 识别可执行文件开头的字节所对应的架构，脚本、通用二进制（fat Mach-O）与无法识别的格式为 `None`
+pub fn detect(header: &[u8]) -> Option<&'static str> {
+    let u16_at = |offset: usize, big_endian: bool| {
+        let bytes: [u8; 2] = header.get(offset..offset + 2)?.try_into().ok()?;
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes: [u8; 4] = header.get(offset..offset + 4)?.try_into().ok()?;
+        Some(u32::from_le_bytes(bytes))
+    };
+
+    if header.starts_with(b"\x7fELF") {
+        // e_ident[EI_DATA] 为2时是大端序
+        let machine = u16_at(18, header.get(5) == Some(&2))?;
+        return match machine {
+            3 => Some("x86"),
+            62 => Some("x86_64"),
+            40 => Some("arm"),
+            183 => Some("aarch64"),
+            243 if header.get(4) == Some(&2) => Some("riscv64"),
+            8 => Some("mips"),
+            20 => Some("powerpc"),
+            21 => Some("powerpc64"),
+            22 => Some("s390x"),
+            _ => None,
+        };
+    }
+    if header.starts_with(b"MZ") {
+        let pe = u32_at(0x3c)? as usize;
+        if header.get(pe..pe + 4)? != b"PE\0\0" {
+            return None;
+        }
+        return match u16_at(pe + 4, false)? {
+            0x14c => Some("x86"),
+            0x8664 => Some("x86_64"),
+            0x1c0 | 0x1c4 => Some("arm"),
+            0xaa64 => Some("aarch64"),
+            _ => None,
+        };
+    }
+    if header.starts_with(b"\xcf\xfa\xed\xfe") || header.starts_with(b"\xce\xfa\xed\xfe") {
+        return match u32_at(4)? {
+            7 => Some("x86"),
+            0x0100_0007 => Some("x86_64"),
+            12 => Some("arm"),
+            0x0100_000c => Some("aarch64"),
+            _ => None,
+        };
+    }
+    None
+}
+
+/// 可执行文件的格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Elf,
+    Pe,
+    MachO,
+    /// 以 `#!` 开头的脚本
+    Script,
+}
+
+impl Format {
+    /// 运行sekaipack的平台的原生格式，即内置启动器的格式
+    pub const NATIVE: Format = if cfg!(windows) {
+        Format::Pe
+    } else if cfg!(target_vendor = "apple") {
+        Format::MachO
+    } else {
+        Format::Elf
+    };
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Elf => "ELF",
+            Format::Pe => "PE",
+            Format::MachO => "Mach-O",
+            Format::Script => "script",
+        }
+    }
+
+    /// 格式为 `self` 的启动器能否执行格式为 `target` 的主程序：格式相同，或非Windows平台上的脚本
+    pub fn can_run(self, target: Format) -> bool {
+        self == target || (target == Format::Script && self != Format::Pe)
+    }
+}
+
+/// 识别可执行文件开头的字节所对应的格式，包括通用二进制（fat Mach-O）
+pub fn format(header: &[u8]) -> Option<Format> {
+    if header.starts_with(b"\x7fELF") {
+        Some(Format::Elf)
+    } else if header.starts_with(b"MZ") {
+        Some(Format::Pe)
+    } else if [
+        b"\xcf\xfa\xed\xfe",
+        b"\xce\xfa\xed\xfe",
+        b"\xca\xfe\xba\xbe",
+    ]
+    .iter()
+    .any(|magic| header.starts_with(*magic))
+    {
+        Some(Format::MachO)
+    } else if header.starts_with(b"#!") {
+        Some(Format::Script)
+    } else {
+        None
+    }
+}
+
+/// 架构为 `host` 的启动器能否运行架构为 `target` 的主程序：相同，或64位系统上对应的32位程序
+pub fn is_compatible(host: &str, target: &str) -> bool {
+    host == target || matches!((host, target), ("x86_64", "x86") | ("aarch64", "arm"))
+}
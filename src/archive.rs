@@ -0,0 +1,952 @@
+// 资源包的生成：将主程序与资源目录写入（压缩的）tar流
+
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use globset::GlobSet;
+use log::{debug, info, warn};
+
+use crate::build_cache::ChunkWriter;
+use crate::checksum::Checksum;
+use crate::compression::Compression;
+use crate::manifest;
+use crate::{BundleError, BundlePlan, Progress};
+
+// `--store-aligned` 时每个文件的内容在打包文件中的起始位置都是此值的整数倍
+pub(crate) const ALIGNMENT: u64 = 4096;
+
+// 可执行文件的来源：磁盘上的文件，或 `bundle_to_vec` 传入的内容
+#[derive(Clone, Copy)]
+pub(crate) enum Source<'a> {
+    File(&'a Path),
+    Memory(&'a [u8]),
+}
+
+impl<'a> Source<'a> {
+    pub(crate) fn len(&self) -> io::Result<u64> {
+        match self {
+            Source::File(path) => Ok(fs::metadata(path)?.len()),
+            Source::Memory(data) => Ok(data.len() as u64),
+        }
+    }
+
+    // 打开内容并生成对应的tar头
+    fn open(&self, plan: &BundlePlan) -> io::Result<(Box<dyn Read + 'a>, tar::Header)> {
+        match *self {
+            Source::File(path) => {
+                let file = fs::File::open(path)?;
+                let header = entry_header(plan, &file.metadata()?);
+                Ok((Box::new(file), header))
+            }
+            Source::Memory(data) => {
+                let header = memory_header(plan, tar::EntryType::Regular, data.len() as u64);
+                Ok((Box::new(data), header))
+            }
+        }
+    }
+
+    // 出错时报告的路径，内存中的内容以包内文件名代替
+    fn error_path<'p>(&self, name: &'p str) -> &'p Path
+    where
+        'a: 'p,
+    {
+        match *self {
+            Source::File(path) => path,
+            Source::Memory(_) => Path::new(name),
+        }
+    }
+}
+
+// 生成的资源包的统计信息
+pub(crate) struct ResourcePackage {
+    // 加入的文件数（包括可执行文件）
+    pub(crate) files: usize,
+    // 所有文件的未压缩总大小
+    pub(crate) unpacked_size: u64,
+    // 单独压缩的可执行文件段的长度，没有该段时为0
+    pub(crate) exe_section_len: u64,
+    // `--store-aligned` 时每个文件的包内路径、内容在打包文件中的偏移与长度
+    pub(crate) aligned: Vec<(PathBuf, u64, u64)>,
+}
+
+// 将资源tar流直接写入 `writer`（通常为输出文件中启动器之后的位置，`offset` 为该位置在
+// 打包文件中的偏移，用于 `--store-aligned` 的对齐），每加入一个文件调用一次 `report`
+pub(crate) fn create_resource_package<W: Write>(
+    plan: &BundlePlan,
+    writer: W,
+    offset: u64,
+    report: &mut dyn FnMut(&Progress),
+) -> Result<ResourcePackage, BundleError> {
+    write_resource_package(plan, writer, offset, report).map_err(archive_error)
+}
+
+// 附带出错时正在加入的源文件路径的io错误，原样沿io错误传递，最终由 `archive_error` 取出
+#[derive(Debug)]
+struct EntryError {
+    path: PathBuf,
+    source: io::Error,
+}
+
+impl fmt::Display for EntryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.source)
+    }
+}
+
+impl std::error::Error for EntryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+// 为错误附上源文件路径；已经附带路径（来自更深一层的条目）时保持不变
+fn entry_error(path: &Path, e: io::Error) -> io::Error {
+    if e.get_ref().is_some_and(|inner| inner.is::<EntryError>()) {
+        return e;
+    }
+    io::Error::new(
+        e.kind(),
+        EntryError {
+            path: path.to_path_buf(),
+            source: e,
+        },
+    )
+}
+
+// 生成资源包时的io错误：附带了源文件路径时为 `ArchiveEntry`，否则为 `ArchiveFailed`
+pub(crate) fn archive_error(e: io::Error) -> BundleError {
+    if !e.get_ref().is_some_and(|inner| inner.is::<EntryError>()) {
+        return BundleError::ArchiveFailed(e);
+    }
+    match e.into_inner().map(|inner| inner.downcast::<EntryError>()) {
+        Some(Ok(entry)) => BundleError::ArchiveEntry {
+            path: entry.path,
+            source: entry.source,
+        },
+        _ => unreachable!("checked to be an EntryError above"),
+    }
+}
+
+// 一个tar流中包含的条目
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Section {
+    All,
+    Executables,
+    Resources,
+}
+
+fn write_resource_package<W: Write>(
+    plan: &BundlePlan,
+    mut writer: W,
+    offset: u64,
+    report: &mut dyn FnMut(&Progress),
+) -> io::Result<ResourcePackage> {
+    // 先统计总数，进度回调据此显示完成比例
+    let mut progress = count_files(plan)?;
+
+    // 可执行文件单独压缩时先写入只含可执行文件的tar流，其后是只含资源的tar流
+    let (section, exe_section_len) = match plan.opts.exe_compression {
+        Some(compression) => {
+            let mut counter = CountingWriter {
+                inner: &mut writer,
+                count: 0,
+            };
+            write_stream(
+                plan,
+                &mut counter,
+                compression,
+                plan.exe_level,
+                Section::Executables,
+                &mut progress,
+                report,
+            )?;
+            (Section::Resources, counter.count)
+        }
+        None => (Section::All, 0),
+    };
+    let mut aligned = Vec::new();
+    if plan.opts.store_aligned {
+        // 对齐需要知道每个条目在打包文件中的位置，只用于不压缩的单个tar流
+        let mut counter = CountingWriter {
+            inner: &mut writer,
+            count: offset,
+        };
+        let mut builder = tar::Builder::new(&mut counter);
+        let mut align = Align {
+            position: |builder: &tar::Builder<&mut CountingWriter<&mut W>>| builder.get_ref().count,
+            files: &mut aligned,
+        };
+        append_resources(
+            &mut builder,
+            plan,
+            section,
+            &mut progress,
+            report,
+            Some(&mut align),
+        )?;
+        builder.into_inner()?;
+    } else {
+        write_stream(
+            plan,
+            &mut writer,
+            plan.opts.compression,
+            plan.level,
+            section,
+            &mut progress,
+            report,
+        )?;
+    }
+    writer.flush()?;
+
+    Ok(ResourcePackage {
+        files: progress.files,
+        unpacked_size: progress.bytes,
+        exe_section_len,
+        aligned,
+    })
+}
+
+fn write_stream<W: Write>(
+    plan: &BundlePlan,
+    writer: W,
+    compression: Compression,
+    level: u32,
+    section: Section,
+    progress: &mut Progress,
+    report: &mut dyn FnMut(&Progress),
+) -> io::Result<()> {
+    use flate2::write::GzEncoder;
+
+    // 使用打包缓存时按块分别压缩，未改动的块直接取自缓存
+    if let Some(dir) = &plan.opts.cache_dir
+        && compression != Compression::None
+        && compression != Compression::Brotli
+    {
+        let mut chunks = ChunkWriter::new(writer, dir, compression, level, plan.threads)?;
+        let mut builder = tar::Builder::new(&mut chunks);
+        append_resources(&mut builder, plan, section, progress, report, None)?;
+        builder.into_inner()?;
+        chunks.finish()?;
+        info!(
+            "Reused {} of {} compressed chunks from {}",
+            chunks.reused,
+            chunks.chunks,
+            dir.display()
+        );
+        return Ok(());
+    }
+
+    let mut writer = match compression {
+        Compression::Gzip => {
+            let encoder = GzEncoder::new(writer, flate2::Compression::new(level));
+            let mut builder = tar::Builder::new(encoder);
+            append_resources(&mut builder, plan, section, progress, report, None)?;
+            // 完成tar流并刷新gzip尾部
+            builder.into_inner()?.finish()?
+        }
+        Compression::Zstd => {
+            // 多线程模式输出的仍是单个zstd帧序列，且与线程数无关，可重现打包不受影响
+            let mut encoder = zstd::Encoder::new(writer, level as i32)?;
+            encoder.multithread(plan.threads)?;
+            let mut builder = tar::Builder::new(encoder);
+            append_resources(&mut builder, plan, section, progress, report, None)?;
+            builder.into_inner()?.finish()?
+        }
+        Compression::Brotli => {
+            let trap = ErrorTrap {
+                inner: writer,
+                error: None,
+            };
+            let encoder = brotli::CompressorWriter::new(trap, 64 * 1024, level, BROTLI_LGWIN);
+            let mut builder = tar::Builder::new(encoder);
+            append_resources(&mut builder, plan, section, progress, report, None)?;
+            // `into_inner` 写入结束块时不返回错误，之后再检查
+            let trap = builder.into_inner()?.into_inner();
+            if let Some(e) = trap.error {
+                return Err(e);
+            }
+            trap.inner
+        }
+        Compression::None => {
+            let mut builder = tar::Builder::new(writer);
+            append_resources(&mut builder, plan, section, progress, report, None)?;
+            builder.into_inner()?
+        }
+    };
+    writer.flush()
+}
+
+// brotli的窗口大小（以2为底的对数），取其标准格式允许的最大值
+const BROTLI_LGWIN: u32 = 24;
+
+// 记下写入时的第一个错误，brotli完成压缩流时会忽略写入错误
+struct ErrorTrap<W> {
+    inner: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Write for ErrorTrap<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf).inspect_err(|e| {
+            self.error
+                .get_or_insert_with(|| io::Error::new(e.kind(), e.to_string()));
+        })
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush().inspect_err(|e| {
+            self.error
+                .get_or_insert_with(|| io::Error::new(e.kind(), e.to_string()));
+        })
+    }
+}
+
+// 统计写入的字节数，用于记录可执行文件段的长度
+struct CountingWriter<W> {
+    inner: W,
+    count: u64,
+}
+
+impl<W: Write> Write for CountingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.count += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+fn count_files(plan: &BundlePlan) -> io::Result<Progress> {
+    let mut progress = Progress {
+        files: 0,
+        total_files: 0,
+        bytes: 0,
+        total_bytes: 0,
+    };
+    for (source, _) in &plan.executables {
+        progress.total_files += 1;
+        progress.total_bytes += source.len()?;
+    }
+    for (source, _) in &plan.data_files {
+        progress.total_files += 1;
+        progress.total_bytes += fs::metadata(source)?.len();
+    }
+    for (_, data) in &plan.memory_resources {
+        progress.total_files += 1;
+        progress.total_bytes += data.len() as u64;
+    }
+    walk_resources(plan, &mut |entry| {
+        if entry.kind == EntryKind::File {
+            progress.total_files += 1;
+            progress.total_bytes += fs::metadata(&entry.source)?.len();
+        }
+        Ok(())
+    })?;
+    Ok(progress)
+}
+
+// `--store-aligned` 与 `--entry-checksums` 在清单中按行记录包内路径，
+// 含换行或不是UTF-8的路径无法原样记录，在开始写入前报错
+pub(crate) fn check_indexed_paths(plan: &BundlePlan) -> io::Result<()> {
+    let check = |path: &Path, source: &Path| match path.to_str() {
+        Some(name) if !name.contains(['\n', '\r']) => Ok(()),
+        _ => Err(entry_error(
+            source,
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                "--store-aligned and --entry-checksums require file names that are valid UTF-8 without line breaks",
+            ),
+        )),
+    };
+    for (source, name) in &plan.executables {
+        check(Path::new(name), source.error_path(name))?;
+    }
+    for (source, name) in &plan.data_files {
+        check(Path::new(name), source)?;
+    }
+    walk_resources(plan, &mut |entry| check(&entry.archive_path, &entry.source))?;
+    for (path, _) in &plan.memory_resources {
+        check(path, path)?;
+    }
+    Ok(())
+}
+
+// `--dedup`：按打包顺序找出内容与权限都与之前的某个资源文件相同的文件，返回（重复文件, 原文件）
+// 的包内路径。重复文件不写入资源包，记录在清单的 `dedup` 中由解压方重建；
+// 包内路径无法在清单中按行记录的文件照常保存，不参与去重
+pub(crate) fn find_duplicates(plan: &BundlePlan) -> io::Result<Vec<(PathBuf, PathBuf)>> {
+    let mut stored: HashMap<(u64, u32, String), PathBuf> = HashMap::new();
+    let mut duplicates = Vec::new();
+    let mut add = |path: &Path, header: &tar::Header, data: &mut dyn Read| -> io::Result<()> {
+        if path
+            .to_str()
+            .is_none_or(|name| name.contains(['\n', '\r', '\t']))
+        {
+            return Ok(());
+        }
+        let key = (
+            header.size()?,
+            header.mode()?,
+            Checksum::Sha256.compute(io::BufReader::new(data))?,
+        );
+        match stored.get(&key) {
+            Some(original) => duplicates.push((path.to_path_buf(), original.clone())),
+            None => {
+                stored.insert(key, path.to_path_buf());
+            }
+        }
+        Ok(())
+    };
+    walk_resources(plan, &mut |entry| match entry.kind {
+        EntryKind::File => {
+            let mut file = fs::File::open(&entry.source)?;
+            let header = entry_header(plan, &file.metadata()?);
+            add(&entry.archive_path, &header, &mut file)
+        }
+        EntryKind::Dir | EntryKind::Symlink => Ok(()),
+    })?;
+    for (path, data) in &plan.memory_resources {
+        let header = memory_header(plan, tar::EntryType::Regular, data.len() as u64);
+        add(path, &header, &mut &data[..]).map_err(|e| entry_error(path, e))?;
+    }
+    Ok(duplicates)
+}
+
+// `--entry-checksums`：按与资源包相同的顺序计算每个文件内容的校验和，返回记录在清单中的文本
+pub(crate) fn entry_checksums(plan: &BundlePlan) -> io::Result<String> {
+    let checksum = plan.opts.checksum;
+    let mut lines = String::new();
+    let mut add = |data: &mut dyn Read, path: &Path| -> io::Result<()> {
+        let digest = checksum.compute(io::BufReader::new(data))?;
+        lines.push_str(&format!("{} {}\n", digest, path.to_string_lossy()));
+        Ok(())
+    };
+    let open = |source: &Path| fs::File::open(source).map_err(|e| entry_error(source, e));
+    for (source, name) in &plan.executables {
+        let (mut data, _) = source
+            .open(plan)
+            .map_err(|e| entry_error(source.error_path(name), e))?;
+        add(&mut data, Path::new(name))?;
+    }
+    for (source, name) in &plan.data_files {
+        add(
+            &mut open(source)?,
+            &Path::new(manifest::DATA_DIR).join(name),
+        )?;
+    }
+    walk_resources(plan, &mut |entry| match entry.kind {
+        EntryKind::File => add(&mut fs::File::open(&entry.source)?, &entry.archive_path),
+        EntryKind::Dir | EntryKind::Symlink => Ok(()),
+    })?;
+    for (path, data) in &plan.memory_resources {
+        add(&mut &data[..], path)?;
+    }
+    Ok(lines)
+}
+
+// `--store-aligned` 时的对齐状态：`position` 返回tar流当前在打包文件中的偏移，`files` 收集每个文件内容的位置
+struct Align<'a, W: Write> {
+    position: fn(&tar::Builder<W>) -> u64,
+    files: &'a mut Vec<(PathBuf, u64, u64)>,
+}
+
+fn append_resources<W: Write>(
+    builder: &mut tar::Builder<W>,
+    plan: &BundlePlan,
+    section: Section,
+    progress: &mut Progress,
+    report: &mut dyn FnMut(&Progress),
+    mut align: Option<&mut Align<W>>,
+) -> io::Result<()> {
+    // 添加主程序与其他可执行文件；它们需要能被执行，因此始终补上可执行位（例如在 Windows 上打包时）
+    let executables = match section {
+        Section::Resources => &[][..],
+        Section::All | Section::Executables => &plan.executables[..],
+    };
+    for (source, name) in executables {
+        let mut add = || -> io::Result<()> {
+            let (mut exe, mut header) = source.open(plan)?;
+            header.set_mode(header.mode()? | 0o111);
+            debug!("Adding {} ({} bytes)", name, header.size()?);
+            append_file(
+                builder,
+                &mut header,
+                Path::new(name),
+                &mut exe,
+                align.as_deref_mut(),
+            )?;
+            progress.add_file(header.size()?);
+            report(progress);
+            Ok(())
+        };
+        add().map_err(|e| entry_error(source.error_path(name), e))?;
+    }
+    if section == Section::Executables {
+        return Ok(());
+    }
+
+    // 附加的数据文件放在单独的目录中，不参与排除规则与去重
+    if !plan.data_files.is_empty() {
+        let dir = Path::new(manifest::DATA_DIR);
+        let mut header = entry_header(plan, &fs::metadata(plan.data_files[0].0)?);
+        header.set_entry_type(tar::EntryType::Directory);
+        header.set_size(0);
+        header.set_mode(0o755);
+        builder.append_data(&mut header, dir, io::empty())?;
+        for (source, name) in &plan.data_files {
+            let mut add = || -> io::Result<()> {
+                let mut file = fs::File::open(source)?;
+                let mut header = entry_header(plan, &file.metadata()?);
+                debug!("Adding data file {} ({} bytes)", name, header.size()?);
+                append_file(
+                    builder,
+                    &mut header,
+                    &dir.join(name),
+                    &mut file,
+                    align.as_deref_mut(),
+                )?;
+                progress.add_file(header.size()?);
+                report(progress);
+                Ok(())
+            };
+            add().map_err(|e| entry_error(source, e))?;
+        }
+    }
+
+    // 去重时 `find_duplicates` 找出的重复文件不写入资源包，由解压方按清单重建
+    let duplicates: HashMap<&Path, &Path> = plan
+        .duplicates
+        .iter()
+        .map(|(path, original)| (path.as_path(), original.as_path()))
+        .collect();
+    let (mut dedup_files, mut dedup_bytes) = (0, 0);
+
+    // 添加资源目录，保持与原先 `cp -r` 相同的布局（以目录名为前缀）
+    walk_resources(plan, &mut |entry| match entry.kind {
+        // 每个目录都有单独的条目，空目录（如程序依赖的 `saves/`）解压时同样会被创建
+        EntryKind::Dir => {
+            debug!("Adding {}/", entry.archive_path.display());
+            let mut header = entry_header(plan, &fs::metadata(&entry.source)?);
+            builder.append_data(&mut header, &entry.archive_path, io::empty())
+        }
+        EntryKind::File => {
+            // 跟随符号链接时 walk_resources 给出的源路径可能是链接，按其目标的内容保存
+            let mut file = fs::File::open(&entry.source)?;
+            let mut header = entry_header(plan, &file.metadata()?);
+            let size = header.size()?;
+            match duplicates.get(entry.archive_path.as_path()) {
+                Some(original) => {
+                    skip_duplicate(&entry.archive_path, original, align.as_deref_mut());
+                    dedup_files += 1;
+                    dedup_bytes += size;
+                }
+                None => {
+                    debug!("Adding {} ({} bytes)", entry.archive_path.display(), size);
+                    append_file(
+                        builder,
+                        &mut header,
+                        &entry.archive_path,
+                        &mut file,
+                        align.as_deref_mut(),
+                    )?;
+                }
+            }
+            progress.add_file(size);
+            report(progress);
+            Ok(())
+        }
+        EntryKind::Symlink => {
+            let target = fs::read_link(&entry.source)?;
+            debug!(
+                "Adding {} -> {}",
+                entry.archive_path.display(),
+                target.display()
+            );
+            let mut header = entry_header(plan, &fs::symlink_metadata(&entry.source)?);
+            builder.append_link(&mut header, &entry.archive_path, &target)
+        }
+    })?;
+
+    // `bundle_to_vec` 传入的资源文件，上级目录在第一次用到时补上目录条目
+    let mut dirs = HashSet::new();
+    for (path, data) in &plan.memory_resources {
+        let mut add = || -> io::Result<()> {
+            let mut parents: Vec<&Path> = path
+                .ancestors()
+                .skip(1)
+                .filter(|dir| !dir.as_os_str().is_empty())
+                .collect();
+            parents.reverse();
+            for dir in parents {
+                if dirs.insert(dir) {
+                    debug!("Adding {}/", dir.display());
+                    let mut header = memory_header(plan, tar::EntryType::Directory, 0);
+                    builder.append_data(&mut header, dir, io::empty())?;
+                }
+            }
+            let size = data.len() as u64;
+            let mut header = memory_header(plan, tar::EntryType::Regular, size);
+            match duplicates.get(path.as_path()) {
+                Some(original) => {
+                    skip_duplicate(path, original, align.as_deref_mut());
+                    dedup_files += 1;
+                    dedup_bytes += size;
+                }
+                None => {
+                    debug!("Adding {} ({} bytes)", path.display(), size);
+                    append_file(
+                        builder,
+                        &mut header,
+                        path,
+                        &mut &data[..],
+                        align.as_deref_mut(),
+                    )?;
+                }
+            }
+            progress.add_file(size);
+            report(progress);
+            Ok(())
+        };
+        add().map_err(|e| entry_error(path, e))?;
+    }
+    if dedup_files > 0 {
+        info!("Deduplicated {} files ({} bytes)", dedup_files, dedup_bytes);
+    }
+
+    Ok(())
+}
+
+// 跳过与 `original` 内容、权限都相同的重复文件；对齐时它与原文件记录同一位置
+fn skip_duplicate<W: Write>(path: &Path, original: &Path, align: Option<&mut Align<W>>) {
+    debug!(
+        "Skipping {} (same as {})",
+        path.display(),
+        original.display()
+    );
+    if let Some(align) = align
+        && let Some(&(_, offset, len)) = align.files.iter().find(|(file, _, _)| file == original)
+    {
+        align.files.push((path.to_path_buf(), offset, len));
+    }
+}
+
+// 写入一个文件条目；对齐时先写入一个pax扩展头，其中的 `path` 记录完整路径，`comment`
+// 填充到使文件内容（位于紧随其后的512字节文件头之后）从 `ALIGNMENT` 的整数倍处开始。
+// 解压时pax头与注释会被忽略，得到的仍是普通的tar流
+fn append_file<W: Write>(
+    builder: &mut tar::Builder<W>,
+    header: &mut tar::Header,
+    path: &Path,
+    data: &mut dyn Read,
+    align: Option<&mut Align<W>>,
+) -> io::Result<()> {
+    let Some(align) = align else {
+        return builder.append_data(header, path, data);
+    };
+
+    let start = (align.position)(builder);
+    let path_record = pax_record("path", path.as_os_str().as_encoded_bytes());
+    // 注释记录最短为 "12 comment=\n"
+    let mut padding = (ALIGNMENT - (start + 1024) % ALIGNMENT) % ALIGNMENT;
+    while padding < path_record.len() as u64 + 12 {
+        padding += ALIGNMENT;
+    }
+    let comment_len = padding as usize - path_record.len();
+    let digits = comment_len.to_string().len();
+    let mut records = path_record;
+    records.extend_from_slice(
+        format!(
+            "{} comment={}\n",
+            comment_len,
+            "0".repeat(comment_len - digits - 10)
+        )
+        .as_bytes(),
+    );
+
+    let mut pax = tar::Header::new_ustar();
+    pax.set_path("././@PaxHeader")?;
+    pax.set_entry_type(tar::EntryType::XHeader);
+    pax.set_mode(0o644);
+    pax.set_mtime(header.mtime()?);
+    pax.set_size(records.len() as u64);
+    pax.set_cksum();
+    builder.append(&pax, &records[..])?;
+
+    // 完整路径已记录在pax头中，文件头中的名称放不下时截断即可
+    if header.set_path(path).is_err() {
+        let bytes = path.as_os_str().as_encoded_bytes();
+        let name = &mut header.as_old_mut().name;
+        let len = bytes.len().min(name.len());
+        name[..len].copy_from_slice(&bytes[..len]);
+    }
+    header.set_cksum();
+    builder.append(header, data)?;
+
+    let offset = start + 1024 + padding;
+    debug_assert_eq!(offset % ALIGNMENT, 0);
+    align
+        .files
+        .push((path.to_path_buf(), offset, header.size()?));
+    Ok(())
+}
+
+// pax扩展头中的一条记录 "<长度> <键>=<值>\n"，长度包括其自身的位数
+fn pax_record(key: &str, value: &[u8]) -> Vec<u8> {
+    let base = key.len() + value.len() + 3;
+    let mut len = base + 1;
+    while base + len.to_string().len() != len {
+        len += 1;
+    }
+    let mut record = format!("{} {}=", len, key).into_bytes();
+    record.extend_from_slice(value);
+    record.push(b'\n');
+    record
+}
+
+// 按文件元数据生成条目的tar头：通常完整记录权限、修改时间与 uid/gid，解压后资源保持原有的可执行位；
+// 可重现打包时 uid/gid 置零、权限只区分是否可执行，修改时间统一为打包时间。
+// 使用GNU格式的头，超过ustar 100字节限制的路径与链接目标以长名称扩展头保存，文件名按原始字节保存
+fn entry_header(plan: &BundlePlan, metadata: &fs::Metadata) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    if plan.opts.reproducible {
+        header.set_metadata_in_mode(metadata, tar::HeaderMode::Deterministic);
+        header.set_mtime(plan.created_at);
+    } else {
+        header.set_metadata_in_mode(metadata, tar::HeaderMode::Complete);
+    }
+    header
+}
+
+// `bundle_to_vec` 传入的条目的tar头：没有文件元数据，属主为0，修改时间总是打包时间
+fn memory_header(plan: &BundlePlan, entry_type: tar::EntryType, size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(entry_type);
+    header.set_size(size);
+    header.set_mode(if entry_type.is_dir() { 0o755 } else { 0o644 });
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(plan.created_at);
+    header
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum EntryKind {
+    Dir,
+    File,
+    // 未跟随的符号链接，原样保存链接目标
+    Symlink,
+}
+
+// 资源目录中的一个条目：包内路径与磁盘上的源路径
+pub(crate) struct ResourceEntry {
+    pub(crate) archive_path: PathBuf,
+    pub(crate) source: PathBuf,
+    pub(crate) kind: EntryKind,
+}
+
+// 按打包顺序遍历所有资源目录与单独的资源文件（不含主程序），已跳过匹配排除规则的条目；
+// `visit` 返回的错误附带该条目的源路径
+pub(crate) fn walk_resources(
+    plan: &BundlePlan,
+    visit: &mut dyn FnMut(&ResourceEntry) -> io::Result<()>,
+) -> io::Result<()> {
+    let visit =
+        &mut |entry: &ResourceEntry| visit(entry).map_err(|e| entry_error(&entry.source, e));
+
+    // 输出文件与正在写入的临时文件位于资源目录中时不能把它们打包进去
+    let outputs: Vec<OutputFile> = [(plan.output, false), (plan.staging.as_deref(), true)]
+        .into_iter()
+        .filter_map(|(path, staging)| {
+            let path = path?;
+            Some(OutputFile {
+                name: path.file_name().map(|name| name.to_owned()),
+                path: path.canonicalize().ok()?,
+                staging,
+            })
+        })
+        .collect();
+
+    for dir_path in plan.resource_dirs {
+        // 单独指定的文件（或指向文件的符号链接）以其名称放在资源包根部
+        if dir_path.is_file() {
+            let canonical = dir_path.canonicalize().ok();
+            if let Some(output) = outputs.iter().find(|o| canonical.as_ref() == Some(&o.path)) {
+                output.skip(dir_path);
+                continue;
+            }
+            if let Some(name) = dir_path.file_name() {
+                visit(&ResourceEntry {
+                    archive_path: PathBuf::from(name),
+                    source: dir_path.clone(),
+                    kind: EntryKind::File,
+                })?;
+            }
+            continue;
+        }
+        // 指向目录的符号链接总是被跟随（与 --follow-symlinks 无关），内容保存在链接自身的名称之下，
+        // 目录中的符号链接仍按 --follow-symlinks 处理
+        if dir_path.exists() && dir_path.is_dir() {
+            let name = match dir_path.file_name() {
+                Some(name) => PathBuf::from(name),
+                None => match dir_path.canonicalize()?.file_name() {
+                    Some(name) => PathBuf::from(name),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidInput,
+                            format!("Invalid resource directory: {}", dir_path.display()),
+                        ));
+                    }
+                },
+            };
+            visit(&ResourceEntry {
+                archive_path: name.clone(),
+                source: dir_path.clone(),
+                kind: EntryKind::Dir,
+            })?;
+            let mut walk = Walk {
+                exclude: &plan.exclude,
+                follow_symlinks: plan.opts.follow_symlinks,
+                outputs: &outputs,
+                ancestors: vec![dir_path.canonicalize()?],
+                visit,
+            };
+            walk.dir(&name, dir_path, Path::new(""))?;
+        }
+    }
+
+    Ok(())
+}
+
+// 资源中不能包含的输出文件：文件名与规范路径
+struct OutputFile {
+    name: Option<OsString>,
+    path: PathBuf,
+    // 是否为写入中的临时文件，跳过时无需提示
+    staging: bool,
+}
+
+impl OutputFile {
+    fn skip(&self, path: &Path) {
+        if !self.staging {
+            warn!(
+                "Skipping {}: it is the output file being written",
+                path.display()
+            );
+        }
+    }
+}
+
+// 一个资源目录的遍历状态
+struct Walk<'a, 'v> {
+    exclude: &'a GlobSet,
+    follow_symlinks: bool,
+    outputs: &'a [OutputFile],
+    // 当前目录及其所有上级目录的规范路径，跟随符号链接时用于检测循环
+    ancestors: Vec<PathBuf>,
+    visit: &'v mut dyn FnMut(&ResourceEntry) -> io::Result<()>,
+}
+
+impl Walk<'_, '_> {
+    // 递归遍历目录内容，跳过匹配排除规则的条目；被排除的目录不再向下遍历
+    fn dir(&mut self, archive_dir: &Path, dir: &Path, relative_dir: &Path) -> io::Result<()> {
+        let mut entries = fs::read_dir(dir)
+            .and_then(|entries| entries.collect::<io::Result<Vec<_>>>())
+            .map_err(|e| entry_error(dir, e))?;
+        // 按名称排序，保证多次打包的结果一致
+        entries.sort_by_key(|entry| entry.file_name());
+
+        for entry in entries {
+            let file_name = entry.file_name();
+            let relative_path = relative_dir.join(&file_name);
+            let archive_path = archive_dir.join(&file_name);
+            // 规则既可以匹配相对于资源目录的路径，也可以只匹配文件名
+            if self.exclude.is_match(&relative_path) || self.exclude.is_match(&file_name) {
+                debug!("Excluding {}", archive_path.display());
+                continue;
+            }
+
+            let path = entry.path();
+            let file_type = entry.file_type()?;
+            let kind = if !file_type.is_symlink() {
+                if file_type.is_dir() {
+                    EntryKind::Dir
+                } else {
+                    EntryKind::File
+                }
+            } else if !self.follow_symlinks {
+                EntryKind::Symlink
+            } else {
+                match fs::metadata(&path) {
+                    Ok(metadata) if metadata.is_dir() => EntryKind::Dir,
+                    Ok(_) => EntryKind::File,
+                    Err(e) => {
+                        warn!("Skipping broken symlink {}: {}", path.display(), e);
+                        continue;
+                    }
+                }
+            };
+
+            if kind == EntryKind::File
+                && let Some(output) = self.output(&path, &file_name, file_type.is_symlink())?
+            {
+                output.skip(&path);
+                continue;
+            }
+            if kind != EntryKind::Dir {
+                (self.visit)(&ResourceEntry {
+                    archive_path,
+                    source: path,
+                    kind,
+                })?;
+                continue;
+            }
+
+            // 指向自身或上级目录的符号链接会导致无限递归
+            let canonical = path.canonicalize()?;
+            if self.ancestors.contains(&canonical) {
+                warn!(
+                    "Skipping symlink loop {} -> {}",
+                    path.display(),
+                    canonical.display()
+                );
+                continue;
+            }
+            (self.visit)(&ResourceEntry {
+                archive_path: archive_path.clone(),
+                source: path.clone(),
+                kind,
+            })?;
+            self.ancestors.push(canonical);
+            self.dir(&archive_path, &path, &relative_path)?;
+            self.ancestors.pop();
+        }
+
+        Ok(())
+    }
+
+    // 只对与输出同名的文件或被跟随的符号链接计算规范路径
+    fn output(
+        &self,
+        path: &Path,
+        file_name: &OsStr,
+        is_symlink: bool,
+    ) -> io::Result<Option<&OutputFile>> {
+        let mut candidates = self
+            .outputs
+            .iter()
+            .filter(|output| is_symlink || output.name.as_deref() == Some(file_name))
+            .peekable();
+        if candidates.peek().is_none() {
+            return Ok(None);
+        }
+        let path = path.canonicalize()?;
+        Ok(candidates.find(|output| output.path == path))
+    }
+}
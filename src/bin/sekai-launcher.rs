@@ -0,0 +1,1026 @@
+use std::borrow::Cow;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus};
+#[cfg(unix)]
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time::Duration;
+
+// 与打包端共用的清单、压缩方式与校验和定义
+use sekaipack::encryption::{self, Encryption};
+use sekaipack::footer;
+use sekaipack::manifest::{self, Manifest};
+use sekaipack::unpack;
+use sekaipack::{BundleError, CacheHash};
+
+// 缓存目录中记录已解压资源校验和的文件，只在解压成功后写入
+const FINGERPRINT_FILE: &str = ".sekai-pack-fingerprint";
+
+// 缓存先解压到缓存根目录中以此开头的临时目录，完成后再整体重命名为缓存目录
+const PARTIAL_PREFIX: &str = ".partial-";
+
+// 超过此时间未修改的 `.partial-*` 目录是被中断的解压留下的，启动时删除；
+// 同时启动的其他实例正在进行的解压不会持续这么久
+const STALE_PARTIAL_AGE: Duration = Duration::from_secs(60 * 60);
+
+// 设置为非空且非 "0" 时忽略缓存中的指纹，总是重新解压
+const FORCE_EXTRACT_ENV: &str = "SEKAI_PACK_FORCE_EXTRACT";
+
+// 设置为非空且非 "0" 时保留临时解压目录，便于调试
+const KEEP_TEMP_ENV: &str = "SEKAI_PACK_KEEP_TEMP";
+
+// 设置为非空且非 "0" 时只解压并输出将要执行的命令，不启动主程序；临时解压目录同样保留
+const PRINT_CMD_ENV: &str = "SEKAI_PACK_PRINT_CMD";
+
+// 设置为非空且非 "0" 时，以 --entry-checksums 打包的文件在解压后逐个回读校验
+const PARANOID_ENV: &str = "SEKAI_PACK_PARANOID";
+
+// 设置时不解压，直接执行该目录中已有的文件（如开发时磁盘上的资源目录）
+const RUN_DIR_ENV: &str = "SEKAI_PACK_RUN_DIR";
+
+// 临时解压目录的根目录，优先于系统默认的临时目录
+const TMPDIR_ENV: &str = "SEKAI_PACK_TMPDIR";
+
+// 以 --set-library-path 打包时，解压目录加到此环境变量的最前面
+#[cfg(target_os = "macos")]
+const LIBRARY_PATH_ENV: &str = "DYLD_LIBRARY_PATH";
+#[cfg(windows)]
+const LIBRARY_PATH_ENV: &str = "PATH";
+#[cfg(not(any(target_os = "macos", windows)))]
+const LIBRARY_PATH_ENV: &str = "LD_LIBRARY_PATH";
+
+// 以 --prefer-memory 打包时，资源不超过此预算（MiB）才解压到内存文件系统
+#[cfg(target_os = "linux")]
+const MEMORY_BUDGET_ENV: &str = "SEKAI_PACK_MEMORY_BUDGET";
+#[cfg(target_os = "linux")]
+const DEFAULT_MEMORY_BUDGET_MIB: u64 = 512;
+
+fn main() {
+    // 保证启动器标识留在二进制文件中，供打包端重用启动器时识别
+    std::hint::black_box(footer::LAUNCHER_ID_RUST);
+    let args: Vec<String> = env::args().collect();
+
+    // 只有打包时记录了声明文本才处理 `--about`，否则与其他参数一样传给主程序
+    if args.len() > 1
+        && args[1] == "--about"
+        && let Some(notice) = read_notice()
+    {
+        print!("{}", notice);
+        if !notice.ends_with('\n') {
+            println!();
+        }
+        return;
+    }
+
+    if args.len() > 1 && args[1] == "--version" {
+        println!(
+            "sekaipack launcher {} (rust, bundle format v{})",
+            env!("CARGO_PKG_VERSION"),
+            footer::VERSION
+        );
+        return;
+    }
+
+    match run(&args) {
+        Ok(code) => std::process::exit(code),
+        Err((code, e)) => {
+            eprintln!("{}", e);
+            std::process::exit(code);
+        }
+    }
+}
+
+// 读取清单中 `--notice` 记录的声明文本；读取失败时交给之后的正常启动流程报告错误
+fn read_notice() -> Option<String> {
+    let (exe_path, read_path) = bundle_paths().ok()?;
+    let bundle = Bundle::open(&read_path, &exe_path).ok()?;
+    Some(bundle.manifest.notice).filter(|notice| !notice.is_empty())
+}
+
+// 启动器自身的失败：保留的退出码与错误信息
+type Failure = (i32, Box<dyn std::error::Error>);
+
+// 尾部、清单、校验和或解密出错说明打包文件本身有问题，其余均视为解压失败
+fn prepare_failure(e: Box<dyn std::error::Error>) -> Failure {
+    let code = match e.downcast_ref::<BundleError>() {
+        Some(
+            BundleError::MissingFooter
+            | BundleError::UnsupportedVersion(_)
+            | BundleError::InvalidFooter(_)
+            | BundleError::InvalidManifest(_)
+            | BundleError::InvalidEntryName(_)
+            | BundleError::ChecksumMismatch
+            | BundleError::DecryptionFailed
+            | BundleError::EntryChecksumMismatch(_)
+            | BundleError::UnsafeEntry { .. },
+        ) => manifest::EXIT_INTEGRITY_FAILED,
+        Some(BundleError::ArchMismatch { .. }) => manifest::EXIT_EXEC_FAILED,
+        _ => manifest::EXIT_EXTRACT_FAILED,
+    };
+    (code, e)
+}
+
+// 返回子进程的退出码
+fn run(args: &[String]) -> Result<i32, Failure> {
+    // 临时目录在 `temp_guard` 离开作用域时删除，因此需要保留到子进程退出之后
+    let mut temp_guard = None;
+    let (manifest, work_dir, exe_path) = prepare(&mut temp_guard).map_err(prepare_failure)?;
+
+    let sekai_path = work_dir.join(&manifest.entry_name);
+    let mut command = Command::new(&sekai_path);
+    command
+        .args(launch_args(&manifest.launch_args, &work_dir))
+        .args(args.iter().skip(1).filter(|arg| *arg != "--version"));
+    let envs = bundle_envs(&manifest, &work_dir, &exe_path);
+    command
+        .envs(envs.iter().cloned())
+        .env_remove(manifest::PACKED_ENV)
+        .env_remove(encryption::PASSPHRASE_ENV);
+    if manifest.workdir == manifest::WorkDir::Temp {
+        command.current_dir(&work_dir);
+    }
+
+    if env_flag(PRINT_CMD_ENV) {
+        print_command(&command, &work_dir);
+        return Ok(0);
+    }
+
+    if !manifest.pre_launch.is_empty() {
+        run_pre_launch(&manifest.pre_launch, &work_dir, &envs)
+            .map_err(|e| (manifest::EXIT_PRE_LAUNCH_FAILED, e))?;
+    }
+
+    // 启动子进程并等待其退出，以便之后清理临时目录；标准输入、输出与错误直接继承，
+    // 主程序可以交互并检测终端
+    let status = spawn_main(&mut command)
+        .and_then(|mut child| child.wait())
+        .map_err(|e| {
+            let message = format!("Failed to execute main program: {}", e);
+            (manifest::EXIT_EXEC_FAILED, message.into())
+        })?;
+    Ok(exit_code(status))
+}
+
+// 校验并解压资源，返回清单、解压目录与打包文件的路径
+fn prepare(
+    temp_guard: &mut Option<tempfile::TempDir>,
+) -> Result<(Manifest, PathBuf, PathBuf), Box<dyn std::error::Error>> {
+    let (exe_path, read_path) =
+        bundle_paths().map_err(|e| format!("Failed to get executable path: {}", e))?;
+    let mut bundle = Bundle::open(&read_path, &exe_path)?;
+
+    // 在解压之前检查，避免解压后执行时才因架构不符而失败
+    let arch = &bundle.manifest.arch;
+    if !arch.is_empty() && !sekaipack::arch::is_compatible(env::consts::ARCH, arch) {
+        return Err(BundleError::ArchMismatch {
+            exe: arch.clone(),
+            launcher: env::consts::ARCH.to_string(),
+        }
+        .into());
+    }
+
+    // 直接在指定的目录中运行时不改动其中的文件：不设置权限，也不写入对齐存放的索引
+    if let Some(dir) = env::var_os(RUN_DIR_ENV).filter(|dir| !dir.is_empty()) {
+        let work_dir = run_dir(Path::new(&dir), &bundle.manifest.entry_name)?;
+        return Ok((bundle.manifest, work_dir, exe_path));
+    }
+
+    // 启用缓存时复用已解压的目录，否则每次解压到新的临时目录；
+    // 解压失败提前返回时临时目录同样会被删除
+    let work_dir = if bundle.manifest.cache {
+        prepare_cache_dir(&mut bundle, &exe_path)?
+    } else {
+        let keep_temp = keep_temp();
+        let temp_root = match memory_root(&bundle.manifest) {
+            Some(dir) => dir,
+            None => temp_root()?,
+        };
+        #[cfg(target_os = "linux")]
+        check_exec_allowed(&temp_root)?;
+        // 解压目录以打包时记录的前缀命名，便于辨认
+        let temp_prefix = bundle.manifest.temp_prefix();
+        if !manifest::is_valid_temp_prefix(temp_prefix) {
+            return Err(BundleError::InvalidManifest(format!(
+                "invalid temp_prefix '{}'",
+                temp_prefix
+            ))
+            .into());
+        }
+        let temp_dir = tempfile::Builder::new()
+            .prefix(temp_prefix)
+            .disable_cleanup(keep_temp)
+            .tempdir_in(&temp_root)
+            .map_err(|e| format!("Failed to create temp directory: {}", e))?;
+        if keep_temp {
+            eprintln!("Keeping extracted files in {}", temp_dir.path().display());
+        }
+        check_free_space(temp_dir.path(), &bundle.manifest)?;
+        bundle.verify()?;
+        bundle.extract_to(temp_dir.path())?;
+        temp_guard.insert(temp_dir).path().to_path_buf()
+    };
+
+    // 文件权限在解压时按打包记录恢复，执行的可执行文件使用清单中指定的权限
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(
+            work_dir.join(&bundle.manifest.entry_name),
+            fs::Permissions::from_mode(bundle.manifest.entry_mode & 0o777),
+        )
+        .map_err(|e| {
+            format!(
+                "Failed to set permissions on {}: {}",
+                bundle.manifest.entry_name, e
+            )
+        })?;
+    }
+
+    if !bundle.manifest.aligned_index.is_empty() {
+        let index = work_dir.join(manifest::ALIGNED_INDEX_FILE);
+        fs::write(&index, &bundle.manifest.aligned_index)
+            .map_err(|e| format!("Failed to write {}: {}", index.display(), e))?;
+    }
+
+    Ok((bundle.manifest, work_dir, exe_path))
+}
+
+// 检查 `SEKAI_PACK_RUN_DIR` 指定的目录中有要执行的文件，返回其绝对路径
+fn run_dir(dir: &Path, entry_name: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = std::path::absolute(dir)?;
+    if !dir.join(entry_name).is_file() {
+        return Err(format!(
+            "{} '{}' does not contain {}",
+            RUN_DIR_ENV,
+            dir.display(),
+            entry_name
+        )
+        .into());
+    }
+    Ok(dir)
+}
+
+// 在解压目录中用shell执行打包时记录的启动前命令，以非零状态退出时中止启动
+fn run_pre_launch(
+    cmd: &str,
+    dir: &Path,
+    envs: &[(String, OsString)],
+) -> Result<(), Box<dyn std::error::Error>> {
+    #[cfg(unix)]
+    let mut command = {
+        let mut command = Command::new("/bin/sh");
+        command.arg("-c").arg(cmd);
+        command
+    };
+    #[cfg(windows)]
+    let mut command = {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(cmd);
+        command
+    };
+    let status = command
+        .envs(envs.iter().cloned())
+        .env_remove(manifest::PACKED_ENV)
+        .env_remove(encryption::PASSPHRASE_ENV)
+        .current_dir(dir)
+        .env(manifest::PRE_LAUNCH_DIR_ENV, dir)
+        .status()
+        .map_err(|e| format!("Failed to run pre-launch command: {}", e))?;
+    if !status.success() {
+        return Err(format!("Pre-launch command failed ({})", status).into());
+    }
+    Ok(())
+}
+
+// 传给主程序与启动前命令的环境变量：--env 设置的变量（覆盖运行环境中的同名变量），
+// --append-data 附加的文件解压后所在的目录，--store-aligned 时的文件索引与打包文件的路径，
+// 以及 --set-library-path 时的动态库搜索路径
+fn bundle_envs(manifest: &Manifest, work_dir: &Path, exe_path: &Path) -> Vec<(String, OsString)> {
+    let mut envs: Vec<(String, OsString)> = manifest
+        .env_vars()
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.into()))
+        .collect();
+    let mut push = |key: &str, value: PathBuf| envs.push((key.to_string(), value.into()));
+    if !manifest.data_files.is_empty() {
+        push(manifest::DATA_DIR_ENV, work_dir.join(manifest::DATA_DIR));
+    }
+    if !manifest.aligned_index.is_empty() {
+        push(
+            manifest::ALIGNED_INDEX_ENV,
+            work_dir.join(manifest::ALIGNED_INDEX_FILE),
+        );
+        push(manifest::BUNDLE_ENV, exe_path.to_path_buf());
+    }
+    if manifest.set_library_path {
+        // 保留原有的搜索路径（--env 设置了该变量时为其值）；不能留下空项，否则会把当前目录加入搜索路径
+        let existing = match manifest
+            .env_vars()
+            .iter()
+            .rfind(|(key, _)| *key == LIBRARY_PATH_ENV)
+        {
+            Some((_, value)) => Some(OsString::from(value)),
+            None => env::var_os(LIBRARY_PATH_ENV),
+        };
+        let library_path = match existing.filter(|paths| !paths.is_empty()) {
+            Some(existing) => {
+                let paths =
+                    std::iter::once(work_dir.to_path_buf()).chain(env::split_paths(&existing));
+                env::join_paths(paths)
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|_| work_dir.to_path_buf())
+            }
+            None => work_dir.to_path_buf(),
+        };
+        push(LIBRARY_PATH_ENV, library_path);
+    }
+    envs
+}
+
+fn keep_temp() -> bool {
+    env_flag(KEEP_TEMP_ENV) || env_flag(PRINT_CMD_ENV)
+}
+
+// 输出解压目录与完整的参数列表（argv[0] 为要执行的文件），每行一项
+fn print_command(command: &Command, dir: &Path) {
+    println!("dir: {}", dir.display());
+    let program = std::iter::once(command.get_program());
+    for (i, arg) in program.chain(command.get_args()).enumerate() {
+        println!("argv[{}]: \"{}\"", i, arg.to_string_lossy());
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var_os(name).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+// 展开打包时记录的启动参数模板：按空白拆分后将 `{dir}` 替换为解压目录
+fn launch_args(template: &str, dir: &Path) -> Vec<OsString> {
+    template
+        .split_whitespace()
+        .map(|token| {
+            let mut arg = OsString::new();
+            for (i, part) in token.split(manifest::DIR_PLACEHOLDER).enumerate() {
+                if i > 0 {
+                    arg.push(dir);
+                }
+                arg.push(part);
+            }
+            arg
+        })
+        .collect()
+}
+
+// 子进程被信号终止时按shell惯例返回 128 + 信号值
+fn exit_code(status: ExitStatus) -> i32 {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+        if let Some(signal) = status.signal() {
+            return 128 + signal;
+        }
+    }
+    status.code().unwrap_or(1)
+}
+
+// 正在运行的子进程pid，供信号处理函数转发信号；子进程位于单独的进程组时为进程组号的负值
+#[cfg(unix)]
+static CHILD_PID: AtomicI32 = AtomicI32::new(0);
+
+#[cfg(unix)]
+extern "C" fn forward_signal(
+    signal: libc::c_int,
+    info: *mut libc::siginfo_t,
+    _context: *mut libc::c_void,
+) {
+    let pid = CHILD_PID.load(Ordering::SeqCst);
+    // 与启动器同一进程组的子进程已经收到终端发出（`si_pid` 为0）的信号，不再转发
+    if pid < 0 || (pid > 0 && unsafe { (*info).si_pid() } != 0) {
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+// 启动主程序，并将SIGINT/SIGTERM转发给它，启动器自身继续等待子进程退出后清理。
+// 连接终端时子进程留在启动器的进程组中，以免妨碍作业控制与读取终端；否则放入单独的进程组，
+// 发给启动器所在进程组的信号只经由启动器到达子进程一次
+#[cfg(unix)]
+fn spawn_main(command: &mut Command) -> io::Result<Child> {
+    use std::os::unix::process::CommandExt;
+
+    let on_terminal = [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO]
+        .into_iter()
+        .any(|fd| unsafe { libc::isatty(fd) } == 1);
+    if !on_terminal {
+        command.process_group(0);
+    }
+
+    unsafe {
+        // 处理函数在启动子进程之前安装，期间收到的信号先阻塞，记下子进程的pid之后再处理
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigprocmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = forward_signal
+            as extern "C" fn(libc::c_int, *mut libc::siginfo_t, *mut libc::c_void)
+            as libc::sighandler_t;
+        action.sa_flags = libc::SA_SIGINFO | libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+        libc::sigaction(libc::SIGTERM, &action, std::ptr::null_mut());
+
+        // 子进程继承信号掩码，恢复默认的信号处理后再解除阻塞
+        command.pre_exec(move || {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+            libc::sigprocmask(libc::SIG_UNBLOCK, &signals, std::ptr::null_mut());
+            Ok(())
+        });
+        let child = command.spawn();
+        if let Ok(child) = &child {
+            let pid = child.id() as i32;
+            CHILD_PID.store(if on_terminal { pid } else { -pid }, Ordering::SeqCst);
+        }
+        libc::sigprocmask(libc::SIG_UNBLOCK, &signals, std::ptr::null_mut());
+        child
+    }
+}
+
+#[cfg(not(unix))]
+fn spawn_main(command: &mut Command) -> io::Result<Child> {
+    command.spawn()
+}
+
+// 定位当前运行的可执行文件（Linux 为 /proc/self/exe，macOS 为 _NSGetExecutablePath）
+// 打包文件的路径（用于缓存目录与 `SEKAI_PACK_BUNDLE`）与实际读取的路径：由 `--pack-launcher` 的解压桩
+// 在内存中启动时，前者为解压桩传入的原打包文件。Linux上总是经由 /proc/self/exe 读取，
+// 它指向正在运行的文件本身，打包文件在启动后被删除或被新版本替换（如自动更新）时仍能读取
+fn bundle_paths() -> io::Result<(PathBuf, PathBuf)> {
+    #[cfg(target_os = "linux")]
+    {
+        let path = match env::var_os(manifest::PACKED_ENV) {
+            Some(path) => PathBuf::from(path),
+            None => current_exe_path()?,
+        };
+        Ok((path, PathBuf::from("/proc/self/exe")))
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let path = current_exe_path()?;
+        Ok((path.clone(), path))
+    }
+}
+
+fn current_exe_path() -> io::Result<PathBuf> {
+    resolve_exe_path(env::current_exe()?)
+}
+
+fn resolve_exe_path(path: PathBuf) -> io::Result<PathBuf> {
+    // 已被删除的文件的链接目标带有 " (deleted)" 后缀，原路径仍用于缓存目录与环境变量
+    #[cfg(target_os = "linux")]
+    let path = match path
+        .to_str()
+        .and_then(|path| path.strip_suffix(" (deleted)"))
+    {
+        Some(original) => PathBuf::from(original),
+        None => path,
+    };
+    // macOS 返回的路径可能包含符号链接或相对部分；文件在启动后被删除时无法再读取
+    #[cfg(target_os = "macos")]
+    let path = path.canonicalize().map_err(|e| match e.kind() {
+        io::ErrorKind::NotFound => {
+            io::Error::new(e.kind(), "the bundle was deleted or moved after it started")
+        }
+        _ => e,
+    })?;
+    Ok(path)
+}
+
+// 临时目录的根目录：优先使用 $SEKAI_PACK_TMPDIR，否则 unix 下为 $TMPDIR（未设置时为 /tmp），
+// Windows 下为 %LOCALAPPDATA%\Temp
+fn temp_root() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(dir) = env::var_os(TMPDIR_ENV).filter(|dir| !dir.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
+    #[cfg(windows)]
+    {
+        if let Some(dir) = env::var_os("LOCALAPPDATA") {
+            return Ok(PathBuf::from(dir).join("Temp"));
+        }
+        Ok(env::temp_dir())
+    }
+    #[cfg(not(windows))]
+    {
+        match env::var_os("TMPDIR") {
+            Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+            _ => Ok(PathBuf::from("/tmp")),
+        }
+    }
+}
+
+// 以 --prefer-memory 打包且资源不超过内存预算时使用 /dev/shm，要求其剩余空间足够且允许执行；
+// 条件不满足时返回 `None`，显式设置了 $SEKAI_PACK_TMPDIR 时总是使用它
+#[cfg(target_os = "linux")]
+fn memory_root(manifest: &Manifest) -> Option<PathBuf> {
+    if !manifest.prefer_memory || env::var_os(TMPDIR_ENV).is_some_and(|dir| !dir.is_empty()) {
+        return None;
+    }
+    let budget = env::var(MEMORY_BUDGET_ENV)
+        .ok()
+        .and_then(|value| value.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_MEMORY_BUDGET_MIB)
+        .saturating_mul(1024 * 1024);
+    if manifest.unpacked_size > budget {
+        return None;
+    }
+
+    let dir = Path::new("/dev/shm");
+    let stat = statvfs(dir)?;
+    if stat.f_flag & libc::ST_NOEXEC != 0 || free_space(dir)? < manifest.unpacked_size {
+        return None;
+    }
+    Some(dir.to_path_buf())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn memory_root(_manifest: &Manifest) -> Option<PathBuf> {
+    None
+}
+
+#[cfg(unix)]
+fn statvfs(dir: &Path) -> Option<libc::statvfs> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    (unsafe { libc::statvfs(path.as_ptr(), &mut stat) } == 0).then_some(stat)
+}
+
+// 目录所在文件系统中非特权用户可用的字节数，无法获取时为 `None`
+#[cfg(unix)]
+fn free_space(dir: &Path) -> Option<u64> {
+    let stat = statvfs(dir)?;
+    // 32位平台上这两个字段不是 u64
+    #[allow(clippy::unnecessary_cast)]
+    Some((stat.f_bavail as u64).saturating_mul(stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn free_space(_dir: &Path) -> Option<u64> {
+    None
+}
+
+// 解压前确认剩余空间足够容纳所有资源，避免解压到一半时失败；
+// 旧版打包文件没有记录未压缩大小，无法获取剩余空间时同样跳过检查
+fn check_free_space(dir: &Path, manifest: &Manifest) -> Result<(), Box<dyn std::error::Error>> {
+    let needed = manifest.unpacked_size;
+    match free_space(dir) {
+        Some(free) if free < needed => Err(format!(
+            "Not enough space in {}: need {} bytes, have {} bytes",
+            dir.display(),
+            needed,
+            free
+        )
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+// 以 noexec 挂载的文件系统上无法执行解压出的主程序，提前报错
+#[cfg(target_os = "linux")]
+fn check_exec_allowed(dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    // 无法获取时交给之后创建临时目录的步骤报错
+    if statvfs(dir).is_some_and(|stat| stat.f_flag & libc::ST_NOEXEC != 0) {
+        return Err(format!(
+            "{} is on a filesystem mounted noexec; set {} to another directory",
+            dir.display(),
+            TMPDIR_ENV
+        )
+        .into());
+    }
+    Ok(())
+}
+
+// 缓存的根目录：unix 下为 $XDG_CACHE_HOME（默认 ~/.cache），Windows 下为 %LOCALAPPDATA%
+fn cache_root() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    #[cfg(windows)]
+    {
+        match env::var_os("LOCALAPPDATA") {
+            Some(dir) => Ok(PathBuf::from(dir)),
+            None => Err("Failed to locate cache directory".into()),
+        }
+    }
+    #[cfg(not(windows))]
+    {
+        match env::var_os("XDG_CACHE_HOME") {
+            Some(dir) if !dir.is_empty() => Ok(PathBuf::from(dir)),
+            _ => match env::var_os("HOME") {
+                Some(home) => Ok(PathBuf::from(home).join(".cache")),
+                None => Err("Failed to locate cache directory".into()),
+            },
+        }
+    }
+}
+
+// 缓存目录为 `<缓存根目录>/sekai-pack/<文件名>-<资源哈希>`，资源哈希取自清单中的资源校验和，
+// 重新打包的文件即使位于同一路径也使用新的目录；目录中的指纹与资源校验和一致时直接复用，
+// 否则（如 `SEKAI_PACK_FORCE_EXTRACT` 或目录内容不完整）重新解压。
+// 解压与写入指纹都在 `.partial-*` 临时目录中完成后才重命名为缓存目录，
+// 被中断的解压不会在缓存目录的位置留下不完整的内容
+fn prepare_cache_dir(
+    bundle: &mut Bundle,
+    exe_path: &Path,
+) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let cache_root = cache_root()?.join("sekai-pack");
+    purge_stale_partials(&cache_root);
+
+    let fingerprint = bundle.fingerprint();
+    let cache_key = cache_key(exe_path, &fingerprint, bundle.manifest.cache_hash);
+    let cache_dir = cache_root.join(&cache_key);
+    let is_current = |dir: &Path| {
+        fs::read_to_string(dir.join(FINGERPRINT_FILE)).is_ok_and(|stored| stored == fingerprint)
+    };
+    if !env_flag(FORCE_EXTRACT_ENV) && is_current(&cache_dir) {
+        return Ok(cache_dir);
+    }
+
+    fs::create_dir_all(&cache_root)
+        .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    // 解压失败提前返回时临时目录随之删除
+    let partial = tempfile::Builder::new()
+        .prefix(PARTIAL_PREFIX)
+        .tempdir_in(&cache_root)
+        .map_err(|e| format!("Failed to create cache directory: {}", e))?;
+    check_free_space(partial.path(), &bundle.manifest)?;
+    bundle.verify()?;
+    bundle.extract_to(partial.path())?;
+    fs::write(partial.path().join(FINGERPRINT_FILE), &fingerprint)
+        .map_err(|e| format!("Failed to write cache fingerprint: {}", e))?;
+
+    // 过期的缓存目录先整体移开再删除，删除被中断时留下的同样是 `.partial-*` 目录
+    if cache_dir.exists() {
+        let stale = cache_root.join(format!(
+            "{}{}-{}",
+            PARTIAL_PREFIX,
+            cache_key,
+            std::process::id()
+        ));
+        fs::rename(&cache_dir, &stale)
+            .map_err(|e| format!("Failed to clean cache directory: {}", e))?;
+        let _ = fs::remove_dir_all(&stale);
+    }
+    let partial = partial.keep();
+    if let Err(e) = fs::rename(&partial, &cache_dir) {
+        let _ = fs::remove_dir_all(&partial);
+        // 同时启动的其他实例可能已经放好了同样的缓存目录
+        if !is_current(&cache_dir) {
+            return Err(format!("Failed to create cache directory: {}", e).into());
+        }
+    }
+
+    Ok(cache_dir)
+}
+
+// 删除被中断的解压或删除留下的 `.partial-*` 目录，失败时下次启动再试
+fn purge_stale_partials(cache_root: &Path) {
+    let Ok(entries) = fs::read_dir(cache_root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let stale = entry
+            .file_name()
+            .to_string_lossy()
+            .starts_with(PARTIAL_PREFIX)
+            && entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.elapsed().ok())
+                .is_some_and(|age| age > STALE_PARTIAL_AGE);
+        if stale {
+            let _ = fs::remove_dir_all(entry.path());
+        }
+    }
+}
+
+// 以可执行文件名加上资源指纹的哈希区分不同内容的打包文件
+fn cache_key(exe_path: &Path, fingerprint: &str, cache_hash: CacheHash) -> String {
+    let name = exe_path
+        .file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let hash = cache_hash.hex(fingerprint.as_bytes());
+    format!("{}-{}", name, &hash[..16])
+}
+
+struct Bundle {
+    source: Source,
+    manifest: Manifest,
+    // 加密的资源数据解密所需的口令
+    passphrase: Option<String>,
+}
+
+// 资源数据的来源：优先将可执行文件映射到内存，校验与解压都直接在映射上进行而无需复制；
+// 无法映射（或非Unix平台）以及以 --split-size 拆分时退回到从文件中读取
+enum Source {
+    #[cfg(unix)]
+    Mapped(Mmap),
+    Stream(Box<dyn Stream>),
+}
+
+trait Stream: Read + Seek {}
+
+impl<T: Read + Seek> Stream for T {}
+
+impl Source {
+    // 返回嵌入的资源数据（仍为压缩状态）中 `range` 范围内的部分
+    fn resources(
+        &mut self,
+        manifest: &Manifest,
+        range: Range<u64>,
+    ) -> Result<Box<dyn Read + '_>, Box<dyn std::error::Error>> {
+        Ok(match self {
+            #[cfg(unix)]
+            Source::Mapped(map) => Box::new(
+                map.range(manifest)?
+                    .get(range.start as usize..range.end as usize)
+                    .ok_or("Resource section extends past the resources")?,
+            ),
+            Source::Stream(file) => {
+                file.seek(SeekFrom::Start(manifest.resource_offset + range.start))
+                    .map_err(|e| format!("Failed to seek to resources: {}", e))?;
+                Box::new(file.take(range.end - range.start))
+            }
+        })
+    }
+
+    // 完整取得资源数据，供解密使用；内存映射时直接借用
+    fn resource_bytes(
+        &mut self,
+        manifest: &Manifest,
+    ) -> Result<Cow<'_, [u8]>, Box<dyn std::error::Error>> {
+        #[cfg(unix)]
+        if let Source::Mapped(map) = self {
+            return Ok(Cow::Borrowed(map.range(manifest)?));
+        }
+        let mut data = Vec::new();
+        self.resources(manifest, 0..manifest.resource_len)?
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Failed to read resources: {}", e))?;
+        Ok(Cow::Owned(data))
+    }
+}
+
+// 把 `<名称>.000`、`<名称>.001`…… 依次拼接为一个连续的只读流
+struct Parts {
+    files: Vec<(fs::File, u64)>,
+    pos: u64,
+    len: u64,
+}
+
+impl Parts {
+    // `first` 为第一部分，之后的部分按编号依次查找，直到某个编号不存在
+    fn open(first: &Path) -> io::Result<Option<Parts>> {
+        let Some(base) = first.to_str().and_then(|path| path.strip_suffix(".000")) else {
+            return Ok(None);
+        };
+        let mut files = Vec::new();
+        for index in 0.. {
+            let file = match fs::File::open(format!("{}.{:03}", base, index)) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                Err(e) => return Err(e),
+            };
+            let len = file.metadata()?.len();
+            files.push((file, len));
+        }
+        let len = files.iter().map(|(_, len)| len).sum();
+        Ok(Some(Parts { files, pos: 0, len }))
+    }
+}
+
+impl Read for Parts {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut start = 0;
+        for (file, len) in &mut self.files {
+            if self.pos < start + *len {
+                file.seek(SeekFrom::Start(self.pos - start))?;
+                let n = file.take(start + *len - self.pos).read(buf)?;
+                self.pos += n as u64;
+                return Ok(n);
+            }
+            start += *len;
+        }
+        Ok(0)
+    }
+}
+
+impl Seek for Parts {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(pos) => Some(pos),
+            SeekFrom::End(offset) => self.len.checked_add_signed(offset),
+            SeekFrom::Current(offset) => self.pos.checked_add_signed(offset),
+        };
+        self.pos =
+            pos.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid seek"))?;
+        Ok(self.pos)
+    }
+}
+
+// 只读映射整个可执行文件；打包文件在运行期间不应被修改
+#[cfg(unix)]
+struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+#[cfg(unix)]
+impl Mmap {
+    fn map(file: &fs::File) -> io::Result<Mmap> {
+        use std::os::unix::io::AsRawFd;
+
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file too large to map"))?;
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_PRIVATE,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        // 资源数据只会按顺序读取，提示内核预读；失败也不影响读取
+        unsafe { libc::madvise(ptr, len, libc::MADV_SEQUENTIAL) };
+        Ok(Mmap { ptr, len })
+    }
+
+    fn range(&self, manifest: &Manifest) -> Result<&[u8], Box<dyn std::error::Error>> {
+        let data = unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) };
+        usize::try_from(manifest.resource_offset)
+            .ok()
+            .zip(usize::try_from(manifest.resource_end()).ok())
+            .and_then(|(start, end)| data.get(start..end))
+            .ok_or_else(|| "Resources extend past the end of the executable".into())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr, self.len) };
+    }
+}
+
+impl Bundle {
+    // `read_path` 为实际读取的文件，拆分出的其他部分按打包文件的路径 `exe_path` 查找
+    fn open(read_path: &Path, exe_path: &Path) -> Result<Bundle, Box<dyn std::error::Error>> {
+        let mut exe =
+            fs::File::open(read_path).map_err(|e| format!("Failed to open executable: {}", e))?;
+
+        // 校验魔数后读取清单；以 --split-size 拆分时运行的是第一部分，清单位于最后一部分的末尾
+        let (manifest, parts) = match Manifest::read_from(&mut exe) {
+            Ok(manifest) => (manifest, None),
+            Err(BundleError::MissingFooter) => {
+                let Some(mut parts) = Parts::open(exe_path)
+                    .map_err(|e| format!("Failed to open split parts: {}", e))?
+                else {
+                    return Err(BundleError::MissingFooter.into());
+                };
+                (Manifest::read_from(&mut parts)?, Some(parts))
+            }
+            Err(e) => return Err(e.into()),
+        };
+        let passphrase = match manifest.encryption {
+            Encryption::None => None,
+            Encryption::Aes256Gcm => Some(
+                encryption::read_passphrase("Passphrase: ")
+                    .map_err(|e| format!("Failed to read passphrase: {}", e))?,
+            ),
+        };
+        let source = match parts {
+            Some(parts) => Source::Stream(Box::new(parts)),
+            #[cfg(unix)]
+            None => match Mmap::map(&exe) {
+                Ok(map) => Source::Mapped(map),
+                Err(_) => Source::Stream(Box::new(exe)),
+            },
+            #[cfg(not(unix))]
+            None => Source::Stream(Box::new(exe)),
+        };
+        Ok(Bundle {
+            source,
+            manifest,
+            passphrase,
+        })
+    }
+
+    // 打包时计算的资源校验和，无需读取资源数据
+    fn fingerprint(&self) -> String {
+        format!(
+            "{}:{}",
+            self.manifest.checksum_type.name(),
+            self.manifest.checksum
+        )
+    }
+
+    // 解压前校验资源完整性，避免在损坏的数据上解压
+    fn verify(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let actual = self
+            .manifest
+            .checksum_type
+            .compute(
+                self.source
+                    .resources(&self.manifest, 0..self.manifest.resource_len)?,
+            )
+            .map_err(|e| format!("Failed to read resources: {}", e))?;
+        if actual != self.manifest.checksum {
+            return Err(BundleError::ChecksumMismatch.into());
+        }
+        Ok(())
+    }
+
+    // 直接从可执行文件中流式解压资源，加密的资源数据先在内存中解密；
+    // 可执行文件单独压缩时依次解压各段到同一目录
+    fn extract_to(&mut self, dir: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        let decrypted = match &self.passphrase {
+            None => None,
+            Some(passphrase) => {
+                let data = self.source.resource_bytes(&self.manifest)?;
+                Some(encryption::decrypt(&self.manifest, passphrase, &data)?)
+            }
+        };
+        let payload_len = decrypted
+            .as_ref()
+            .map_or(self.manifest.resource_len, |data| data.len() as u64);
+        let paranoid = env_flag(PARANOID_ENV);
+        for (compression, range) in self.manifest.sections(payload_len) {
+            let section: Box<dyn Read + '_> = match &decrypted {
+                Some(data) => Box::new(&data[range.start as usize..range.end as usize]),
+                None => self.source.resources(&self.manifest, range)?,
+            };
+            unpack::unpack_section(&self.manifest, compression, section, dir, paranoid).map_err(
+                |e| match e {
+                    BundleError::ArchiveFailed(e) => Box::<dyn std::error::Error>::from(format!(
+                        "Failed to extract resources: {}",
+                        e
+                    )),
+                    e => e.into(),
+                },
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_exe_exists() {
+        assert!(current_exe_path().unwrap().is_file());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn strips_deleted_suffix() {
+        assert_eq!(
+            resolve_exe_path(PathBuf::from("/opt/game/sekai (deleted)")).unwrap(),
+            Path::new("/opt/game/sekai")
+        );
+        assert_eq!(
+            resolve_exe_path(PathBuf::from("/opt/game/sekai")).unwrap(),
+            Path::new("/opt/game/sekai")
+        );
+    }
+
+    #[cfg(target_os = "macos")]
+    #[test]
+    fn resolves_to_canonical_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let exe = dir.path().join("sekai");
+        fs::write(&exe, b"").unwrap();
+        let relative = dir.path().join(".").join("sekai");
+        assert_eq!(
+            resolve_exe_path(relative).unwrap(),
+            exe.canonicalize().unwrap()
+        );
+        let missing = resolve_exe_path(dir.path().join("missing")).unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -0,0 +1,346 @@
+// `--cache-dir` 的打包缓存：把压缩的tar流切分为彼此独立压缩的块，以块的内容哈希为键缓存压缩结果
+//
+// 块总在tar条目之间切分：条目较大（不小于 `LARGE_ENTRY`）或其文件头中名称的哈希命中时在其后切分，
+// 是否切分只取决于该条目本身，文件的增删改不会移动其他位置的切分点。
+// gzip的每块是以同步刷新结束、不引用其他块内容的deflate数据，拼接后加上结束块与整个流的CRC32，
+// 仍是单个普通的gzip流；zstd的每块是一个完整的帧，多个帧依次拼接同样是有效的zstd流。
+// 因此启动器与 `list`、`extract` 等无需区分是否分块打包。
+//
+// 缓存的键为块中原始的tar数据（包括文件头中的路径、大小、权限、修改时间与属主）加上压缩方式与等级，
+// 其中任一项变化都会重新压缩该块；线程数不影响输出，不计入键中。缓存不会自动清理，可以随时删除。
+
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::compression::Compression;
+
+// 内容不小于此大小的条目单独成为一块（或作为一块的结尾）
+const LARGE_ENTRY: u64 = 64 * 1024;
+
+// 名称哈希对此取模为0的条目之后切分，小文件平均约此数量组成一块
+const SMALL_ENTRIES_PER_CHUNK: u32 = 16;
+
+// 块的原始数据超过此大小后暂存到临时文件中，而不是全部保留在内存里
+const SPOOL_LIMIT: usize = 8 * 1024 * 1024;
+
+// 计入缓存键中，块的格式变化时更改使旧的缓存失效
+const KEY_VERSION: &str = "sekaipack-chunk-v1";
+
+// tar块的大小
+const BLOCK: usize = 512;
+
+// 接收tar流，按条目切分为块，逐块从缓存中取出或压缩后写入 `inner`
+pub(crate) struct ChunkWriter<'a, W: Write> {
+    inner: W,
+    dir: &'a Path,
+    compression: Compression,
+    level: u32,
+    threads: u32,
+    // 当前块的原始数据及其缓存键的哈希
+    spool: tempfile::SpooledTempFile,
+    spool_len: u64,
+    hasher: Sha256,
+    // 整个tar流的CRC32与长度，用于gzip尾部
+    crc: crc32fast::Hasher,
+    total_len: u64,
+    // 正在读取的文件头，以及当前条目剩余的内容长度（已补齐到512字节）
+    header: Vec<u8>,
+    remaining: u64,
+    entry: Option<Entry>,
+    /// 写入的块数与其中从缓存中取出的块数
+    pub(crate) chunks: usize,
+    pub(crate) reused: usize,
+}
+
+// 当前条目的文件头中决定是否在其后切分的信息
+struct Entry {
+    // GNU长名称、pax等扩展头描述的是其后的条目，不在其后切分
+    extension: bool,
+    size: u64,
+    name_hash: u32,
+}
+
+impl<'a, W: Write> ChunkWriter<'a, W> {
+    pub(crate) fn new(
+        mut inner: W,
+        dir: &'a Path,
+        compression: Compression,
+        level: u32,
+        threads: u32,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        if compression == Compression::Gzip {
+            // 修改时间为0，操作系统未知
+            inner.write_all(&[0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff])?;
+        }
+        Ok(ChunkWriter {
+            inner,
+            dir,
+            compression,
+            level,
+            threads,
+            spool: tempfile::SpooledTempFile::new(SPOOL_LIMIT),
+            spool_len: 0,
+            hasher: chunk_hasher(compression, level),
+            crc: crc32fast::Hasher::new(),
+            total_len: 0,
+            header: Vec::with_capacity(BLOCK),
+            remaining: 0,
+            entry: None,
+            chunks: 0,
+            reused: 0,
+        })
+    }
+
+    // 写入最后一块，gzip时补上结束块与尾部；之后不应再写入
+    pub(crate) fn finish(&mut self) -> io::Result<()> {
+        self.end_chunk()?;
+        if self.compression == Compression::Gzip {
+            // 空的最后一个固定哈夫曼块
+            self.inner.write_all(&[0x03, 0x00])?;
+            self.inner
+                .write_all(&self.crc.clone().finalize().to_le_bytes())?;
+            self.inner
+                .write_all(&(self.total_len as u32).to_le_bytes())?;
+        }
+        self.inner.flush()
+    }
+
+    fn append(&mut self, data: &[u8]) -> io::Result<()> {
+        self.spool.write_all(data)?;
+        self.spool_len += data.len() as u64;
+        self.hasher.update(data);
+        self.crc.update(data);
+        self.total_len += data.len() as u64;
+        Ok(())
+    }
+
+    // 读完一个文件头：结尾的全零块不是条目，其余记下大小与名称
+    fn start_entry(&mut self) -> io::Result<()> {
+        if self.header.iter().all(|&b| b == 0) {
+            return Ok(());
+        }
+        let header = tar::Header::from_byte_slice(&self.header);
+        let size = header.entry_size()?;
+        self.entry = Some(Entry {
+            extension: matches!(self.header[156], b'L' | b'K' | b'x' | b'g'),
+            size,
+            name_hash: crc32fast::hash(&self.header[..100]),
+        });
+        self.remaining = size.next_multiple_of(BLOCK as u64);
+        Ok(())
+    }
+
+    fn end_entry(&mut self) -> io::Result<()> {
+        let Some(entry) = self.entry.take() else {
+            return Ok(());
+        };
+        if !entry.extension
+            && (entry.size >= LARGE_ENTRY || entry.name_hash % SMALL_ENTRIES_PER_CHUNK == 0)
+        {
+            self.end_chunk()?;
+        }
+        Ok(())
+    }
+
+    // 结束当前块：缓存中有同样的块时直接复制，否则压缩后写入缓存再复制
+    fn end_chunk(&mut self) -> io::Result<()> {
+        if self.spool_len == 0 {
+            return Ok(());
+        }
+        let hasher =
+            std::mem::replace(&mut self.hasher, chunk_hasher(self.compression, self.level));
+        let key: String = hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        let path = self.dir.join(key);
+        let mut spool =
+            std::mem::replace(&mut self.spool, tempfile::SpooledTempFile::new(SPOOL_LIMIT));
+        self.spool_len = 0;
+
+        self.chunks += 1;
+        let mut cached = match fs::File::open(&path) {
+            Ok(file) => {
+                self.reused += 1;
+                file
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                // 先写入同一目录中的临时文件，中断时不会留下不完整的缓存项
+                spool.rewind()?;
+                let mut temp = tempfile::NamedTempFile::new_in(self.dir)?;
+                self.compress(&mut spool, temp.as_file_mut())?;
+                let mut file = temp.persist(&path).map_err(|e| e.error)?;
+                file.rewind()?;
+                file
+            }
+            Err(e) => return Err(e),
+        };
+        io::copy(&mut cached, &mut self.inner)?;
+        Ok(())
+    }
+
+    fn compress(&self, raw: &mut dyn Read, output: &mut fs::File) -> io::Result<()> {
+        match self.compression {
+            Compression::Gzip => deflate_chunk(raw, self.level, output),
+            Compression::Zstd => {
+                let mut encoder = zstd::Encoder::new(output, self.level as i32)?;
+                encoder.multithread(self.threads)?;
+                io::copy(raw, &mut encoder)?;
+                encoder.finish()?;
+                Ok(())
+            }
+            Compression::None => io::copy(raw, output).map(|_| ()),
+            Compression::Brotli => unreachable!("brotli streams cannot be concatenated"),
+        }
+    }
+}
+
+impl<W: Write> Write for ChunkWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut rest = buf;
+        while !rest.is_empty() {
+            if self.remaining == 0 {
+                let n = (BLOCK - self.header.len()).min(rest.len());
+                self.header.extend_from_slice(&rest[..n]);
+                self.append(&rest[..n])?;
+                rest = &rest[n..];
+                if self.header.len() == BLOCK {
+                    self.start_entry()?;
+                    self.header.clear();
+                    if self.remaining == 0 {
+                        self.end_entry()?;
+                    }
+                }
+            } else {
+                let n = (self.remaining.min(rest.len() as u64)) as usize;
+                self.append(&rest[..n])?;
+                rest = &rest[n..];
+                self.remaining -= n as u64;
+                if self.remaining == 0 {
+                    self.end_entry()?;
+                }
+            }
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// 缓存键的哈希，先计入格式版本、压缩方式与等级
+fn chunk_hasher(compression: Compression, level: u32) -> Sha256 {
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{} {} {}\n", KEY_VERSION, compression.name(), level).as_bytes());
+    hasher
+}
+
+// 压缩为不含结束块的deflate数据，以同步刷新结束在字节边界上，可以与其他块直接拼接
+fn deflate_chunk(raw: &mut dyn Read, level: u32, output: &mut dyn Write) -> io::Result<()> {
+    use flate2::{Compress, FlushCompress};
+
+    let mut compress = Compress::new(flate2::Compression::new(level), false);
+    let mut input = vec![0u8; 64 * 1024];
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = raw.read(&mut input)?;
+        let flush = if n == 0 {
+            FlushCompress::Sync
+        } else {
+            FlushCompress::None
+        };
+        let mut data = &input[..n];
+        loop {
+            let (before_in, before_out) = (compress.total_in(), compress.total_out());
+            compress
+                .compress(data, &mut buffer, flush)
+                .map_err(io::Error::other)?;
+            let consumed = (compress.total_in() - before_in) as usize;
+            let produced = (compress.total_out() - before_out) as usize;
+            output.write_all(&buffer[..produced])?;
+            data = &data[consumed..];
+            // 输出缓冲区未被填满说明已处理完输入（以及刷新）
+            if data.is_empty() && produced < buffer.len() {
+                break;
+            }
+        }
+        if n == 0 {
+            return Ok(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 由不小于 `LARGE_ENTRY` 的文件组成的tar流，每个文件单独成为一块
+    fn tar_stream(fills: &[u8]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (i, &fill) in fills.iter().enumerate() {
+            let data = vec![fill; LARGE_ENTRY as usize];
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, format!("res/{}", i), &data[..])
+                .unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    // 返回压缩后的数据、块数与其中从缓存中取出的块数
+    fn build(dir: &Path, tar: &[u8], compression: Compression) -> (Vec<u8>, usize, usize) {
+        let mut output = Vec::new();
+        let mut writer = ChunkWriter::new(
+            &mut output,
+            dir,
+            compression,
+            compression.default_level(),
+            1,
+        )
+        .unwrap();
+        writer.write_all(tar).unwrap();
+        writer.finish().unwrap();
+        let (chunks, reused) = (writer.chunks, writer.reused);
+        (output, chunks, reused)
+    }
+
+    fn decode(compression: Compression, data: &[u8]) -> Vec<u8> {
+        let mut decoded = Vec::new();
+        compression
+            .decoder(data)
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn second_build_reuses_chunks() {
+        for compression in [Compression::Gzip, Compression::Zstd] {
+            let dir = tempfile::tempdir().unwrap();
+            let tar = tar_stream(&[1, 2, 3]);
+            let (first, chunks, reused) = build(dir.path(), &tar, compression);
+            assert_eq!(reused, 0);
+            assert_eq!(decode(compression, &first), tar);
+
+            let (second, again, reused) = build(dir.path(), &tar, compression);
+            assert_eq!((again, reused), (chunks, chunks));
+            assert_eq!(second, first);
+
+            // 只有修改过的文件所在的块重新压缩
+            let changed = tar_stream(&[1, 9, 3]);
+            let (third, chunks, reused) = build(dir.path(), &changed, compression);
+            assert_eq!(reused, chunks - 1);
+            assert_eq!(decode(compression, &third), changed);
+        }
+    }
+}
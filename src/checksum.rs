@@ -0,0 +1,114 @@
+// 资源数据的校验和，打包端计算后写入清单，启动器解压前重新计算比对；
+// 以及启动器缓存目录名中使用的哈希
+
+use std::io::{self, Read};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// 在清单中以名称（crc32 / sha256）记录
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Checksum {
+    Crc32,
+    Sha256,
+}
+
+impl Checksum {
+    pub fn parse(name: &str) -> Option<Checksum> {
+        match name {
+            "crc32" => Some(Checksum::Crc32),
+            "sha256" => Some(Checksum::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Checksum::Crc32 => "crc32",
+            Checksum::Sha256 => "sha256",
+        }
+    }
+
+    // 计算校验和，返回小写十六进制字符串（crc32 为大端序的8位十六进制）
+    pub fn compute<R: Read>(self, mut reader: R) -> io::Result<String> {
+        let mut buffer = vec![0u8; 64 * 1024];
+        let digest = match self {
+            Checksum::Crc32 => {
+                let mut hasher = crc32fast::Hasher::new();
+                loop {
+                    let n = reader.read(&mut buffer)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..n]);
+                }
+                hasher.finalize().to_be_bytes().to_vec()
+            }
+            Checksum::Sha256 => {
+                let mut hasher = Sha256::new();
+                loop {
+                    let n = reader.read(&mut buffer)?;
+                    if n == 0 {
+                        break;
+                    }
+                    hasher.update(&buffer[..n]);
+                }
+                hasher.finalize().to_vec()
+            }
+        };
+        Ok(digest.iter().map(|b| format!("{:02x}", b)).collect())
+    }
+}
+
+/// 启动器 `--cache` 目录名中资源指纹的哈希算法，在清单中以名称（blake3 / sha256）记录，
+/// 启动器按清单中的记录计算，不会与打包端不一致
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheHash {
+    Blake3,
+    Sha256,
+}
+
+impl CacheHash {
+    pub fn parse(name: &str) -> Option<CacheHash> {
+        match name {
+            "blake3" => Some(CacheHash::Blake3),
+            "sha256" => Some(CacheHash::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            CacheHash::Blake3 => "blake3",
+            CacheHash::Sha256 => "sha256",
+        }
+    }
+
+    /// 计算哈希，返回小写十六进制字符串
+    pub fn hex(self, data: &[u8]) -> String {
+        let digest = match self {
+            CacheHash::Blake3 => blake3::hash(data).as_bytes().to_vec(),
+            CacheHash::Sha256 => Sha256::digest(data).to_vec(),
+        };
+        digest.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_hash_known_digests() {
+        assert_eq!(
+            CacheHash::Blake3.hex(b""),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+        assert_eq!(
+            CacheHash::Sha256.hex(b""),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+// 资源包的压缩方式，打包端与启动器共用
+
+use std::io::{self, Read};
+
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+
+// 在清单中以名称（gzip / zstd / brotli / none）记录，启动器据此选择解压方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    /// 适合通过HTTP分发、与CDN的编码一致；仅Rust启动器支持
+    Brotli,
+    None,
+}
+
+impl Compression {
+    pub fn parse(name: &str) -> Option<Compression> {
+        match name {
+            "gzip" => Some(Compression::Gzip),
+            "zstd" => Some(Compression::Zstd),
+            "brotli" => Some(Compression::Brotli),
+            "none" => Some(Compression::None),
+            _ => None,
+        }
+    }
+
+    // 可用的压缩等级范围，None 不支持设置等级
+    pub fn level_range(self) -> Option<(u32, u32)> {
+        match self {
+            Compression::Gzip => Some((0, 9)),
+            Compression::Zstd => Some((1, 22)),
+            Compression::Brotli => Some((0, 11)),
+            Compression::None => None,
+        }
+    }
+
+    pub fn default_level(self) -> u32 {
+        match self {
+            Compression::Gzip => 6,
+            Compression::Zstd => 3,
+            // 最高的11压缩得更小但慢得多
+            Compression::Brotli => 9,
+            Compression::None => 0,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+            Compression::Brotli => "brotli",
+            Compression::None => "none",
+        }
+    }
+
+    // 包装解压器，返回解压后的tar流
+    pub fn decoder<'a, R: Read + 'a>(self, reader: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => Box::new(GzDecoder::new(reader)),
+            Compression::Zstd => Box::new(zstd::Decoder::new(reader)?),
+            Compression::Brotli => Box::new(brotli::Decompressor::new(reader, 64 * 1024)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn brotli_round_trip() {
+        let data: Vec<u8> = (0..100_000u32)
+            .flat_map(|i| (i % 251).to_le_bytes())
+            .collect();
+        let mut encoder = brotli::CompressorWriter::new(Vec::new(), 4096, 9, 22);
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.into_inner();
+        assert!(compressed.len() < data.len());
+
+        let mut decoded = Vec::new();
+        Compression::Brotli
+            .decoder(&compressed[..])
+            .unwrap()
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, data);
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for compression in [
+            Compression::Gzip,
+            Compression::Zstd,
+            Compression::Brotli,
+            Compression::None,
+        ] {
+            assert_eq!(Compression::parse(compression.name()), Some(compression));
+        }
+    }
+}
@@ -0,0 +1,207 @@
+// 打包配置文件（sekai-pack.toml）：记录常用的打包参数，命令行参数优先于配置文件
+//
+// 配置文件中的相对路径相对于配置文件所在目录。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Deserializer};
+
+use crate::manifest;
+use crate::{
+    BundleError, BundleOptions, CacheHash, Checksum, Compression, Launcher, Layout, TargetOs,
+    WorkDir,
+};
+
+/// 未指定 `--config` 时在当前目录查找的配置文件名
+pub const DEFAULT_CONFIG: &str = "sekai-pack.toml";
+
+/// 配置文件的内容，未出现的键保持默认值，出现未知的键时报错
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub main_exe: Option<PathBuf>,
+    #[serde(default)]
+    pub resource_dirs: Vec<PathBuf>,
+    pub files_from: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    pub launcher: Option<Launcher>,
+    pub target_os: Option<TargetOs>,
+    pub format: Option<Layout>,
+    pub workdir: Option<WorkDir>,
+    pub compression: Option<Compression>,
+    pub compress_executable: Option<Compression>,
+    pub level: Option<u32>,
+    pub threads: Option<u32>,
+    pub checksum: Option<Checksum>,
+    pub cache_hash: Option<CacheHash>,
+    #[serde(default)]
+    pub entry_checksums: bool,
+    #[serde(default)]
+    pub cache: bool,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    pub entry_name: Option<String>,
+    #[serde(default)]
+    pub extra_exes: Vec<PathBuf>,
+    #[serde(default)]
+    pub append_data: Vec<PathBuf>,
+    pub entry: Option<String>,
+    pub launch_arg_template: Option<String>,
+    pub pre_launch: Option<String>,
+    /// 每项为 "KEY=VALUE"，与 `--env` 相同
+    #[serde(default, deserialize_with = "deserialize_env")]
+    pub env: Vec<(String, String)>,
+    /// 声明文件的路径，由调用方以 [`read_notice`] 读取
+    pub notice: Option<PathBuf>,
+    pub temp_prefix: Option<String>,
+    #[serde(default, rename = "static")]
+    pub static_launcher: bool,
+    #[serde(default)]
+    pub store_aligned: bool,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+    #[serde(default)]
+    pub dedup: bool,
+    #[serde(default)]
+    pub ignore_missing: bool,
+    #[serde(default)]
+    pub prefer_memory: bool,
+    #[serde(default)]
+    pub set_library_path: bool,
+    pub split_size: Option<u64>,
+    #[serde(default)]
+    pub reproducible: bool,
+    #[serde(default)]
+    pub strip: bool,
+    #[serde(default)]
+    pub no_exec_bit: bool,
+    #[serde(default)]
+    pub no_exe_check: bool,
+    #[serde(default)]
+    pub pack_launcher: bool,
+    #[serde(default)]
+    pub no_launcher: bool,
+    #[serde(default)]
+    pub force: bool,
+    pub reuse_launcher: Option<PathBuf>,
+    pub cache_dir: Option<PathBuf>,
+    /// 八进制字符串，如 `"0700"`
+    #[serde(default, deserialize_with = "deserialize_mode")]
+    pub entry_mode: Option<u32>,
+}
+
+impl Config {
+    /// 读取并解析配置文件，路径相对于配置文件所在目录解析
+    pub fn load(path: &Path) -> Result<Config, BundleError> {
+        let invalid =
+            |msg: String| BundleError::InvalidOptions(format!("{}: {}", path.display(), msg));
+        let text = fs::read_to_string(path).map_err(|e| invalid(e.to_string()))?;
+        let mut config: Config = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+
+        let base = path.parent().unwrap_or(Path::new(""));
+        let resolve = |p: &mut PathBuf| *p = base.join(&*p);
+        config.main_exe.iter_mut().for_each(resolve);
+        config.output.iter_mut().for_each(resolve);
+        config.output_dir.iter_mut().for_each(resolve);
+        config.resource_dirs.iter_mut().for_each(resolve);
+        config.files_from.iter_mut().for_each(resolve);
+        config.extra_exes.iter_mut().for_each(resolve);
+        config.append_data.iter_mut().for_each(resolve);
+        config.reuse_launcher.iter_mut().for_each(resolve);
+        config.notice.iter_mut().for_each(resolve);
+        config.cache_dir.iter_mut().for_each(resolve);
+        Ok(config)
+    }
+
+    /// 以配置文件的取值覆盖默认值得到的打包选项
+    pub fn bundle_options(&self) -> BundleOptions {
+        let defaults = BundleOptions::default();
+        BundleOptions {
+            launcher: self.launcher.unwrap_or(defaults.launcher),
+            target_os: self.target_os,
+            format: self.format.unwrap_or(defaults.format),
+            // --store-aligned 默认不压缩
+            compression: match self.compression {
+                Some(compression) => compression,
+                None if self.store_aligned => Compression::None,
+                None => defaults.compression,
+            },
+            exe_compression: self.compress_executable,
+            level: self.level,
+            threads: self.threads,
+            checksum: self.checksum.unwrap_or(defaults.checksum),
+            entry_checksums: self.entry_checksums,
+            cache: self.cache,
+            cache_hash: self.cache_hash.unwrap_or(defaults.cache_hash),
+            exclude: self.exclude.clone(),
+            entry_name: self.entry_name.clone(),
+            extra_exes: self.extra_exes.clone(),
+            append_data: self.append_data.clone(),
+            entry: self.entry.clone(),
+            launch_arg_template: self.launch_arg_template.clone(),
+            pre_launch: self.pre_launch.clone(),
+            env: self.env.clone(),
+            temp_prefix: self.temp_prefix.clone(),
+            static_launcher: self.static_launcher,
+            store_aligned: self.store_aligned,
+            follow_symlinks: self.follow_symlinks,
+            dedup: self.dedup,
+            ignore_missing: self.ignore_missing,
+            prefer_memory: self.prefer_memory,
+            set_library_path: self.set_library_path,
+            split_size: self.split_size,
+            reproducible: self.reproducible,
+            strip: self.strip,
+            no_exec_bit: self.no_exec_bit,
+            no_exe_check: self.no_exe_check,
+            pack_launcher: self.pack_launcher,
+            no_launcher: self.no_launcher,
+            force: self.force,
+            reuse_launcher: self.reuse_launcher.clone(),
+            cache_dir: self.cache_dir.clone(),
+            workdir: self.workdir.unwrap_or(defaults.workdir),
+            entry_mode: self.entry_mode.unwrap_or(defaults.entry_mode),
+            ..defaults
+        }
+    }
+}
+
+fn deserialize_mode<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<u32>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    manifest::parse_entry_mode(&text)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+fn deserialize_env<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<(String, String)>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|var| manifest::parse_env_var(var).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// 读取 `--notice` 指定的声明文件，内容须为UTF-8文本
+pub fn read_notice(path: &Path) -> Result<String, BundleError> {
+    fs::read_to_string(path).map_err(|e| {
+        BundleError::InvalidOptions(format!("Cannot read notice '{}': {}", path.display(), e))
+    })
+}
+
+/// 读取 `--files-from` 列表：每行一个要打包的文件或目录，忽略空行与以 `#` 开头的注释行，
+/// 相对路径相对于列表文件所在目录
+pub fn read_file_list(path: &Path) -> Result<Vec<PathBuf>, BundleError> {
+    let text = fs::read_to_string(path).map_err(|e| {
+        BundleError::InvalidOptions(format!("Cannot read '{}': {}", path.display(), e))
+    })?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| base.join(line))
+        .collect())
+}
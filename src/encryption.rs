@@ -0,0 +1,167 @@
+// 资源数据的加密：以口令派生密钥，用 AES-256-GCM 加密整个（已压缩的）资源数据
+//
+// 密钥由 Argon2id（默认参数）从口令与随机盐派生，盐与随机数以十六进制记录在清单中。
+// GCM 需要完整的密文才能校验认证标签，因此加密与解密都在内存中进行。
+
+use std::env;
+use std::io;
+
+use aes_gcm::aead::{Aead, Generate, KeyInit};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use argon2::Argon2;
+use serde::{Deserialize, Serialize};
+
+use crate::BundleError;
+use crate::manifest::Manifest;
+
+/// 打包端与启动器读取口令的环境变量，未设置时在终端提示输入
+pub const PASSPHRASE_ENV: &str = "SEKAI_PACK_PASSPHRASE";
+
+const SALT_LEN: usize = 16;
+
+// 在清单中以名称（none / aes-256-gcm）记录
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encryption {
+    #[default]
+    None,
+    #[serde(rename = "aes-256-gcm")]
+    Aes256Gcm,
+}
+
+impl Encryption {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encryption::None => "none",
+            Encryption::Aes256Gcm => "aes-256-gcm",
+        }
+    }
+}
+
+/// 加密后的资源数据及解密所需的参数（十六进制）
+pub struct Encrypted {
+    pub data: Vec<u8>,
+    pub salt: String,
+    pub nonce: String,
+}
+
+/// 用随机盐与随机数加密资源数据
+pub fn encrypt(passphrase: &str, plaintext: &[u8]) -> Result<Encrypted, BundleError> {
+    let salt = <[u8; SALT_LEN]>::try_generate().map_err(|e| io::Error::other(e.to_string()))?;
+    let nonce = Nonce::try_generate().map_err(|e| io::Error::other(e.to_string()))?;
+    let data = cipher(passphrase, &salt)?
+        .encrypt(&nonce, plaintext)
+        .map_err(|_| io::Error::other("failed to encrypt resources"))?;
+    Ok(Encrypted {
+        data,
+        salt: to_hex(&salt),
+        nonce: to_hex(&nonce),
+    })
+}
+
+/// 按清单记录的参数解密资源数据，口令错误或数据被篡改时返回 `DecryptionFailed`
+pub fn decrypt(
+    manifest: &Manifest,
+    passphrase: &str,
+    ciphertext: &[u8],
+) -> Result<Vec<u8>, BundleError> {
+    let invalid = || BundleError::InvalidManifest("invalid encryption parameters".to_string());
+    let salt = from_hex(&manifest.kdf_salt).ok_or_else(invalid)?;
+    let nonce = from_hex(&manifest.nonce)
+        .and_then(|nonce| Nonce::try_from(&nonce[..]).ok())
+        .ok_or_else(invalid)?;
+    cipher(passphrase, &salt)?
+        .decrypt(&nonce, ciphertext)
+        .map_err(|_| BundleError::DecryptionFailed)
+}
+
+/// 从 `SEKAI_PACK_PASSPHRASE` 读取口令，未设置时在终端提示输入（不回显）
+pub fn read_passphrase(prompt: &str) -> io::Result<String> {
+    match env::var(PASSPHRASE_ENV) {
+        Ok(passphrase) => Ok(passphrase),
+        Err(_) => rpassword::prompt_password(prompt),
+    }
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<Aes256Gcm, BundleError> {
+    let mut key = Key::<Aes256Gcm>::default();
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| io::Error::other(format!("failed to derive key: {}", e)))?;
+    Ok(Aes256Gcm::new(&key))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(sealed: &Encrypted) -> Manifest {
+        let mut manifest: Manifest = serde_json::from_value(serde_json::json!({
+            "tool_version": "0.0.0",
+            "created_at": 0,
+            "entry_name": "app",
+            "launch_args": "",
+            "compression": "none",
+            "checksum_type": "crc32",
+            "checksum": "",
+            "cache": false,
+            "resource_offset": 0,
+            "resource_len": sealed.data.len(),
+        }))
+        .unwrap();
+        manifest.encryption = Encryption::Aes256Gcm;
+        manifest.kdf_salt = sealed.salt.clone();
+        manifest.nonce = sealed.nonce.clone();
+        manifest
+    }
+
+    #[test]
+    fn round_trip() {
+        let plaintext = b"sekai resources".repeat(100);
+        let sealed = encrypt("secret", &plaintext).unwrap();
+        assert_ne!(sealed.data[..plaintext.len()], plaintext[..]);
+        let manifest = manifest(&sealed);
+        assert_eq!(
+            decrypt(&manifest, "secret", &sealed.data).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn wrong_passphrase_or_tampered_data() {
+        let sealed = encrypt("secret", b"sekai resources").unwrap();
+        let manifest = manifest(&sealed);
+        assert!(matches!(
+            decrypt(&manifest, "wrong", &sealed.data),
+            Err(BundleError::DecryptionFailed)
+        ));
+        let mut tampered = sealed.data.clone();
+        tampered[0] ^= 1;
+        assert!(matches!(
+            decrypt(&manifest, "secret", &tampered),
+            Err(BundleError::DecryptionFailed)
+        ));
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(to_hex(&[0x00, 0xab, 0xff]), "00abff");
+        assert_eq!(from_hex("00abff"), Some(vec![0x00, 0xab, 0xff]));
+        assert_eq!(from_hex("abc"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+}
@@ -0,0 +1,171 @@
+// 打包与读取打包文件时的错误类型
+
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+/// 打包过程中的错误
+#[derive(Debug)]
+pub enum BundleError {
+    /// 主程序不存在
+    MainExeNotFound(PathBuf),
+    /// 附加的可执行文件不存在
+    ExeNotFound(PathBuf),
+    /// `--append-data` 附加的文件不存在
+    DataNotFound(PathBuf),
+    /// 不存在或既不是文件也不是目录的资源路径
+    InvalidResources(Vec<PathBuf>),
+    /// 打包选项无效或相互冲突
+    InvalidOptions(String),
+    /// 找不到编译C启动器所需的gcc
+    CompilerNotFound,
+    /// C启动器编译失败，附带gcc的输出
+    CompileFailed(String),
+    /// 资源包的生成或读取失败
+    ArchiveFailed(io::Error),
+    /// 向资源包加入该文件时失败（如没有读取权限）
+    ArchiveEntry { path: PathBuf, source: io::Error },
+    /// 文件末尾没有有效的尾部元数据
+    MissingFooter,
+    /// 尾部元数据的格式版本不受支持
+    UnsupportedVersion(u8),
+    /// 尾部的魔数有效，但其中的清单偏移超出文件或不指向清单（文件被截断或篡改）
+    InvalidFooter(String),
+    /// 清单缺失字段、取值未知或与文件布局不符
+    InvalidManifest(String),
+    /// 主程序文件名不是单个普通文件名
+    InvalidEntryName(String),
+    /// 资源数据与记录的校验和不一致
+    ChecksumMismatch,
+    /// 资源数据已加密，但没有提供口令
+    PassphraseRequired,
+    /// 口令错误或加密数据被篡改
+    DecryptionFailed,
+    /// 资源条目会被解压到目标目录之外
+    UnsafeEntry { entry: PathBuf, dest: PathBuf },
+    /// 这些文件的内容与 `--entry-checksums` 记录的校验和不一致
+    EntryChecksumMismatch(Vec<PathBuf>),
+    /// 打包文件中没有该路径的文件条目
+    EntryNotFound(PathBuf),
+    /// 主程序的架构与启动器不兼容
+    ArchMismatch { exe: String, launcher: String },
+    /// 主程序不是启动器所在平台能执行的可执行文件或脚本，`expected` 描述启动器能执行的格式
+    NotExecutable { path: PathBuf, expected: String },
+    /// 其他文件读写错误
+    Io(io::Error),
+}
+
+impl fmt::Display for BundleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BundleError::MainExeNotFound(path) => {
+                write!(f, "Main executable '{}' not found", path.display())
+            }
+            BundleError::ExeNotFound(path) => {
+                write!(f, "Executable '{}' not found", path.display())
+            }
+            BundleError::DataNotFound(path) => {
+                write!(f, "Data file '{}' not found", path.display())
+            }
+            BundleError::InvalidResources(paths) => {
+                write!(
+                    f,
+                    "Resource paths not found (pass --ignore-missing to skip them):"
+                )?;
+                for path in paths {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            BundleError::InvalidOptions(msg) => write!(f, "{}", msg),
+            BundleError::CompilerNotFound => write!(
+                f,
+                "gcc not found; install a C compiler or use --launcher=rust"
+            ),
+            BundleError::CompileFailed(output) => {
+                write!(f, "Failed to compile launcher")?;
+                if !output.is_empty() {
+                    write!(f, ":\n{}", output.trim_end())?;
+                }
+                Ok(())
+            }
+            BundleError::ArchiveFailed(e) => write!(f, "Resource archive error: {}", e),
+            BundleError::ArchiveEntry { path, source } => {
+                write!(
+                    f,
+                    "Failed to add '{}' to the bundle: {}",
+                    path.display(),
+                    source
+                )
+            }
+            BundleError::MissingFooter => write!(f, "not a sekai-pack bundle"),
+            BundleError::UnsupportedVersion(version) => {
+                write!(f, "unsupported sekai-pack format version {}", version)
+            }
+            BundleError::InvalidFooter(msg) => write!(
+                f,
+                "Invalid bundle footer: {}; the bundle is truncated or corrupted",
+                msg
+            ),
+            BundleError::InvalidManifest(msg) => write!(f, "Invalid bundle manifest: {}", msg),
+            BundleError::InvalidEntryName(name) => write!(f, "Invalid entry name '{}'", name),
+            BundleError::ChecksumMismatch => {
+                write!(f, "Resource checksum mismatch: bundle is corrupted")
+            }
+            BundleError::PassphraseRequired => {
+                write!(f, "Bundle is encrypted, a passphrase is required")
+            }
+            BundleError::DecryptionFailed => {
+                write!(
+                    f,
+                    "Failed to decrypt resources: wrong passphrase or corrupted bundle"
+                )
+            }
+            BundleError::UnsafeEntry { entry, dest } => write!(
+                f,
+                "Refusing to extract '{}' outside of {}",
+                entry.display(),
+                dest.display()
+            ),
+            BundleError::EntryChecksumMismatch(paths) => {
+                write!(f, "Checksum mismatch: these files are corrupted:")?;
+                for path in paths {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            BundleError::EntryNotFound(path) => {
+                write!(f, "No file '{}' in bundle", path.display())
+            }
+            BundleError::ArchMismatch { exe, launcher } => write!(
+                f,
+                "architecture mismatch: main executable is {}, launcher is {}",
+                exe, launcher
+            ),
+            BundleError::NotExecutable { path, expected } => write!(
+                f,
+                "'{}' is not an executable the launcher can run (expected {}); pass --no-exe-check to bundle it anyway",
+                path.display(),
+                expected
+            ),
+            BundleError::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for BundleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BundleError::ArchiveFailed(e)
+            | BundleError::ArchiveEntry { source: e, .. }
+            | BundleError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for BundleError {
+    fn from(e: io::Error) -> Self {
+        BundleError::Io(e)
+    }
+}
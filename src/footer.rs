@@ -0,0 +1,241 @@
+// 打包文件尾部的元数据，打包端与启动器必须保持一致
+//
+// 打包文件布局为 `[启动器][资源数据][清单JSON][尾部]`（`--no-launcher` 时没有启动器），尾部只负责定位清单，
+// 资源的位置、压缩方式与校验和等信息均记录在清单中（见 manifest.rs）。
+// 尾部布局（小端序，位于文件末尾）：
+//   manifest_offset: u64   清单起始偏移，清单一直延续到尾部之前
+//   bundle_len:      u64   打包数据（从启动器或资源数据开头直到尾部结束）的总长度
+//   version:         u8    格式版本
+//   magic:           [u8; 8] = "SEKAIPK1"
+// 偏移都相对于打包数据的开头，即文件长度减去 `bundle_len` 处：sekaipack生成的文件为0，
+// 把 `--no-launcher` 的输出附加到宿主程序之后时为宿主程序原来的长度
+
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use crate::BundleError;
+use crate::manifest::Manifest;
+
+pub const MAGIC: &[u8; 8] = b"SEKAIPK1";
+pub const VERSION: u8 = 7;
+pub const FOOTER_LEN: usize = 25;
+
+/// 嵌入在启动器中的标识，重用已生成的启动器时据此识别其类型；末尾的版本号与 `VERSION` 一致
+pub const LAUNCHER_ID_RUST: &[u8] = b"SEKAIPK1-LAUNCHER rust v7";
+pub const LAUNCHER_ID_C: &[u8] = b"SEKAIPK1-LAUNCHER c v7";
+
+pub struct Footer {
+    pub manifest_offset: u64,
+    pub bundle_len: u64,
+}
+
+impl Footer {
+    pub fn to_bytes(&self) -> [u8; FOOTER_LEN] {
+        let mut bytes = [0u8; FOOTER_LEN];
+        bytes[0..8].copy_from_slice(&self.manifest_offset.to_le_bytes());
+        bytes[8..16].copy_from_slice(&self.bundle_len.to_le_bytes());
+        bytes[16] = VERSION;
+        bytes[17..25].copy_from_slice(MAGIC);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8; FOOTER_LEN]) -> Result<Footer, BundleError> {
+        // 先校验魔数，再信任其中的偏移
+        if &bytes[17..25] != MAGIC {
+            return Err(BundleError::MissingFooter);
+        }
+        if bytes[16] != VERSION {
+            return Err(BundleError::UnsupportedVersion(bytes[16]));
+        }
+        Ok(Footer {
+            manifest_offset: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            bundle_len: u64::from_le_bytes(bytes[8..16].try_into().unwrap()),
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
+
+    // 从文件末尾读取尾部元数据，返回元数据及打包数据在文件中的起始位置
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<(Footer, u64), BundleError> {
+        let file_size = reader.seek(SeekFrom::End(0))?;
+        let footer_start = file_size
+            .checked_sub(FOOTER_LEN as u64)
+            .ok_or(BundleError::MissingFooter)?;
+
+        let mut bytes = [0u8; FOOTER_LEN];
+        reader.seek(SeekFrom::Start(footer_start))?;
+        reader.read_exact(&mut bytes)?;
+
+        // 打包数据须包含尾部且不超出文件；清单不能为空，偏移须位于尾部之前
+        let footer = Footer::from_bytes(&bytes)?;
+        let base = file_size
+            .checked_sub(footer.bundle_len)
+            .filter(|_| footer.bundle_len >= FOOTER_LEN as u64)
+            .ok_or_else(|| {
+                BundleError::InvalidFooter(format!(
+                    "bundle length {} does not fit in the file ({} bytes)",
+                    footer.bundle_len, file_size
+                ))
+            })?;
+        if footer.manifest_offset >= footer.manifest_end() {
+            return Err(BundleError::InvalidFooter(format!(
+                "manifest offset {} is past the end of the bundle ({} bytes)",
+                footer.manifest_offset, footer.bundle_len
+            )));
+        }
+        Ok((footer, base))
+    }
+
+    /// 清单的结束位置（即尾部的起始位置），相对于打包数据的开头
+    pub fn manifest_end(&self) -> u64 {
+        self.bundle_len - FOOTER_LEN as u64
+    }
+}
+
+/// 打包数据在文件中的位置，均为相对文件开头的偏移
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// 资源数据的起始位置与长度
+    pub resource_offset: u64,
+    pub resource_len: u64,
+    /// 清单的起始位置与长度，清单之后紧接尾部
+    pub manifest_offset: u64,
+    pub manifest_len: u64,
+    /// 尾部的起始位置，其后即为文件末尾
+    pub footer_offset: u64,
+}
+
+/// 在文件末尾定位尾部、清单与资源数据，返回各部分的位置与清单。
+/// 打包数据的起始位置由尾部记录的总长度得出，因此也适用于附加在宿主程序之后的 `--no-launcher` 输出。
+/// 与启动器一样校验魔数、版本、清单偏移与资源范围，任一项不符时报错
+pub fn locate<R: Read + Seek>(reader: &mut R) -> Result<(Location, Manifest), BundleError> {
+    let (footer, base) = Footer::read_from(reader)?;
+    let manifest = Manifest::read_from(&mut Based {
+        inner: reader,
+        base,
+    })?;
+    let location = Location {
+        resource_offset: base + manifest.resource_offset,
+        resource_len: manifest.resource_len,
+        manifest_offset: base + footer.manifest_offset,
+        manifest_len: footer.manifest_end() - footer.manifest_offset,
+        footer_offset: base + footer.manifest_end(),
+    };
+    Ok((location, manifest))
+}
+
+// 把 `base` 之后的部分当作整个文件读取
+struct Based<'a, R> {
+    inner: &'a mut R,
+    base: u64,
+}
+
+impl<R: Read> Read for Based<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for Based<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let pos = match pos {
+            SeekFrom::Start(offset) => SeekFrom::Start(self.base + offset),
+            pos => pos,
+        };
+        let position = self.inner.seek(pos)?;
+        position.checked_sub(self.base).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the base offset is past the end of the file",
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::tests::test_bundle;
+    use std::io::Cursor;
+
+    #[test]
+    fn locate_after_host() {
+        let bundle = test_bundle(b"resources");
+        let (location, _) = locate(&mut Cursor::new(&bundle)).unwrap();
+        assert_eq!(location.resource_offset, 0);
+
+        // 附加在宿主程序之后，起始位置由尾部记录的总长度得出
+        let mut host = b"host executable".to_vec();
+        let host_len = host.len() as u64;
+        host.extend_from_slice(&bundle);
+        let (location, manifest) = locate(&mut Cursor::new(&host)).unwrap();
+        assert_eq!(location.resource_offset, host_len);
+        assert_eq!(location.resource_len, 9);
+        assert_eq!(
+            location.footer_offset,
+            host.len() as u64 - FOOTER_LEN as u64
+        );
+        assert_eq!(manifest.entry_name, "app");
+        // 从文件开头读取清单时拒绝，而不是把偏移当作相对于文件开头
+        assert!(matches!(
+            Manifest::read_from(&mut Cursor::new(&host)),
+            Err(BundleError::InvalidFooter(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_bundle() {
+        let bundle = test_bundle(b"resources");
+        let truncated = bundle[3..].to_vec();
+        assert!(matches!(
+            locate(&mut Cursor::new(&truncated)),
+            Err(BundleError::InvalidFooter(_))
+        ));
+    }
+
+    // 把尾部记录的清单偏移改为 `offset`
+    fn with_manifest_offset(offset: u64) -> Vec<u8> {
+        let mut bundle = test_bundle(b"resources");
+        let footer = bundle.len() - FOOTER_LEN;
+        bundle[footer..footer + 8].copy_from_slice(&offset.to_le_bytes());
+        bundle
+    }
+
+    #[test]
+    fn rejects_offset_too_large() {
+        // 尾部的起始位置处的清单为空
+        let footer = (test_bundle(b"resources").len() - FOOTER_LEN) as u64;
+        for offset in [u64::MAX, footer] {
+            let bundle = with_manifest_offset(offset);
+            match Manifest::read_from(&mut Cursor::new(&bundle)) {
+                Err(BundleError::InvalidFooter(msg)) => assert!(msg.contains("past the end")),
+                Err(e) => panic!("unexpected error {}", e),
+                Ok(_) => panic!("accepted offset {}", offset),
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_offset_zero() {
+        let bundle = with_manifest_offset(0);
+        match Manifest::read_from(&mut Cursor::new(&bundle)) {
+            Err(BundleError::InvalidFooter(msg)) => {
+                assert!(msg.contains("does not point at the manifest"))
+            }
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("accepted offset 0"),
+        }
+    }
+
+    #[test]
+    fn rejects_missing_magic() {
+        let mut bundle = test_bundle(b"resources");
+        let last = bundle.len() - 1;
+        bundle[last] ^= 1;
+        assert!(matches!(
+            Manifest::read_from(&mut Cursor::new(&bundle)),
+            Err(BundleError::MissingFooter)
+        ));
+    }
+}
@@ -0,0 +1,585 @@
+// 读取已生成的打包文件：列出、读取或解压其中的资源
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::BundleError;
+use crate::checksum::Checksum;
+use crate::compression::Compression;
+use crate::encryption::{self, Encryption};
+use crate::launcher::{self, Launcher};
+use crate::manifest::{Layout, Manifest, is_contained_path};
+use crate::unpack;
+
+/// 读取打包文件的清单
+pub fn manifest(bundle: &Path) -> Result<Manifest, BundleError> {
+    Manifest::read_from(&mut fs::File::open(bundle)?)
+}
+
+// `--pack-launcher` 生成的文件：校验外层的校验和后，把其中完整的自解压文件解压到临时文件中，
+// 之后按该文件读取；其他打包文件返回 `None`
+fn unpack_packed(path: &Path) -> Result<Option<tempfile::NamedTempFile>, BundleError> {
+    let mut file = fs::File::open(path)?;
+    let manifest = Manifest::read_from(&mut file)?;
+    if manifest.layout != Layout::Packed {
+        return Ok(None);
+    }
+    if compute_checksum(&mut file, &manifest)? != manifest.checksum {
+        return Err(BundleError::ChecksumMismatch);
+    }
+    file.seek(SeekFrom::Start(manifest.resource_offset))?;
+    let mut inner = tempfile::NamedTempFile::new()?;
+    let mut decoder = manifest
+        .compression
+        .decoder(file.take(manifest.resource_len))?;
+    io::copy(&mut decoder, &mut inner).map_err(BundleError::ArchiveFailed)?;
+    Ok(Some(inner))
+}
+
+// 打包文件中的资源数据：未加密时不读入内存，每次读取时重新打开文件并限定在所需范围内；
+// 已加密时需要先完整解密（AES-GCM 只能整体校验），之后从解密后的数据中读取
+pub(crate) struct Resources {
+    source: Source,
+    len: u64,
+    // `--pack-launcher` 的文件中解压出的自解压文件，需要在读取期间保留
+    _packed: Option<tempfile::NamedTempFile>,
+}
+
+enum Source {
+    File { path: PathBuf, offset: u64 },
+    Decrypted(Vec<u8>),
+}
+
+// 通过清单定位打包文件中的资源数据，已加密时用口令解密
+pub(crate) fn open_resources(
+    path: &Path,
+    passphrase: Option<&str>,
+) -> Result<(Manifest, Resources), BundleError> {
+    let packed = unpack_packed(path)?;
+    let path = packed.as_ref().map_or(path, |packed| packed.path());
+    let mut file = fs::File::open(path)?;
+    let manifest = Manifest::read_from(&mut file)?;
+
+    let (source, len) = match manifest.encryption {
+        Encryption::None => (
+            Source::File {
+                path: path.to_path_buf(),
+                offset: manifest.resource_offset,
+            },
+            manifest.resource_len,
+        ),
+        Encryption::Aes256Gcm => {
+            let mut data = Vec::new();
+            file.seek(SeekFrom::Start(manifest.resource_offset))?;
+            file.take(manifest.resource_len).read_to_end(&mut data)?;
+            let data = decrypt_resources(&manifest, passphrase, data)?;
+            let len = data.len() as u64;
+            (Source::Decrypted(data), len)
+        }
+    };
+    let resources = Resources {
+        source,
+        len,
+        _packed: packed,
+    };
+    Ok((manifest, resources))
+}
+
+impl Resources {
+    // 资源数据（解密后）中指定范围的读取器
+    fn open(&self, range: Range<u64>) -> io::Result<Box<dyn Read + '_>> {
+        match &self.source {
+            Source::File { path, offset } => {
+                let mut file = fs::File::open(path)?;
+                file.seek(SeekFrom::Start(offset + range.start))?;
+                Ok(Box::new(io::BufReader::new(
+                    file.take(range.end - range.start),
+                )))
+            }
+            Source::Decrypted(data) => {
+                Ok(Box::new(&data[range.start as usize..range.end as usize]))
+            }
+        }
+    }
+
+    fn sections(&self, manifest: &Manifest) -> Vec<(Compression, Range<u64>)> {
+        manifest.sections(self.len)
+    }
+
+    // 依次解压各段，拼接为连续的tar流
+    pub(crate) fn decode(&self, manifest: &Manifest) -> io::Result<Box<dyn Read + '_>> {
+        let mut reader: Box<dyn Read + '_> = Box::new(io::empty());
+        for (compression, range) in self.sections(manifest) {
+            reader = Box::new(reader.chain(compression.decoder(self.open(range)?)?));
+        }
+        Ok(reader)
+    }
+}
+
+// 分段时每段的tar流各自以全零块结束，需要越过它们继续读取下一段
+pub(crate) fn tar_archive<R: Read>(manifest: &Manifest, reader: R) -> tar::Archive<R> {
+    let mut archive = tar::Archive::new(reader);
+    archive.set_ignore_zeros(manifest.exe_compression.is_some());
+    archive
+}
+
+fn decrypt_resources(
+    manifest: &Manifest,
+    passphrase: Option<&str>,
+    resources: Vec<u8>,
+) -> Result<Vec<u8>, BundleError> {
+    match (manifest.encryption, passphrase) {
+        (Encryption::None, _) => Ok(resources),
+        (Encryption::Aes256Gcm, Some(passphrase)) => {
+            encryption::decrypt(manifest, passphrase, &resources)
+        }
+        (Encryption::Aes256Gcm, None) => Err(BundleError::PassphraseRequired),
+    }
+}
+
+/// 已打开的打包文件：打开时只读取一遍tar流记下各条目，读取条目时才按需解压其内容，无需解压到磁盘
+pub struct Bundle {
+    manifest: Manifest,
+    resources: Resources,
+    entries: Vec<BundleEntry>,
+}
+
+/// 资源包中的一个条目（文件、目录或链接）
+#[derive(Clone, Debug, Serialize)]
+pub struct BundleEntry {
+    /// 包内路径，在unix上保留原始字节（可以不是合法的UTF-8）
+    #[serde(serialize_with = "serialize_path_lossy")]
+    pub path: PathBuf,
+    /// 文件内容的大小，`--dedup` 的重复文件（以及旧版打包文件中的硬链接）为其原文件的大小，其余条目为0
+    pub size: u64,
+    pub mode: u32,
+    // 文件内容的位置，非文件条目为 `None`
+    #[serde(skip)]
+    data: Option<EntryData>,
+}
+
+// 文件内容位于第 `section` 段解压后的tar流中 `start` 处
+#[derive(Clone, Copy, Debug)]
+struct EntryData {
+    section: usize,
+    start: u64,
+    len: u64,
+}
+
+// 序列化为字符串时无法表示的字节替换为 U+FFFD，而不是让整个输出失败
+fn serialize_path_lossy<S: serde::Serializer>(
+    path: &Path,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// 打开打包文件以读取其中的条目。
+/// 加密的打包文件需要口令才能读出tar流中的条目，因此与 `list`、`extract` 一样接受 `passphrase`，
+/// 未加密的打包文件传入 `None`
+pub fn open_bundle(path: &Path, passphrase: Option<&str>) -> Result<Bundle, BundleError> {
+    let (manifest, resources) = open_resources(path, passphrase)?;
+
+    // 逐段流式读取tar流，只记下每个文件内容的位置，不保留解压后的数据
+    let index = || -> io::Result<Vec<BundleEntry>> {
+        let mut entries: Vec<BundleEntry> = Vec::new();
+        for (section, (compression, range)) in resources.sections(&manifest).into_iter().enumerate()
+        {
+            let mut archive = tar::Archive::new(compression.decoder(resources.open(range)?)?);
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let header = entry.header();
+                let kind = header.entry_type();
+                let data = if kind.is_file() {
+                    Some(EntryData {
+                        section,
+                        start: entry.raw_file_position(),
+                        len: header.size()?,
+                    })
+                } else if kind.is_hard_link() {
+                    let target = entry.link_name()?.unwrap_or_default();
+                    entries
+                        .iter()
+                        .find(|e| e.path == *target)
+                        .and_then(|e| e.data)
+                } else {
+                    None
+                };
+                entries.push(BundleEntry {
+                    path: entry.path()?.into_owned(),
+                    size: data.map_or(0, |data| data.len),
+                    mode: header.mode()?,
+                    data,
+                });
+            }
+        }
+        // `--dedup` 的重复文件不在tar流中，与原文件共享同一份内容
+        for (path, original) in manifest.duplicates() {
+            if let Some(original) = entries.iter().find(|e| e.path == Path::new(original)) {
+                let entry = BundleEntry {
+                    path: PathBuf::from(path),
+                    ..original.clone()
+                };
+                entries.push(entry);
+            }
+        }
+        Ok(entries)
+    };
+    let entries = index().map_err(BundleError::ArchiveFailed)?;
+
+    Ok(Bundle {
+        manifest,
+        resources,
+        entries,
+    })
+}
+
+impl Bundle {
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    /// 按tar流中的顺序遍历所有条目
+    pub fn entries(&self) -> impl Iterator<Item = &BundleEntry> {
+        self.entries.iter()
+    }
+
+    /// `--append-data` 附加的文件，路径位于 `.sekai-pack-data` 目录中
+    pub fn data_entries(&self) -> impl Iterator<Item = &BundleEntry> {
+        self.entries
+            .iter()
+            .filter(|e| self.manifest.is_data_entry(&e.path))
+    }
+
+    /// 读取文件条目的内容，`name` 为包内路径（如 `script/main.lua`）。
+    /// 未压缩的资源直接读取文件中的对应范围，压缩的资源从所在段的开头解压到该条目为止。
+    /// 每次调用都重新解压，不保留上次的进度，逐个读取所有条目的开销随条目数成平方增长；
+    /// 需要读取大部分文件时应改用 [`extract`]
+    pub fn read_entry(&self, name: impl AsRef<Path>) -> Result<Vec<u8>, BundleError> {
+        let name = name.as_ref();
+        let data = self
+            .entries
+            .iter()
+            .find(|e| e.path == name)
+            .and_then(|e| e.data)
+            .ok_or_else(|| BundleError::EntryNotFound(name.to_path_buf()))?;
+        let read = || -> io::Result<Vec<u8>> {
+            let (compression, range) = self
+                .resources
+                .sections(&self.manifest)
+                .swap_remove(data.section);
+            let mut reader = match compression {
+                Compression::None => {
+                    let start = range.start + data.start;
+                    self.resources.open(start..start + data.len)?
+                }
+                _ => {
+                    let mut decoder = compression.decoder(self.resources.open(range)?)?;
+                    io::copy(&mut (&mut decoder).take(data.start), &mut io::sink())?;
+                    decoder
+                }
+            };
+            let mut contents = vec![0; data.len as usize];
+            reader.read_exact(&mut contents)?;
+            Ok(contents)
+        };
+        read().map_err(BundleError::ArchiveFailed)
+    }
+}
+
+/// 列出打包文件中的资源路径及大小，不解压到磁盘；加密的打包文件需要提供口令
+pub fn list(bundle: &Path, passphrase: Option<&str>) -> Result<Vec<(PathBuf, u64)>, BundleError> {
+    let bundle = open_bundle(bundle, passphrase)?;
+    Ok(bundle.entries().map(|e| (e.path.clone(), e.size)).collect())
+}
+
+/// 将打包文件中的资源解压到指定目录，目录不存在时自动创建；加密的打包文件需要提供口令。
+/// 与启动器使用同一套解压流程（[`unpack::unpack_section`]），未加密的资源逐段从文件流式解压
+pub fn extract(
+    bundle: &Path,
+    dest_dir: &Path,
+    passphrase: Option<&str>,
+) -> Result<(), BundleError> {
+    let (manifest, resources) = open_resources(bundle, passphrase)?;
+    fs::create_dir_all(dest_dir)?;
+    for (compression, range) in resources.sections(&manifest) {
+        let section = resources.open(range)?;
+        unpack::unpack_section(&manifest, compression, section, dest_dir, false)?;
+    }
+    Ok(())
+}
+
+/// 校验结果：资源包中的文件数（含可执行文件）与文件总大小，以及生成打包文件的工具版本与其中的启动器
+#[derive(Clone, Debug)]
+pub struct VerifyReport {
+    /// tar流中的条目数（含目录与链接）
+    pub entries: usize,
+    pub files: usize,
+    pub bytes: u64,
+    /// 其中可执行文件的个数，其余为资源文件
+    pub executables: usize,
+    pub tool_version: String,
+    /// 无法识别时（例如由旧版本生成，或以 `--no-launcher` 生成）为 `None`
+    pub launcher: Option<Launcher>,
+    pub layout: Layout,
+    pub checksum_type: Checksum,
+    /// 重新计算的资源校验和，校验成功时与清单中记录的相同
+    pub checksum: String,
+}
+
+/// 按清单中记录的算法重新计算打包文件的资源校验和，不与记录的值比较
+pub fn resource_checksum(bundle: &Path) -> Result<String, BundleError> {
+    let mut file = fs::File::open(bundle)?;
+    let manifest = Manifest::read_from(&mut file)?;
+    compute_checksum(&mut file, &manifest)
+}
+
+fn compute_checksum(file: &mut fs::File, manifest: &Manifest) -> Result<String, BundleError> {
+    file.seek(SeekFrom::Start(manifest.resource_offset))?;
+    Ok(manifest
+        .checksum_type
+        .compute(file.take(manifest.resource_len))?)
+}
+
+/// 校验打包文件：检查尾部与清单、重新计算资源校验和，并完整读取一遍tar流（不写入磁盘）；
+/// 加密的打包文件需要提供口令
+pub fn verify(bundle: &Path, passphrase: Option<&str>) -> Result<VerifyReport, BundleError> {
+    // 其中的自解压文件按原样校验，报告外层（已校验过）的校验和，与清单中记录的一致
+    if let Some(inner) = unpack_packed(bundle)? {
+        let outer = Manifest::read_from(&mut fs::File::open(bundle)?)?;
+        let mut report = verify(inner.path(), passphrase)?;
+        (report.checksum_type, report.checksum) = (outer.checksum_type, outer.checksum);
+        return Ok(report);
+    }
+
+    let mut file = fs::File::open(bundle)?;
+    let manifest = Manifest::read_from(&mut file)?;
+    let launcher = launcher::read_reusable_launcher(bundle)
+        .ok()
+        .map(|(kind, _)| kind);
+
+    let checksum = compute_checksum(&mut file, &manifest)?;
+    // 记录了每个文件的校验和时继续读取tar流，以便指出具体损坏的文件
+    let entry_checksums = manifest.entry_checksum_map();
+    if checksum != manifest.checksum && entry_checksums.is_empty() {
+        return Err(BundleError::ChecksumMismatch);
+    }
+
+    // 加密的资源数据需要先完整解密，未加密时直接从文件流式读取
+    let (_, resources) = open_resources(bundle, passphrase)?;
+
+    // 读取每个条目的内容，以便同时检出压缩流本身的损坏；与启动器一样拒绝会解压到目录之外的条目
+    let mut corrupted = Vec::new();
+    let mut unsafe_entry = None;
+    // `--dedup` 的原文件的大小，重复文件按原文件计入
+    let duplicates = manifest.duplicates();
+    let mut originals: HashMap<&str, Option<u64>> = duplicates
+        .iter()
+        .map(|&(_, original)| (original, None))
+        .collect();
+    let mut scan = || -> io::Result<VerifyReport> {
+        let mut report = VerifyReport {
+            entries: 0,
+            files: 0,
+            bytes: 0,
+            executables: 0,
+            tool_version: manifest.tool_version.clone(),
+            launcher,
+            layout: manifest.layout,
+            checksum_type: manifest.checksum_type,
+            checksum: checksum.clone(),
+        };
+        let mut archive = tar_archive(&manifest, resources.decode(&manifest)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            report.entries += 1;
+            let path = entry.path()?.into_owned();
+            if !is_contained_path(&path) {
+                unsafe_entry = Some(path);
+                break;
+            }
+            let name = path.to_string_lossy().into_owned();
+            let expected = entry_checksums.get(name.as_str());
+            let size = match expected {
+                Some(expected) if entry.header().entry_type().is_file() => {
+                    if manifest.checksum_type.compute(&mut entry)? != *expected {
+                        corrupted.push(path);
+                    }
+                    entry.size()
+                }
+                _ => io::copy(&mut entry, &mut io::sink())?,
+            };
+            if entry.header().entry_type().is_file() {
+                if let Some(original) = originals.get_mut(name.as_str()) {
+                    *original = Some(size);
+                }
+                report.files += 1;
+                report.bytes += size;
+                if manifest.is_executable_entry(&entry.path()?) {
+                    report.executables += 1;
+                }
+            }
+        }
+        Ok(report)
+    };
+
+    let report = scan();
+    if let Some(entry) = unsafe_entry {
+        return Err(BundleError::UnsafeEntry {
+            entry,
+            dest: PathBuf::from("the extraction directory"),
+        });
+    }
+    if !corrupted.is_empty() {
+        return Err(BundleError::EntryChecksumMismatch(corrupted));
+    }
+    if checksum != manifest.checksum {
+        return Err(BundleError::ChecksumMismatch);
+    }
+    let mut report = report.map_err(BundleError::ArchiveFailed)?;
+    for (path, original) in duplicates {
+        if !is_contained_path(Path::new(path)) {
+            return Err(BundleError::UnsafeEntry {
+                entry: PathBuf::from(path),
+                dest: PathBuf::from("the extraction directory"),
+            });
+        }
+        let size = originals.get(original).copied().flatten().ok_or_else(|| {
+            BundleError::InvalidManifest(format!(
+                "duplicate '{}' refers to '{}', which is not a file in the archive",
+                path, original
+            ))
+        })?;
+        report.files += 1;
+        report.bytes += size;
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::manifest::tests::test_bundle;
+
+    // 直接写入tar头的文件名，`tar::Builder` 不接受 `..` 与绝对路径
+    fn raw_tar(entries: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (name, data) in entries {
+            let mut header = tar::Header::new_old();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn write_bundle(dir: &Path, entries: &[(&str, &[u8])]) -> PathBuf {
+        let path = dir.join("test.bundle");
+        fs::write(&path, test_bundle(&raw_tar(entries))).unwrap();
+        path
+    }
+
+    #[test]
+    fn verify_accepts_contained_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = write_bundle(dir.path(), &[("app", b"#!/bin/sh\n"), ("res/a", b"aaa")]);
+        let report = verify(&bundle, None).unwrap();
+        assert_eq!(report.files, 2);
+        assert_eq!(report.bytes, 13);
+    }
+
+    #[test]
+    fn verify_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["../evil", "res/../../evil", "/tmp/evil"] {
+            let bundle = write_bundle(dir.path(), &[("app", b""), (name, b"evil")]);
+            match verify(&bundle, None) {
+                Err(BundleError::UnsafeEntry { entry, .. }) => assert_eq!(entry, Path::new(name)),
+                Err(e) => panic!("{}: unexpected error {}", name, e),
+                Ok(_) => panic!("{}: verify accepted the entry", name),
+            }
+            let dest = dir.path().join("out");
+            assert!(matches!(
+                extract(&bundle, &dest, None),
+                Err(BundleError::UnsafeEntry { .. })
+            ));
+        }
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn verify_names_corrupted_entry() {
+        let opts = crate::BundleOptions {
+            no_launcher: true,
+            compression: crate::Compression::None,
+            entry_checksums: true,
+            ..crate::BundleOptions::default()
+        };
+        let entries = [
+            ("res/good".to_string(), b"good contents".to_vec()),
+            ("res/bad".to_string(), b"bad contents".to_vec()),
+        ];
+        let mut data = crate::bundle_to_vec(b"#!/bin/sh\n", &entries, opts).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("test.bundle");
+        fs::write(&bundle, &data).unwrap();
+        verify(&bundle, None).unwrap();
+
+        // 只改动 res/bad 的内容
+        let pos = data
+            .windows(12)
+            .position(|window| window == b"bad contents")
+            .unwrap();
+        data[pos] = b'B';
+        fs::write(&bundle, &data).unwrap();
+        match verify(&bundle, None) {
+            Err(BundleError::EntryChecksumMismatch(paths)) => {
+                assert_eq!(paths, [PathBuf::from("res/bad")])
+            }
+            Err(e) => panic!("unexpected error {}", e),
+            Ok(_) => panic!("verify accepted the corrupted bundle"),
+        }
+    }
+
+    #[test]
+    fn read_entry_decodes_on_demand() {
+        let entries = [
+            ("res/a".to_string(), b"first file".repeat(100)),
+            ("res/b".to_string(), b"second file".repeat(100)),
+        ];
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("test.bundle");
+        for (compression, exe_compression) in [
+            (crate::Compression::None, None),
+            (crate::Compression::Gzip, None),
+            (crate::Compression::Zstd, Some(crate::Compression::Gzip)),
+        ] {
+            let opts = crate::BundleOptions {
+                no_launcher: true,
+                compression,
+                exe_compression,
+                ..crate::BundleOptions::default()
+            };
+            fs::write(
+                &bundle,
+                crate::bundle_to_vec(b"#!/bin/sh\n", &entries, opts).unwrap(),
+            )
+            .unwrap();
+            let opened = open_bundle(&bundle, None).unwrap();
+            for (name, contents) in &entries {
+                assert_eq!(opened.read_entry(name).unwrap(), *contents);
+            }
+            assert!(matches!(
+                opened.read_entry("res/missing"),
+                Err(BundleError::EntryNotFound(_))
+            ));
+        }
+    }
+}
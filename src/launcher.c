@@ -1,4 +1,7 @@
+// glibc 只在启用扩展时声明 nftw，macOS 默认即可使用
+#define _GNU_SOURCE
 #include <stdio.h>
+#include <stdarg.h>
 #include <stdlib.h>
 #include <string.h>
 #include <unistd.h>
@@ -6,123 +9,917 @@
 #include <fcntl.h>
 #include <libgen.h>
 #include <stdint.h>
+#include <limits.h>
+#include <errno.h>
+#include <signal.h>
+#include <sys/wait.h>
+#include <sys/statvfs.h>
+#include <ftw.h>
+#include <zlib.h>
+#ifdef __APPLE__
+#include <mach-o/dyld.h>
+#endif
 
-int main(int argc, char *argv[]) {
-    if (argc > 1 && strcmp(argv[1], "--version") == 0) {
-        printf("bundled app v1.0\n");
-        return 0;
+#define FOOTER_LEN 25
+#define FOOTER_VERSION 7
+#define FOOTER_MAGIC "SEKAIPK1"
+// 打包工具的版本，编译时由 sekaipack 以 -D 传入
+#ifndef SEKAIPACK_VERSION
+#define SEKAIPACK_VERSION "unknown"
+#endif
+#define MAX_MANIFEST_LEN (1024 * 1024)
+#define KEEP_TEMP_ENV "SEKAI_PACK_KEEP_TEMP"
+#define TMPDIR_ENV "SEKAI_PACK_TMPDIR"
+#define PRINT_CMD_ENV "SEKAI_PACK_PRINT_CMD"
+#define RUN_DIR_ENV "SEKAI_PACK_RUN_DIR"
+// 与 manifest.rs 中的 DEFAULT_TEMP_PREFIX 保持一致
+#define DEFAULT_TEMP_PREFIX "bundled_app_"
+// 与 manifest.rs 中的 DATA_DIR 与 DATA_DIR_ENV 保持一致
+#define DATA_DIR ".sekai-pack-data"
+#define DATA_DIR_ENV "SEKAI_PACK_DATA"
+// 与 manifest.rs 中的 ALIGNED_INDEX_FILE、ALIGNED_INDEX_ENV 与 BUNDLE_ENV 保持一致
+#define ALIGNED_INDEX_FILE ".sekai-pack-index"
+#define ALIGNED_INDEX_ENV "SEKAI_PACK_INDEX"
+#define BUNDLE_ENV "SEKAI_PACK_BUNDLE"
+// 与 manifest.rs 中的 PACKED_ENV 保持一致
+#define PACKED_ENV "SEKAI_PACK_PACKED"
+// 以 --set-library-path 打包时，解压目录加到此环境变量的最前面
+#if defined(__APPLE__)
+#define LIBRARY_PATH_ENV "DYLD_LIBRARY_PATH"
+#else
+#define LIBRARY_PATH_ENV "LD_LIBRARY_PATH"
+#endif
+
+// 启动器自身失败时的保留退出码，与 manifest.rs 中的定义保持一致
+#define EXIT_EXTRACT_FAILED 120
+#define EXIT_INTEGRITY_FAILED 121
+#define EXIT_EXEC_FAILED 122
+
+// 启动器自身的架构，名称与 arch.rs 中的一致
+#if defined(__x86_64__)
+#define LAUNCHER_ARCH "x86_64"
+#elif defined(__i386__)
+#define LAUNCHER_ARCH "x86"
+#elif defined(__aarch64__)
+#define LAUNCHER_ARCH "aarch64"
+#elif defined(__arm__)
+#define LAUNCHER_ARCH "arm"
+#elif defined(__riscv) && __riscv_xlen == 64
+#define LAUNCHER_ARCH "riscv64"
+#else
+#define LAUNCHER_ARCH ""
+#endif
+
+// 启动器标识，与 footer.rs 中的 LAUNCHER_ID_C 保持一致
+__attribute__((used)) static const char launcher_id[] = "SEKAIPK1-LAUNCHER c v7";
+
+// 退出时删除的临时解压目录，为空时不清理
+static char cleanup_dir[PATH_MAX];
+
+// 正在运行的子进程pid，供信号处理函数转发信号；子进程位于单独的进程组时为进程组号的负值
+static volatile pid_t child_pid = 0;
+
+// 定位当前运行的可执行文件：Linux 读取 /proc/self/exe，macOS 使用 _NSGetExecutablePath；
+// 得到的路径用于 SEKAI_PACK_BUNDLE，Linux上资源总是经由 /proc/self/exe 读取
+static int get_exe_path(char *buf, size_t size) {
+#ifdef __APPLE__
+    char raw[PATH_MAX];
+    uint32_t raw_size = sizeof(raw);
+    if (size < PATH_MAX || _NSGetExecutablePath(raw, &raw_size) != 0) {
+        return -1;
     }
-    
-    char exe_path[4096];
-    ssize_t len = readlink("/proc/self/exe", exe_path, sizeof(exe_path) - 1);
+    // _NSGetExecutablePath 可能返回包含符号链接或相对部分的路径
+    if (!realpath(raw, buf)) {
+        return -1;
+    }
+    return 0;
+#else
+    ssize_t len = readlink("/proc/self/exe", buf, size - 1);
     if (len == -1) {
-        perror("Failed to get executable path");
-        return 1;
+        return -1;
     }
-    exe_path[len] = '\0';
-    
-    char temp_template[] = "/tmp/bundled_app_XXXXXX";
-    char *temp_dir = mkdtemp(temp_template);
-    if (!temp_dir) {
-        perror("Failed to create temp directory");
-        return 1;
+    buf[len] = '\0';
+    // 打包文件在启动后被删除（如自动更新时被替换）时链接目标带有此后缀，去掉后为原路径
+    const char *deleted = " (deleted)";
+    size_t suffix_len = strlen(deleted);
+    if ((size_t)len > suffix_len && strcmp(buf + len - suffix_len, deleted) == 0) {
+        buf[len - suffix_len] = '\0';
     }
-    
-    int exe_fd = open(exe_path, O_RDONLY);
-    if (exe_fd == -1) {
-        perror("Failed to open executable");
+    return 0;
+#endif
+}
+
+// 本启动器能否运行该架构的主程序，与 arch.rs 中的 is_compatible 保持一致；
+// 未记录架构（旧版清单或无法识别）时不检查
+static int arch_compatible(const char *arch) {
+    if (!arch || arch[0] == '\0' || strcmp(arch, LAUNCHER_ARCH) == 0) {
         return 1;
     }
-    
-    struct stat st;
-    if (fstat(exe_fd, &st) == -1) {
-        perror("Failed to get file size");
+    return (strcmp(LAUNCHER_ARCH, "x86_64") == 0 && strcmp(arch, "x86") == 0)
+        || (strcmp(LAUNCHER_ARCH, "aarch64") == 0 && strcmp(arch, "arm") == 0);
+}
+
+// 格式化路径或命令，结果被截断时返回 -1（避免用截断的路径继续执行）
+__attribute__((format(printf, 3, 4)))
+static int format_checked(char *buf, size_t size, const char *fmt, ...) {
+    va_list args;
+    va_start(args, fmt);
+    int len = vsnprintf(buf, size, fmt, args);
+    va_end(args);
+    if (len < 0 || (size_t)len >= size) {
+        fprintf(stderr, "Path too long: %s...\n", buf);
+        return -1;
+    }
+    return 0;
+}
+
+static int env_flag(const char *name) {
+    const char *value = getenv(name);
+    return value && value[0] != '\0' && strcmp(value, "0") != 0;
+}
+
+// 设置为非空且非 "0" 时保留临时解压目录，便于调试；只输出命令时同样保留
+static int keep_temp(void) {
+    return env_flag(KEEP_TEMP_ENV) || env_flag(PRINT_CMD_ENV);
+}
+
+static int remove_entry(const char *path, const struct stat *st, int type, struct FTW *ftw) {
+    (void)st;
+    (void)type;
+    (void)ftw;
+    remove(path);
+    return 0;
+}
+
+// 通过 atexit 注册，启动器从 main 返回或 exit 时（包括出错提前返回）删除临时目录；
+// 自底向上删除且不跟随符号链接，不经过shell
+static void remove_temp_dir(void) {
+    if (cleanup_dir[0] == '\0') {
+        return;
+    }
+    nftw(cleanup_dir, remove_entry, 16, FTW_DEPTH | FTW_PHYS);
+}
+
+// 在 dir 中解压资源包，直接执行 tar 而不经过shell，compress_flag 为 NULL 时不解压缩
+static int extract_archive(const char *compress_flag, const char *archive, const char *dir) {
+    pid_t pid = fork();
+    if (pid == -1) {
+        return -1;
+    }
+    if (pid == 0) {
+        char *args[7];
+        int i = 0;
+        args[i++] = "tar";
+        if (compress_flag) {
+            args[i++] = (char *)compress_flag;
+        }
+        args[i++] = "-xf";
+        args[i++] = (char *)archive;
+        args[i++] = "-C";
+        args[i++] = (char *)dir;
+        args[i] = NULL;
+        execvp("tar", args);
+        perror("Failed to run tar");
+        _exit(127);
+    }
+
+    int status;
+    while (waitpid(pid, &status, 0) == -1) {
+        if (errno != EINTR) {
+            return -1;
+        }
+    }
+    return WIFEXITED(status) && WEXITSTATUS(status) == 0 ? 0 : -1;
+}
+
+// 将SIGINT/SIGTERM转发给子进程，启动器自身继续等待子进程退出后清理；
+// 与启动器同一进程组的子进程已经收到终端发出（si_pid 为0）的信号，不再转发
+static void forward_signal(int sig, siginfo_t *info, void *context) {
+    (void)context;
+    if (child_pid < 0 || (child_pid > 0 && info->si_pid != 0)) {
+        kill(child_pid, sig);
+    }
+}
+
+// 将参数中的每个 {dir} 替换为解压目录，返回新分配的字符串
+static char *expand_launch_arg(const char *token, const char *dir) {
+    const char *placeholder = "{dir}";
+    size_t placeholder_len = strlen(placeholder);
+    size_t count = 0;
+    for (const char *p = strstr(token, placeholder); p; p = strstr(p + placeholder_len, placeholder)) {
+        count++;
+    }
+
+    size_t len = strlen(token) + count * strlen(dir) + 1;
+    char *arg = malloc(len);
+    if (!arg) {
+        perror("Failed to expand launch arguments");
+        return NULL;
+    }
+
+    char *out = arg;
+    const char *p;
+    while ((p = strstr(token, placeholder))) {
+        memcpy(out, token, p - token);
+        out += p - token;
+        strcpy(out, dir);
+        out += strlen(dir);
+        token = p + placeholder_len;
+    }
+    strcpy(out, token);
+    return arg;
+}
+
+// 清单中C启动器需要的字段，布局见 manifest.rs
+struct manifest {
+    char *entry_name;
+    char *launch_args;
+    char *compression;
+    char *checksum_type;
+    char *checksum;
+    char *encryption;
+    // 以 / 分隔的附加数据文件名，旧版清单没有此字段
+    char *data_files;
+    // 解压目录名的前缀，旧版清单没有此字段
+    char *temp_prefix;
+    // --store-aligned 时每个文件内容在打包文件中的位置，旧版清单没有此字段
+    char *aligned_index;
+    // 主程序的架构，旧版清单没有此字段
+    char *arch;
+    // 主程序的工作目录，"temp" 为解压目录，旧版清单没有此字段（即运行时所在的目录）
+    char *workdir;
+    // --env 设置的环境变量，每行为 KEY=VALUE，旧版清单没有此字段
+    char *env;
+    // --notice 记录的声明文本，旧版清单没有此字段
+    char *notice;
+    // 旧版清单没有此字段，默认为 0755
+    mode_t entry_mode;
+    // 所有文件的未压缩总大小，旧版清单没有此字段时为 0
+    uint64_t unpacked_size;
+    // 把解压目录加到动态库搜索路径中，旧版清单没有此字段
+    int set_library_path;
+    uint64_t resource_offset;
+    uint64_t resource_len;
+};
+
+static const char *skip_ws(const char *p) {
+    while (*p == ' ' || *p == '\t' || *p == '\n' || *p == '\r') {
+        p++;
+    }
+    return p;
+}
+
+// 解析JSON字符串（p 指向起始引号），返回新分配的字符串，*end 指向结束引号之后
+static char *parse_json_string(const char *p, const char **end) {
+    if (*p != '"') {
+        return NULL;
+    }
+    p++;
+    // 转义展开后不会比原文更长
+    char *out = malloc(strlen(p) + 1);
+    if (!out) {
+        return NULL;
+    }
+    char *o = out;
+    while (*p && *p != '"') {
+        if (*p != '\\') {
+            *o++ = *p++;
+            continue;
+        }
+        p++;
+        switch (*p) {
+        case '"': *o++ = '"'; break;
+        case '\\': *o++ = '\\'; break;
+        case '/': *o++ = '/'; break;
+        case 'b': *o++ = '\b'; break;
+        case 'f': *o++ = '\f'; break;
+        case 'n': *o++ = '\n'; break;
+        case 'r': *o++ = '\r'; break;
+        case 't': *o++ = '\t'; break;
+        case 'u': {
+            // 仅支持基本多文种平面内的字符，按UTF-8写出
+            unsigned int cp = 0;
+            for (int i = 1; i <= 4; i++) {
+                char c = p[i];
+                int digit = (c >= '0' && c <= '9') ? c - '0'
+                    : (c >= 'a' && c <= 'f') ? c - 'a' + 10
+                    : (c >= 'A' && c <= 'F') ? c - 'A' + 10 : -1;
+                if (digit < 0) {
+                    free(out);
+                    return NULL;
+                }
+                cp = cp * 16 + digit;
+            }
+            if (cp == 0 || (cp >= 0xD800 && cp <= 0xDFFF)) {
+                free(out);
+                return NULL;
+            }
+            if (cp < 0x80) {
+                *o++ = cp;
+            } else if (cp < 0x800) {
+                *o++ = 0xC0 | (cp >> 6);
+                *o++ = 0x80 | (cp & 0x3F);
+            } else {
+                *o++ = 0xE0 | (cp >> 12);
+                *o++ = 0x80 | ((cp >> 6) & 0x3F);
+                *o++ = 0x80 | (cp & 0x3F);
+            }
+            p += 4;
+            break;
+        }
+        default:
+            free(out);
+            return NULL;
+        }
+        p++;
+    }
+    if (*p != '"') {
+        free(out);
+        return NULL;
+    }
+    *o = '\0';
+    *end = p + 1;
+    return out;
+}
+
+// 解析打包端生成的扁平JSON清单，只接受字符串、非负整数与布尔值
+static int parse_manifest(const char *json, struct manifest *m) {
+    memset(m, 0, sizeof(*m));
+    m->entry_mode = 0755;
+    int have_offset = 0, have_len = 0;
+    const char *p = skip_ws(json);
+    if (*p++ != '{') {
+        return -1;
+    }
+    for (p = skip_ws(p); *p != '}'; p = skip_ws(p)) {
+        char *key = parse_json_string(p, &p);
+        if (!key) {
+            return -1;
+        }
+        p = skip_ws(p);
+        if (*p++ != ':') {
+            free(key);
+            return -1;
+        }
+        p = skip_ws(p);
+
+        if (*p == '"') {
+            char *value = parse_json_string(p, &p);
+            if (!value) {
+                free(key);
+                return -1;
+            }
+            char **field = NULL;
+            if (strcmp(key, "entry_name") == 0) field = &m->entry_name;
+            else if (strcmp(key, "launch_args") == 0) field = &m->launch_args;
+            else if (strcmp(key, "compression") == 0) field = &m->compression;
+            else if (strcmp(key, "checksum_type") == 0) field = &m->checksum_type;
+            else if (strcmp(key, "checksum") == 0) field = &m->checksum;
+            else if (strcmp(key, "encryption") == 0) field = &m->encryption;
+            else if (strcmp(key, "data_files") == 0) field = &m->data_files;
+            else if (strcmp(key, "temp_prefix") == 0) field = &m->temp_prefix;
+            else if (strcmp(key, "aligned_index") == 0) field = &m->aligned_index;
+            else if (strcmp(key, "workdir") == 0) field = &m->workdir;
+            else if (strcmp(key, "arch") == 0) field = &m->arch;
+            else if (strcmp(key, "env") == 0) field = &m->env;
+            else if (strcmp(key, "notice") == 0) field = &m->notice;
+            if (field) {
+                free(*field);
+                *field = value;
+            } else {
+                free(value);
+            }
+        } else if (*p >= '0' && *p <= '9') {
+            char *end;
+            uint64_t value = strtoull(p, &end, 10);
+            p = end;
+            if (strcmp(key, "resource_offset") == 0) {
+                m->resource_offset = value;
+                have_offset = 1;
+            } else if (strcmp(key, "resource_len") == 0) {
+                m->resource_len = value;
+                have_len = 1;
+            } else if (strcmp(key, "entry_mode") == 0) {
+                m->entry_mode = (mode_t)(value & 0777);
+            } else if (strcmp(key, "unpacked_size") == 0) {
+                m->unpacked_size = value;
+            }
+        } else if (strncmp(p, "true", 4) == 0) {
+            p += 4;
+            if (strcmp(key, "set_library_path") == 0) {
+                m->set_library_path = 1;
+            }
+        } else if (strncmp(p, "false", 5) == 0) {
+            p += 5;
+        } else {
+            free(key);
+            return -1;
+        }
+        free(key);
+
+        p = skip_ws(p);
+        if (*p == ',') {
+            p++;
+        } else if (*p != '}') {
+            return -1;
+        }
+    }
+
+    if (!m->entry_name || !m->launch_args || !m->compression || !m->checksum_type
+        || !m->checksum || !have_offset || !have_len) {
+        return -1;
+    }
+    return 0;
+}
+
+// 将打包文件中的资源数据复制到解压目录，校验后解压，成功时返回0，否则返回退出码；关闭 exe_fd
+static int extract_resources(int exe_fd, const struct manifest *m, const char *temp_dir, off_t offset,
+                             off_t data_end) {
+    // 资源数据先复制到解压目录再解压，两者都需要放得下，避免解压到一半时失败
+    struct statvfs dir_fs;
+    uint64_t needed = m->unpacked_size + m->resource_len;
+    if (m->unpacked_size > 0 && statvfs(temp_dir, &dir_fs) == 0
+        && (uint64_t)dir_fs.f_bavail * dir_fs.f_frsize < needed) {
+        fprintf(stderr, "Not enough space in %s: need %llu bytes, have %llu bytes\n", temp_dir,
+                (unsigned long long)needed,
+                (unsigned long long)dir_fs.f_bavail * dir_fs.f_frsize);
         close(exe_fd);
-        return 1;
+        return EXIT_EXTRACT_FAILED;
     }
-    off_t file_size = st.st_size;
-    
-    uint64_t offset;
-    if (lseek(exe_fd, file_size - 8, SEEK_SET) == -1) {
-        perror("Failed to seek to offset");
+
+    // 加密的资源数据只能由Rust启动器解密
+    if (m->encryption && strcmp(m->encryption, "none") != 0) {
+        fprintf(stderr, "Unsupported encryption: %s\n", m->encryption);
         close(exe_fd);
-        return 1;
+        return EXIT_INTEGRITY_FAILED;
     }
-    if (read(exe_fd, &offset, 8) != 8) {
-        perror("Failed to read offset");
+
+    // C启动器仅支持crc32校验
+    char *hex_end;
+    uint32_t expected_crc = strtoul(m->checksum, &hex_end, 16);
+    if (strcmp(m->checksum_type, "crc32") != 0 || strlen(m->checksum) != 8 || *hex_end != '\0') {
+        fprintf(stderr, "Unsupported checksum type: %s\n", m->checksum_type);
         close(exe_fd);
-        return 1;
+        return EXIT_INTEGRITY_FAILED;
+    }
+
+    // 与清单中的压缩方式名称对应
+    const char *compress_flag;
+    if (strcmp(m->compression, "none") == 0) {
+        compress_flag = NULL;
+    } else if (strcmp(m->compression, "gzip") == 0) {
+        compress_flag = "-z";
+    } else if (strcmp(m->compression, "zstd") == 0) {
+        compress_flag = "--zstd";
+    } else {
+        fprintf(stderr, "Unknown compression type: %s\n", m->compression);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
     }
     
     if (lseek(exe_fd, offset, SEEK_SET) == -1) {
         perror("Failed to seek to resources");
         close(exe_fd);
-        return 1;
+        return EXIT_EXTRACT_FAILED;
     }
     
-    char resources_path[512];
-    snprintf(resources_path, sizeof(resources_path), "%s/resources.tar.gz", temp_dir);
+    char resources_path[PATH_MAX];
+    if (format_checked(resources_path, sizeof(resources_path), "%s/resources.pack", temp_dir) == -1) {
+        close(exe_fd);
+        return EXIT_EXTRACT_FAILED;
+    }
     
     int resources_fd = open(resources_path, O_CREAT | O_WRONLY, 0644);
     if (resources_fd == -1) {
         perror("Failed to create resources file");
         close(exe_fd);
-        return 1;
+        return EXIT_EXTRACT_FAILED;
     }
     
     char buffer[4096];
     ssize_t bytes_read;
-    off_t remaining = file_size - 8 - offset;
+    off_t remaining = data_end - offset;
+    uLong crc = crc32(0L, Z_NULL, 0);
     while (remaining > 0 && (bytes_read = read(exe_fd, buffer, sizeof(buffer))) > 0) {
         if (bytes_read > remaining) bytes_read = remaining;
+        crc = crc32(crc, (const Bytef *)buffer, bytes_read);
         write(resources_fd, buffer, bytes_read);
         remaining -= bytes_read;
     }
     
     close(exe_fd);
     close(resources_fd);
+
+    // 解压前校验资源完整性
+    if (remaining != 0 || (uint32_t)crc != expected_crc) {
+        fprintf(stderr, "Resource checksum mismatch: bundle is corrupted\n");
+        unlink(resources_path);
+        return EXIT_INTEGRITY_FAILED;
+    }
     
-    char extract_cmd[1024];
-    snprintf(extract_cmd, sizeof(extract_cmd), "cd '%s' && tar -xzf resources.tar.gz", temp_dir);
-    int result = system(extract_cmd);
-    if (result != 0) {
+    if (extract_archive(compress_flag, resources_path, temp_dir) == -1) {
         fprintf(stderr, "Failed to extract resources\n");
-        return 1;
+        return EXIT_EXTRACT_FAILED;
     }
     unlink(resources_path);
+    return 0;
+}
+
+int main(int argc, char *argv[]) {
+    if (argc > 1 && strcmp(argv[1], "--version") == 0) {
+        printf("sekaipack launcher %s (c, bundle format v%d)\n", SEKAIPACK_VERSION, FOOTER_VERSION);
+        return 0;
+    }
+    
+    char exe_path[PATH_MAX];
+    if (get_exe_path(exe_path, sizeof(exe_path)) == -1) {
+        perror("Failed to get executable path");
+        return EXIT_EXTRACT_FAILED;
+    }
     
-    char sekai_path[512];
-    snprintf(sekai_path, sizeof(sekai_path), "%s/sekai.x86_64", temp_dir);
+    // 解压到 $SEKAI_PACK_TMPDIR，其次为 $TMPDIR，都未设置时为 /tmp
+    const char *temp_root = getenv(TMPDIR_ENV);
+    if (!temp_root || temp_root[0] == '\0') {
+        temp_root = getenv("TMPDIR");
+    }
+    if (!temp_root || temp_root[0] == '\0') {
+        temp_root = "/tmp";
+    }
+    const char *run_dir_env = getenv(RUN_DIR_ENV);
+    int use_run_dir = run_dir_env && run_dir_env[0] != '\0';
+#ifdef __linux__
+    // 以 noexec 挂载的文件系统上无法执行解压出的主程序
+    struct statvfs temp_fs;
+    if (!use_run_dir && statvfs(temp_root, &temp_fs) == 0 && (temp_fs.f_flag & ST_NOEXEC)) {
+        fprintf(stderr, "%s is on a filesystem mounted noexec; set %s to another directory\n",
+                temp_root, TMPDIR_ENV);
+        return EXIT_EXTRACT_FAILED;
+    }
+#endif
+    // 由 --pack-launcher 的解压桩在内存中启动时，打包文件只能通过 /proc/self/exe 读取；
+    // 解压桩传入的原文件路径用于 SEKAI_PACK_BUNDLE，且不传给主程序。
+    // Linux上总是经由 /proc/self/exe 读取：它指向正在运行的文件本身，
+    // 打包文件在启动后被删除或被新版本替换时仍能读取
+    const char *packed_path = getenv(PACKED_ENV);
+    if (packed_path) {
+        if (format_checked(exe_path, sizeof(exe_path), "%s", packed_path) == -1) {
+            return EXIT_EXTRACT_FAILED;
+        }
+        unsetenv(PACKED_ENV);
+    }
+#ifdef __linux__
+    int exe_fd = open("/proc/self/exe", O_RDONLY);
+#else
+    int exe_fd = open(exe_path, O_RDONLY);
+    if (exe_fd == -1 && errno == ENOENT) {
+        fprintf(stderr, "Failed to open executable: the bundle was deleted or moved after it started\n");
+        return EXIT_EXTRACT_FAILED;
+    }
+#endif
+    if (exe_fd == -1) {
+        perror("Failed to open executable");
+        return EXIT_EXTRACT_FAILED;
+    }
+    
+    struct stat st;
+    if (fstat(exe_fd, &st) == -1) {
+        perror("Failed to get file size");
+        close(exe_fd);
+        return EXIT_EXTRACT_FAILED;
+    }
+    off_t file_size = st.st_size;
+    
+    // 尾部布局见 footer.rs：8字节清单偏移 + 8字节总长度 + 1字节版本 + 8字节魔数
+    unsigned char footer[FOOTER_LEN];
+    if (file_size < FOOTER_LEN || lseek(exe_fd, file_size - FOOTER_LEN, SEEK_SET) == -1) {
+        fprintf(stderr, "not a sekai-pack bundle\n");
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    if (read(exe_fd, footer, FOOTER_LEN) != FOOTER_LEN) {
+        perror("Failed to read footer");
+        close(exe_fd);
+        return EXIT_EXTRACT_FAILED;
+    }
+    // 先校验魔数，再信任其中的偏移
+    if (memcmp(footer + 17, FOOTER_MAGIC, 8) != 0) {
+        fprintf(stderr, "not a sekai-pack bundle\n");
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    if (footer[16] != FOOTER_VERSION) {
+        fprintf(stderr, "unsupported sekai-pack format version %d\n", footer[16]);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    uint64_t manifest_offset, bundle_len;
+    memcpy(&manifest_offset, footer, 8);
+    memcpy(&bundle_len, footer + 8, 8);
+    // 启动器位于打包数据的开头，记录的总长度即为文件长度
+    if (bundle_len != (uint64_t)file_size) {
+        fprintf(stderr, "Invalid bundle footer: bundle length %llu does not match the file size (%lld bytes); "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)bundle_len, (long long)file_size);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    off_t manifest_end = file_size - FOOTER_LEN;
+    // 清单不能为空，且偏移处应是清单的JSON对象，而不是启动器或资源数据（如偏移为0时）
+    if (manifest_offset >= (uint64_t)manifest_end) {
+        fprintf(stderr, "Invalid bundle footer: manifest offset %llu is past the end of the file (%lld bytes); "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)manifest_offset, (long long)file_size);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    char first;
+    if (pread(exe_fd, &first, 1, manifest_offset) != 1) {
+        perror("Failed to read manifest");
+        close(exe_fd);
+        return EXIT_EXTRACT_FAILED;
+    }
+    if (first != '{') {
+        fprintf(stderr, "Invalid bundle footer: manifest offset %llu does not point at the manifest; "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)manifest_offset);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    if (manifest_end - manifest_offset > MAX_MANIFEST_LEN) {
+        fprintf(stderr, "Invalid bundle manifest: manifest is too large (%lld bytes)\n",
+                (long long)(manifest_end - manifest_offset));
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+
+    // 读取位于资源数据与尾部之间的清单
+    size_t manifest_len = manifest_end - manifest_offset;
+    char *manifest_json = malloc(manifest_len + 1);
+    if (!manifest_json || lseek(exe_fd, manifest_offset, SEEK_SET) == -1
+        || read(exe_fd, manifest_json, manifest_len) != (ssize_t)manifest_len) {
+        perror("Failed to read manifest");
+        close(exe_fd);
+        return EXIT_EXTRACT_FAILED;
+    }
+    manifest_json[manifest_len] = '\0';
+    struct manifest manifest;
+    if (parse_manifest(manifest_json, &manifest) == -1) {
+        fprintf(stderr, "Invalid bundle manifest\n");
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    free(manifest_json);
+
+    uint64_t offset = manifest.resource_offset;
+    if (offset > manifest_offset || manifest.resource_len != manifest_offset - offset) {
+        fprintf(stderr, "Invalid bundle manifest: resource range does not match the manifest offset\n");
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    off_t data_end = manifest_offset;
+
+    // 只有打包时记录了声明文本才处理 --about，否则与其他参数一样传给主程序
+    if (argc > 1 && strcmp(argv[1], "--about") == 0 && manifest.notice && manifest.notice[0] != '\0') {
+        size_t notice_len = strlen(manifest.notice);
+        fputs(manifest.notice, stdout);
+        if (manifest.notice[notice_len - 1] != '\n') {
+            putchar('\n');
+        }
+        close(exe_fd);
+        return 0;
+    }
+
+    // 在解压之前检查，避免解压后执行时才因架构不符而失败
+    if (!arch_compatible(manifest.arch)) {
+        fprintf(stderr, "architecture mismatch: main executable is %s, launcher is %s\n",
+                manifest.arch, LAUNCHER_ARCH[0] != '\0' ? LAUNCHER_ARCH : "unknown");
+        close(exe_fd);
+        return EXIT_EXEC_FAILED;
+    }
+
+    // 主程序文件名只允许单个普通文件名
+    const char *entry_name = manifest.entry_name;
+    if (entry_name[0] == '\0' || strlen(entry_name) > NAME_MAX || strchr(entry_name, '/')
+        || strcmp(entry_name, ".") == 0 || strcmp(entry_name, "..") == 0) {
+        fprintf(stderr, "Invalid entry name '%s'\n", entry_name);
+        close(exe_fd);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    char *launch_args = manifest.launch_args;
+
+    // 设置了 SEKAI_PACK_RUN_DIR 时直接执行该目录中已有的文件，不解压也不改动其中的文件
+    char run_dir[PATH_MAX];
+    char *temp_dir;
+    if (use_run_dir) {
+        close(exe_fd);
+        struct stat entry_stat;
+        char entry_path[PATH_MAX];
+        if (!realpath(run_dir_env, run_dir)
+            || format_checked(entry_path, sizeof(entry_path), "%s/%s", run_dir, entry_name) == -1
+            || stat(entry_path, &entry_stat) == -1 || !S_ISREG(entry_stat.st_mode)) {
+            fprintf(stderr, "%s '%s' does not contain %s\n", RUN_DIR_ENV, run_dir_env, entry_name);
+            return EXIT_EXTRACT_FAILED;
+        }
+        temp_dir = run_dir;
+    } else {
+        // 解压目录以清单中记录的前缀命名，旧版清单没有此字段时为 bundled_app_
+        const char *temp_prefix = manifest.temp_prefix && manifest.temp_prefix[0] != '\0'
+            ? manifest.temp_prefix : DEFAULT_TEMP_PREFIX;
+        if (strchr(temp_prefix, '/')) {
+            fprintf(stderr, "Invalid bundle manifest: temp_prefix contains '/'\n");
+            close(exe_fd);
+            return EXIT_INTEGRITY_FAILED;
+        }
+        char temp_template[PATH_MAX];
+        if (format_checked(temp_template, sizeof(temp_template), "%s/%sXXXXXX", temp_root, temp_prefix) == -1) {
+            close(exe_fd);
+            return EXIT_EXTRACT_FAILED;
+        }
+        temp_dir = mkdtemp(temp_template);
+        if (!temp_dir) {
+            perror("Failed to create temp directory");
+            close(exe_fd);
+            return EXIT_EXTRACT_FAILED;
+        }
+        if (keep_temp()) {
+            fprintf(stderr, "Keeping extracted files in %s\n", temp_dir);
+        } else {
+            strcpy(cleanup_dir, temp_dir);
+            atexit(remove_temp_dir);
+        }
+    }
+
+    if (!use_run_dir) {
+        int code = extract_resources(exe_fd, &manifest, temp_dir, offset, data_end);
+        if (code != 0) {
+            return code;
+        }
+    }
+    
+    char sekai_path[PATH_MAX];
+    if (format_checked(sekai_path, sizeof(sekai_path), "%s/%s", temp_dir, entry_name) == -1) {
+        return EXIT_EXTRACT_FAILED;
+    }
+    if (!use_run_dir && chmod(sekai_path, manifest.entry_mode) == -1) {
+        fprintf(stderr, "Failed to set permissions on %s: %s\n", entry_name, strerror(errno));
+        return EXIT_EXTRACT_FAILED;
+    }
     //printf("%s", temp_dir);
     fflush(stdout);
 
-    int devnull = open("/dev/null", O_WRONLY);
-    dup2(devnull, STDOUT_FILENO);
-    dup2(devnull, STDERR_FILENO);
-    close(devnull);
+    // --env 设置的环境变量覆盖运行环境中的同名变量，打包时已保证每行都含有 =
+    for (char *line = manifest.env; line && *line != '\0';) {
+        char *end = strchr(line, '\n');
+        if (end) {
+            *end = '\0';
+        }
+        char *eq = strchr(line, '=');
+        if (eq) {
+            *eq = '\0';
+            if (setenv(line, eq + 1, 1) == -1) {
+                fprintf(stderr, "Failed to set %s: %s\n", line, strerror(errno));
+                return EXIT_EXTRACT_FAILED;
+            }
+        }
+        line = end ? end + 1 : NULL;
+    }
 
-    chmod(sekai_path, 0755);
-    
-    char *exec_args[argc + 4];
+    // 告知主程序附加数据文件解压后所在的目录
+    if (manifest.data_files && manifest.data_files[0] != '\0') {
+        char data_dir[PATH_MAX];
+        if (format_checked(data_dir, sizeof(data_dir), "%s/%s", temp_dir, DATA_DIR) == -1
+            || setenv(DATA_DIR_ENV, data_dir, 1) == -1) {
+            return EXIT_EXTRACT_FAILED;
+        }
+    }
+
+    // 解压目录加到动态库搜索路径的最前面，保留原有的路径；没有原有路径时不能留下空项（即当前目录）
+    if (manifest.set_library_path) {
+        const char *existing = getenv(LIBRARY_PATH_ENV);
+        if (existing && existing[0] != '\0') {
+            // 原有路径的长度不受限制，不能放进固定大小的缓冲区
+            size_t len = strlen(temp_dir) + strlen(existing) + 2;
+            char *library_path = malloc(len);
+            if (!library_path) {
+                return EXIT_EXTRACT_FAILED;
+            }
+            snprintf(library_path, len, "%s:%s", temp_dir, existing);
+            if (setenv(LIBRARY_PATH_ENV, library_path, 1) == -1) {
+                return EXIT_EXTRACT_FAILED;
+            }
+            free(library_path);
+        } else if (setenv(LIBRARY_PATH_ENV, temp_dir, 1) == -1) {
+            return EXIT_EXTRACT_FAILED;
+        }
+    }
+
+    // 对齐存放时写出文件索引，主程序可据此直接映射打包文件中的资源
+    if (!use_run_dir && manifest.aligned_index && manifest.aligned_index[0] != '\0') {
+        char index_path[PATH_MAX];
+        if (format_checked(index_path, sizeof(index_path), "%s/%s", temp_dir, ALIGNED_INDEX_FILE) == -1) {
+            return EXIT_EXTRACT_FAILED;
+        }
+        FILE *index = fopen(index_path, "w");
+        if (!index || fputs(manifest.aligned_index, index) == EOF || fclose(index) == EOF) {
+            fprintf(stderr, "Failed to write %s: %s\n", index_path, strerror(errno));
+            return EXIT_EXTRACT_FAILED;
+        }
+        if (setenv(ALIGNED_INDEX_ENV, index_path, 1) == -1 || setenv(BUNDLE_ENV, exe_path, 1) == -1) {
+            return EXIT_EXTRACT_FAILED;
+        }
+    }
+
+    // 按空白拆分启动参数模板，每一项中的 {dir} 替换为解压目录
+    char *exec_args[argc + strlen(launch_args) / 2 + 2];
     exec_args[0] = sekai_path;
-    exec_args[1] = "--path";
-    exec_args[2] = temp_dir;
-    
-    int j = 3;
+
+    int j = 1;
+    for (char *token = strtok(launch_args, " \t\n\r\f\v"); token; token = strtok(NULL, " \t\n\r\f\v")) {
+        exec_args[j] = expand_launch_arg(token, temp_dir);
+        if (!exec_args[j]) {
+            return EXIT_EXTRACT_FAILED;
+        }
+        j++;
+    }
     for (int i = 1; i < argc; i++) {
         if (strcmp(argv[i], "--version") != 0) {
             exec_args[j++] = argv[i];
         }
     }
     exec_args[j] = NULL;
-    
-    execv(sekai_path, exec_args);
-    
-    perror("Failed to execute main program");
-    
-    char cleanup_cmd[512];
-    snprintf(cleanup_cmd, sizeof(cleanup_cmd), "rm -rf '%s'", temp_dir);
-    system(cleanup_cmd);
-    
-    return 1;
+
+    // 只输出解压目录与将要执行的参数列表，不启动主程序
+    if (env_flag(PRINT_CMD_ENV)) {
+        printf("dir: %s\n", temp_dir);
+        for (int i = 0; exec_args[i]; i++) {
+            printf("argv[%d]: \"%s\"\n", i, exec_args[i]);
+        }
+        return 0;
+    }
+
+    // 连接终端时子进程留在启动器的进程组中，以免妨碍作业控制与读取终端；否则放入单独的进程组，
+    // 发给启动器所在进程组的信号只经由启动器到达子进程一次
+    int own_group = !isatty(STDIN_FILENO) && !isatty(STDOUT_FILENO) && !isatty(STDERR_FILENO);
+
+    // 处理函数在启动子进程之前安装，期间收到的信号先阻塞，记下子进程的pid之后再处理
+    sigset_t signals;
+    sigemptyset(&signals);
+    sigaddset(&signals, SIGINT);
+    sigaddset(&signals, SIGTERM);
+    sigprocmask(SIG_BLOCK, &signals, NULL);
+    struct sigaction action;
+    memset(&action, 0, sizeof(action));
+    action.sa_sigaction = forward_signal;
+    action.sa_flags = SA_SIGINFO | SA_RESTART;
+    sigemptyset(&action.sa_mask);
+    sigaction(SIGINT, &action, NULL);
+    sigaction(SIGTERM, &action, NULL);
+
+    // 启动子进程并等待其退出，以便之后清理临时目录
+    pid_t pid = fork();
+    if (pid == -1) {
+        perror("Failed to execute main program");
+        sigprocmask(SIG_UNBLOCK, &signals, NULL);
+        return EXIT_EXEC_FAILED;
+    }
+    if (pid == 0) {
+        // 子进程恢复默认的信号处理后再解除阻塞
+        signal(SIGINT, SIG_DFL);
+        signal(SIGTERM, SIG_DFL);
+        if (own_group) {
+            setpgid(0, 0);
+        }
+        sigprocmask(SIG_UNBLOCK, &signals, NULL);
+        // 标准输入、输出与错误直接继承，主程序可以交互并检测终端
+        if (manifest.workdir && strcmp(manifest.workdir, "temp") == 0 && chdir(temp_dir) == -1) {
+            fprintf(stderr, "Failed to change to %s: %s\n", temp_dir, strerror(errno));
+            _exit(EXIT_EXEC_FAILED);
+        }
+        execv(sekai_path, exec_args);
+
+        fprintf(stderr, "Failed to execute main program: %s\n", strerror(errno));
+        // 不执行 atexit 注册的清理，由父进程负责
+        _exit(EXIT_EXEC_FAILED);
+    }
+
+    // 父进程同样设置进程组，转发信号时子进程一定已经位于其中
+    if (own_group) {
+        setpgid(pid, pid);
+    }
+    child_pid = own_group ? -pid : pid;
+    sigprocmask(SIG_UNBLOCK, &signals, NULL);
+
+    int status;
+    while (waitpid(pid, &status, 0) == -1) {
+        if (errno != EINTR) {
+            perror("Failed to wait for main program");
+            return EXIT_EXEC_FAILED;
+        }
+    }
+
+    // 子进程被信号终止时按shell惯例返回 128 + 信号值
+    if (WIFSIGNALED(status)) {
+        return 128 + WTERMSIG(status);
+    }
+    return WEXITSTATUS(status);
 }
@@ -0,0 +1,355 @@
+// 启动器的生成：嵌入预编译的Rust启动器，或在打包时用gcc编译C启动器
+
+use std::fs;
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::BundleError;
+use crate::arch;
+use crate::footer;
+use crate::manifest::{Layout, Manifest};
+
+// 预编译的Rust启动器（由build.rs静态链接构建）
+const RUST_LAUNCHER: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/sekai-launcher"));
+
+// 构建时按 `SEKAIPACK_CROSS_TARGETS` 额外嵌入的其他平台的Rust启动器，未嵌入的为空
+const CROSS_LAUNCHERS: [(TargetOs, &[u8]); 3] = [
+    (
+        TargetOs::Linux,
+        include_bytes!(concat!(env!("OUT_DIR"), "/sekai-launcher-linux")),
+    ),
+    (
+        TargetOs::Windows,
+        include_bytes!(concat!(env!("OUT_DIR"), "/sekai-launcher-windows")),
+    ),
+    (
+        TargetOs::Macos,
+        include_bytes!(concat!(env!("OUT_DIR"), "/sekai-launcher-macos")),
+    ),
+];
+
+/// 打包时嵌入的启动器
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Launcher {
+    /// 编译时嵌入的静态链接Rust启动器
+    Rust,
+    /// 打包时用gcc编译的旧版C启动器
+    C,
+}
+
+impl Launcher {
+    pub fn parse(name: &str) -> Option<Launcher> {
+        match name {
+            "rust" => Some(Launcher::Rust),
+            "c" => Some(Launcher::C),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Launcher::Rust => "rust",
+            Launcher::C => "c",
+        }
+    }
+
+    fn id(self) -> &'static [u8] {
+        match self {
+            Launcher::Rust => footer::LAUNCHER_ID_RUST,
+            Launcher::C => footer::LAUNCHER_ID_C,
+        }
+    }
+}
+
+/// 打包文件运行的平台，决定嵌入哪个平台的Rust启动器
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TargetOs {
+    Linux,
+    Windows,
+    Macos,
+}
+
+impl TargetOs {
+    /// 运行sekaipack的平台，无法识别时为 `None`
+    pub const NATIVE: Option<TargetOs> = if cfg!(windows) {
+        Some(TargetOs::Windows)
+    } else if cfg!(target_os = "macos") {
+        Some(TargetOs::Macos)
+    } else if cfg!(target_os = "linux") {
+        Some(TargetOs::Linux)
+    } else {
+        None
+    };
+
+    pub fn parse(name: &str) -> Option<TargetOs> {
+        match name {
+            "linux" => Some(TargetOs::Linux),
+            "windows" => Some(TargetOs::Windows),
+            "macos" => Some(TargetOs::Macos),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TargetOs::Linux => "linux",
+            TargetOs::Windows => "windows",
+            TargetOs::Macos => "macos",
+        }
+    }
+
+    /// 该平台的可执行文件格式
+    pub fn format(self) -> arch::Format {
+        match self {
+            TargetOs::Linux => arch::Format::Elf,
+            TargetOs::Windows => arch::Format::Pe,
+            TargetOs::Macos => arch::Format::MachO,
+        }
+    }
+}
+
+// 为 `target_os` 嵌入的Rust启动器：运行sekaipack的平台使用自身的启动器，其他平台的须在构建时嵌入
+pub(crate) fn rust_launcher(target_os: Option<TargetOs>) -> Result<&'static [u8], BundleError> {
+    let Some(os) = target_os.filter(|&os| Some(os) != TargetOs::NATIVE) else {
+        return Ok(RUST_LAUNCHER);
+    };
+    match CROSS_LAUNCHERS.iter().find(|(cross, _)| *cross == os) {
+        Some((_, bytes)) if !bytes.is_empty() => Ok(bytes),
+        _ => Err(BundleError::InvalidOptions(format!(
+            "This build of sekaipack does not include a launcher for --target-os={}; \
+             rebuild it with SEKAIPACK_CROSS_TARGETS set to a {} target",
+            os.name(),
+            os.name()
+        ))),
+    }
+}
+
+// 读取之前生成的启动器以便重用：可以是单独的启动器，也可以是自解压打包文件（取资源数据之前的部分）；
+// 只接受支持当前格式版本的启动器，返回其类型与内容
+pub(crate) fn read_reusable_launcher(path: &Path) -> Result<(Launcher, Vec<u8>), BundleError> {
+    let read = || -> std::io::Result<Vec<u8>> {
+        let mut file = fs::File::open(path)?;
+        let len = match Manifest::read_from(&mut file) {
+            Ok(manifest) if manifest.layout == Layout::SelfExtract => manifest.resource_offset,
+            _ => u64::MAX,
+        };
+        file.rewind()?;
+        let mut bytes = Vec::new();
+        file.take(len).read_to_end(&mut bytes)?;
+        Ok(bytes)
+    };
+    let bytes = read().map_err(|e| {
+        BundleError::InvalidOptions(format!("Cannot read launcher '{}': {}", path.display(), e))
+    })?;
+
+    // 启动器必须是可执行文件（ELF、PE 或 Mach-O），例如tar包中虽然含有启动器却不能直接重用
+    let executable = [
+        &b"\x7fELF"[..],
+        b"MZ",
+        b"\xcf\xfa\xed\xfe",
+        b"\xca\xfe\xba\xbe",
+    ]
+    .iter()
+    .any(|magic| bytes.starts_with(magic));
+    let kind = [Launcher::Rust, Launcher::C]
+        .into_iter()
+        .filter(|_| executable)
+        .find(|kind| bytes.windows(kind.id().len()).any(|w| w == kind.id()));
+    match kind {
+        Some(kind) => Ok((kind, bytes)),
+        None => Err(BundleError::InvalidOptions(format!(
+            "'{}' is not a launcher built for this version of sekai-pack",
+            path.display()
+        ))),
+    }
+}
+
+pub(crate) fn embed_rust_launcher<W: Write>(
+    writer: &mut W,
+    target_os: Option<TargetOs>,
+) -> Result<(), BundleError> {
+    info!("Embedding Rust launcher...");
+    writer.write_all(rust_launcher(target_os)?)?;
+    Ok(())
+}
+
+// 启动gcc时遇到暂时性错误的最多尝试次数，以及第一次重试前的等待时间（之后每次加倍）
+const GCC_SPAWN_ATTEMPTS: u32 = 3;
+const GCC_SPAWN_BACKOFF: Duration = Duration::from_millis(200);
+
+// 运行gcc，进程因资源暂时不足（EAGAIN、ENOMEM）无法启动时稍后重试；
+// gcc已启动后的编译失败由调用方根据退出状态处理，不会重试
+fn run_gcc<T>(mut run: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut attempt = 1;
+    let mut delay = GCC_SPAWN_BACKOFF;
+    loop {
+        match run() {
+            Err(e) if attempt < GCC_SPAWN_ATTEMPTS && is_transient_spawn_error(&e) => {
+                warn!(
+                    "Failed to start gcc ({}), retrying in {} ms ({}/{})",
+                    e,
+                    delay.as_millis(),
+                    attempt,
+                    GCC_SPAWN_ATTEMPTS - 1
+                );
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient_spawn_error(e: &io::Error) -> bool {
+    matches!(
+        e.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::OutOfMemory | io::ErrorKind::Interrupted
+    )
+}
+
+// 在创建任何文件之前确认gcc可用
+pub(crate) fn check_c_compiler() -> Result<(), BundleError> {
+    let status = run_gcc(|| {
+        Command::new("gcc")
+            .arg("--version")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+    });
+    match status {
+        Ok(status) if status.success() => Ok(()),
+        // 重试后仍无法启动时如实报告，而不是误报为找不到gcc
+        Err(e) if is_transient_spawn_error(&e) => Err(BundleError::CompileFailed(format!(
+            "failed to run gcc: {}",
+            e
+        ))),
+        _ => Err(BundleError::CompilerNotFound),
+    }
+}
+
+// 静态链接需要的静态库，gcc找不到时 `-print-file-name` 原样输出文件名
+fn missing_static_libs() -> Vec<&'static str> {
+    ["libz.a", "libc.a"]
+        .into_iter()
+        .filter(|lib| {
+            let output = run_gcc(|| {
+                Command::new("gcc")
+                    .arg(format!("-print-file-name={}", lib))
+                    .stderr(Stdio::null())
+                    .output()
+            });
+            match output {
+                Ok(output) => {
+                    !Path::new(String::from_utf8_lossy(&output.stdout).trim()).is_absolute()
+                }
+                Err(_) => true,
+            }
+        })
+        .collect()
+}
+
+// `static_link` 时以 `-static` 链接，生成不依赖系统libz.so的启动器；
+// 缺少所需的静态库时给出警告并照常动态链接。`keep_temps` 时gcc的中间文件保留在 `temp_dir` 中
+pub(crate) fn compile_c_launcher(
+    temp_dir: &Path,
+    output_file: &Path,
+    static_link: bool,
+    keep_temps: bool,
+) -> Result<(), BundleError> {
+    // 启动器源码（编译时嵌入）
+    const LAUNCHER_C: &str = include_str!("launcher.c");
+    // `--version` 输出打包工具的版本
+    let version = format!("-DSEKAIPACK_VERSION=\"{}\"", env!("CARGO_PKG_VERSION"));
+
+    compile_c(
+        temp_dir,
+        "launcher",
+        LAUNCHER_C,
+        &[&version],
+        output_file,
+        static_link,
+        keep_temps,
+    )
+}
+
+// 编译 `--pack-launcher` 的解压桩，按体积优化并去除符号
+pub(crate) fn compile_stub(
+    temp_dir: &Path,
+    output_file: &Path,
+    static_link: bool,
+    keep_temps: bool,
+) -> Result<(), BundleError> {
+    const STUB_C: &str = include_str!("stub.c");
+
+    compile_c(
+        temp_dir,
+        "stub",
+        STUB_C,
+        &["-Os", "-s"],
+        output_file,
+        static_link,
+        keep_temps,
+    )
+}
+
+fn compile_c(
+    temp_dir: &Path,
+    name: &str,
+    source: &str,
+    flags: &[&str],
+    output_file: &Path,
+    static_link: bool,
+    keep_temps: bool,
+) -> Result<(), BundleError> {
+    let source_file = temp_dir.join(format!("{}.c", name));
+    fs::write(&source_file, source)?;
+
+    let static_link = static_link && {
+        let missing = missing_static_libs();
+        if !missing.is_empty() {
+            warn!(
+                "Static {} not found, linking the {} dynamically",
+                missing.join(" and "),
+                name
+            );
+        }
+        missing.is_empty()
+    };
+
+    info!(
+        "Compiling {}{}...",
+        name,
+        if static_link { " (static)" } else { "" }
+    );
+    let output = run_gcc(|| {
+        let mut gcc = Command::new("gcc");
+        gcc.arg("-o").arg(output_file).arg(&source_file);
+        gcc.args(flags);
+        if static_link {
+            gcc.arg("-static");
+        }
+        // 预处理后的源码、汇编与目标文件写在输出文件旁
+        if keep_temps {
+            gcc.arg("-save-temps=obj");
+        }
+        gcc.arg("-lz").output()
+    })
+    .map_err(|e| BundleError::CompileFailed(format!("failed to run gcc: {}", e)))?;
+
+    if !output.status.success() {
+        return Err(BundleError::CompileFailed(
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,2070 @@
+//! sekaipack：将 sekai 主程序与资源目录打包为单个可执行文件
+
+use std::collections::HashSet;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use log::{debug, info, warn};
+
+pub mod arch;
+pub mod checksum;
+pub mod compression;
+pub mod config;
+pub mod encryption;
+pub mod footer;
+pub mod manifest;
+pub mod unpack;
+
+mod archive;
+mod build_cache;
+mod error;
+mod inspect;
+mod launcher;
+mod split;
+mod update;
+
+pub use checksum::{CacheHash, Checksum};
+pub use compression::Compression;
+pub use config::Config;
+pub use encryption::Encryption;
+pub use error::BundleError;
+pub use inspect::{
+    Bundle, BundleEntry, VerifyReport, extract, list, manifest, open_bundle, resource_checksum,
+    verify,
+};
+pub use launcher::{Launcher, TargetOs};
+pub use manifest::{Layout, Manifest, WorkDir};
+pub use split::{join, part_path};
+pub use update::update;
+
+/// 可重现打包时作为打包时间与修改时间的环境变量（Unix 时间戳）
+pub const SOURCE_DATE_EPOCH_ENV: &str = "SOURCE_DATE_EPOCH";
+
+/// 打包选项
+#[derive(Clone, Debug)]
+pub struct BundleOptions {
+    /// 嵌入的启动器
+    pub launcher: Launcher,
+    /// 打包文件运行的平台，`None` 时为运行sekaipack的平台；其他平台的启动器须在构建sekaipack时嵌入
+    pub target_os: Option<TargetOs>,
+    /// 资源压缩方式
+    pub compression: Compression,
+    /// 压缩等级，`None` 时使用压缩方式的默认等级
+    pub level: Option<u32>,
+    /// zstd 压缩使用的线程数，`None` 时使用可用的CPU数
+    pub threads: Option<u32>,
+    /// 单独压缩可执行文件所用的压缩方式，`None` 时可执行文件与资源一起压缩
+    pub exe_compression: Option<Compression>,
+    /// 资源校验和算法
+    pub checksum: Checksum,
+    /// 在清单中额外记录每个文件的校验和，`verify` 据此指出损坏的文件
+    pub entry_checksums: bool,
+    /// 解压到持久缓存目录并在多次启动间复用
+    pub cache: bool,
+    /// 启动器缓存目录名中资源哈希的算法
+    pub cache_hash: CacheHash,
+    /// 打包时跳过的文件glob规则
+    pub exclude: Vec<String>,
+    /// 主程序在包内的文件名，`None` 时使用主程序自身的文件名
+    pub entry_name: Option<String>,
+    /// 与主程序一起放在解压目录根部的其他可执行文件，以各自的文件名保存
+    pub extra_exes: Vec<PathBuf>,
+    /// 附加的数据文件（如授权文件、构建信息），以各自的文件名放在资源包的 `.sekai-pack-data` 目录中
+    pub append_data: Vec<PathBuf>,
+    /// 启动器执行的可执行文件（包内文件名），`None` 时执行主程序
+    pub entry: Option<String>,
+    /// 启动器传给主程序的参数模板（以空白分隔，`{dir}` 替换为解压目录），`None` 时不传任何参数
+    pub launch_arg_template: Option<String>,
+    /// 解压后、启动主程序前在解压目录中执行的shell命令，`None` 时不执行
+    pub pre_launch: Option<String>,
+    /// 启动器为主程序设置的环境变量，覆盖运行环境中的同名变量，同名时后面的优先
+    pub env: Vec<(String, String)>,
+    /// 以 `--about` 运行打包文件时启动器输出的声明文本（如NOTICE文件的内容），`None` 时不记录
+    pub notice: Option<String>,
+    /// 启动器临时解压目录名的前缀，`None` 时为输出文件名加 `_`
+    pub temp_prefix: Option<String>,
+    /// 不压缩资源，并使每个文件的内容在打包文件中按4KB对齐，偏移记录在清单中，供主程序直接映射
+    pub store_aligned: bool,
+    /// 以 `-static` 编译C启动器，使其不依赖运行环境中的libz.so
+    pub static_launcher: bool,
+    /// 打包缓存目录：压缩的资源按块以内容哈希为键缓存，之后的打包中未改动的块不再重新压缩，`None` 时不使用
+    pub cache_dir: Option<PathBuf>,
+    /// 只列出将要打包的文件与总大小，不编译启动器也不生成输出文件
+    pub dry_run: bool,
+    /// 不检查主程序是否为启动器所在平台的可执行文件或脚本
+    pub no_exe_check: bool,
+    /// 保留编译C启动器或解压桩的临时目录（含源码与gcc的中间文件），供排查编译问题
+    pub keep_build: bool,
+    /// 覆盖已存在的输出文件，否则在开始打包前报错
+    pub force: bool,
+    /// 以此口令加密资源数据，`None` 时不加密
+    pub passphrase: Option<String>,
+    /// 跟随资源目录中的符号链接并保存其目标的内容，否则以链接条目原样保存
+    pub follow_symlinks: bool,
+    /// 内容与权限都相同的资源文件只保存一份，其余记录在清单中，解压时以硬链接（或复制）重建
+    pub dedup: bool,
+    /// 跳过不存在的资源路径（给出警告），否则在打包前报错
+    pub ignore_missing: bool,
+    /// 输出超过此大小（字节）时拆分为 `<输出>.000`、`<输出>.001`…… 与索引 `<输出>.index`
+    pub split_size: Option<u64>,
+    /// 资源不超过运行环境的内存预算时，启动器优先解压到内存文件系统（Linux 上的 /dev/shm）
+    pub prefer_memory: bool,
+    /// 启动器把解压目录加到主程序的动态库搜索路径中，使其能加载放在同一目录中的动态库
+    pub set_library_path: bool,
+    /// 输出自解压可执行文件，或启动器、资源数据与清单各自独立的tar包
+    pub format: Layout,
+    /// 将整个自解压文件（含启动器）再以gzip压缩，前面加上在内存中解压并执行它的解压桩（仅Linux）
+    pub pack_launcher: bool,
+    /// 不写入启动器，只输出 `[资源数据][清单][尾部]`，供自行读取尾部的宿主程序附加在其后
+    pub no_launcher: bool,
+    /// 可重现打包：相同的输入总是生成逐字节相同的输出，tar头中不记录属主与实际修改时间
+    pub reproducible: bool,
+    /// 打包前用 `strip` 去除主程序副本中的符号，原文件不受影响
+    pub strip: bool,
+    /// 不为自解压输出文件设置可执行权限（保持0644），供之后的打包流程自行设置
+    pub no_exec_bit: bool,
+    /// 启动器运行主程序时的工作目录
+    pub workdir: WorkDir,
+    /// 启动器解压后为执行的可执行文件设置的权限
+    pub entry_mode: u32,
+    /// 重用之前生成的启动器（单独的启动器或自解压打包文件），不再嵌入或编译新的启动器
+    pub reuse_launcher: Option<PathBuf>,
+}
+
+// 校验并解析后的打包参数
+pub(crate) struct BundlePlan<'a> {
+    // 所有可执行文件的来源与包内文件名，第一个为主程序
+    pub(crate) executables: Vec<(archive::Source<'a>, String)>,
+    // 记录在清单中的临时解压目录前缀
+    pub(crate) temp_prefix: String,
+    // 附加数据文件的源路径与文件名
+    pub(crate) data_files: Vec<(&'a Path, String)>,
+    // 启动器执行的可执行文件
+    pub(crate) entry_name: String,
+    // 主程序的架构，无法识别时为空
+    pub(crate) arch: String,
+    pub(crate) resource_dirs: &'a [PathBuf],
+    // `bundle_to_vec` 传入的资源文件：包内路径与内容，位于所有资源目录之后
+    pub(crate) memory_resources: Vec<(PathBuf, &'a [u8])>,
+    // 输出文件，`bundle_to_vec` 时为 `None`
+    pub(crate) output: Option<&'a Path>,
+    pub(crate) opts: &'a BundleOptions,
+    pub(crate) level: u32,
+    // 单独压缩可执行文件时使用的压缩等级
+    pub(crate) exe_level: u32,
+    pub(crate) threads: u32,
+    pub(crate) exclude: GlobSet,
+    // 记录在清单中的打包时间，可重现打包时同时作为所有条目的修改时间
+    pub(crate) created_at: u64,
+    // 重用的启动器内容
+    pub(crate) reused_launcher: Option<Vec<u8>>,
+    // 正在写入的临时文件，完成后重命名为输出文件
+    pub(crate) staging: Option<PathBuf>,
+    // `--dedup` 时不写入资源包的重复文件与其原文件的包内路径，开始写入前确定
+    pub(crate) duplicates: Vec<(PathBuf, PathBuf)>,
+}
+
+impl Default for BundleOptions {
+    fn default() -> Self {
+        BundleOptions {
+            launcher: Launcher::Rust,
+            target_os: None,
+            compression: Compression::Gzip,
+            level: None,
+            threads: None,
+            exe_compression: None,
+            checksum: Checksum::Crc32,
+            entry_checksums: false,
+            cache: false,
+            cache_hash: CacheHash::Blake3,
+            exclude: Vec::new(),
+            entry_name: None,
+            extra_exes: Vec::new(),
+            append_data: Vec::new(),
+            entry: None,
+            launch_arg_template: None,
+            pre_launch: None,
+            env: Vec::new(),
+            notice: None,
+            temp_prefix: None,
+            static_launcher: false,
+            store_aligned: false,
+            cache_dir: None,
+            dry_run: false,
+            keep_build: false,
+            no_exe_check: false,
+            force: false,
+            passphrase: None,
+            follow_symlinks: false,
+            dedup: false,
+            ignore_missing: false,
+            prefer_memory: false,
+            set_library_path: false,
+            split_size: None,
+            format: Layout::SelfExtract,
+            pack_launcher: false,
+            no_launcher: false,
+            reproducible: false,
+            strip: false,
+            no_exec_bit: false,
+            workdir: WorkDir::Temp,
+            entry_mode: manifest::DEFAULT_ENTRY_MODE,
+            reuse_launcher: None,
+        }
+    }
+}
+
+/// 打包进度：已加入资源包的文件数与未压缩字节数，以及各自的总数
+#[derive(Clone, Copy, Debug)]
+pub struct Progress {
+    pub files: usize,
+    pub total_files: usize,
+    pub bytes: u64,
+    pub total_bytes: u64,
+}
+
+impl Progress {
+    pub(crate) fn add_file(&mut self, size: u64) {
+        self.files += 1;
+        self.bytes += size;
+    }
+}
+
+/// 将主程序与资源目录打包为 `output`
+pub fn bundle(
+    main_exe: &Path,
+    resources: &[PathBuf],
+    output: &Path,
+    opts: BundleOptions,
+) -> Result<(), BundleError> {
+    bundle_with_progress(main_exe, resources, output, opts, &mut |_| {})
+}
+
+/// 与 [`bundle`] 相同，每向资源包加入一个文件（包括主程序）后调用一次 `progress`
+pub fn bundle_with_progress(
+    main_exe: &Path,
+    resources: &[PathBuf],
+    output: &Path,
+    opts: BundleOptions,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), BundleError> {
+    let (level, threads, exe_level) = check_options(&opts)?;
+
+    let exclude = build_exclude_set(&opts.exclude)?;
+    let executables = resolve_executables(main_exe, &opts)?;
+    let entry_name = match &opts.entry {
+        Some(entry) => {
+            if !executables.iter().any(|(_, name)| name == entry) {
+                return Err(BundleError::InvalidOptions(format!(
+                    "--entry '{}' does not name a bundled executable",
+                    entry
+                )));
+            }
+            entry.clone()
+        }
+        None => executables[0].1.clone(),
+    };
+
+    // 检查可执行文件是否存在
+    if !main_exe.exists() {
+        return Err(BundleError::MainExeNotFound(main_exe.to_path_buf()));
+    }
+    for exe in &opts.extra_exes {
+        if !exe.is_file() {
+            return Err(BundleError::ExeNotFound(exe.clone()));
+        }
+    }
+    let data_files = resolve_data_files(&opts)?;
+    let temp_prefix = resolve_temp_prefix(&opts, Some(output))?;
+
+    // 一次列出所有不存在（或既不是文件也不是目录）的资源路径
+    let missing: Vec<PathBuf> = resources
+        .iter()
+        .filter(|path| !path.is_file() && !path.is_dir())
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        if !opts.ignore_missing {
+            return Err(BundleError::InvalidResources(missing));
+        }
+        for path in &missing {
+            warn!("Skipping missing resource {}", path.display());
+        }
+    }
+
+    // 不覆盖已有的输出文件（拆分输出时为第一部分），除非指定了 --force
+    if !opts.force && !opts.dry_run {
+        let mut targets = vec![output.to_path_buf()];
+        if opts.split_size.is_some() {
+            targets.push(split::part_path(output, 0));
+        }
+        if let Some(path) = targets.iter().find(|path| path.exists()) {
+            return Err(BundleError::InvalidOptions(format!(
+                "Output '{}' exists; use --force to overwrite it",
+                path.display()
+            )));
+        }
+    }
+
+    // 资源文件与资源目录同可执行文件一样位于资源包根部，名称不能重复
+    let mut root_names: Vec<OsString> = executables
+        .iter()
+        .map(|(_, name)| OsString::from(name))
+        .collect();
+    for path in resources {
+        let (kind, name) = if path.is_file() {
+            ("file", path.file_name().map(|name| name.to_owned()))
+        } else if path.is_dir() {
+            // 与打包时相同，`.` 等没有文件名的路径取其实际目录名
+            let name = match path.file_name() {
+                Some(name) => Some(name.to_owned()),
+                None => path.canonicalize()?.file_name().map(|name| name.to_owned()),
+            };
+            ("directory", name)
+        } else {
+            continue;
+        };
+        let Some(name) = name else {
+            continue;
+        };
+        if root_names.contains(&name) {
+            return Err(BundleError::InvalidOptions(format!(
+                "Resource {} '{}' has the same name as another top-level entry '{}'",
+                kind,
+                path.display(),
+                name.to_string_lossy()
+            )));
+        }
+        root_names.push(name);
+    }
+    // 附加数据文件所在的目录名保留给 --append-data
+    if !data_files.is_empty()
+        && let Some(dir) = resources
+            .iter()
+            .find(|path| path.file_name() == Some(manifest::DATA_DIR.as_ref()))
+    {
+        return Err(BundleError::InvalidOptions(format!(
+            "Resource '{}' uses the name reserved for --append-data",
+            dir.display()
+        )));
+    }
+
+    // 输出文件以截断方式打开，不能指向任何输入的可执行文件
+    if let Ok(output) = output.canonicalize() {
+        for (source, _) in &executables {
+            if source.canonicalize()? == output {
+                return Err(BundleError::InvalidOptions(format!(
+                    "Output '{}' would overwrite the executable '{}'",
+                    output.display(),
+                    source.display()
+                )));
+            }
+        }
+    }
+
+    // 先读入内存，重用的启动器可以就是将被覆盖的输出文件
+    let reused_launcher = read_reused_launcher(&opts)?;
+    let target_launcher = target_launcher(&opts, reused_launcher.as_deref())?;
+
+    // 在临时目录中的副本上去除符号，函数返回时删除
+    let strip_dir;
+    let stripped;
+    let mut executables = executables;
+    if opts.strip && !opts.dry_run {
+        strip_dir = tempfile::Builder::new()
+            .prefix("sekaipack_strip_")
+            .tempdir()?;
+        if let Some(path) = strip_executable(main_exe, strip_dir.path())? {
+            stripped = path;
+            executables[0].0 = &stripped;
+        }
+    }
+
+    let header = read_header(executables[0].0)?;
+    // 没有启动器时由宿主程序决定如何执行主程序，不检查其格式与架构
+    if !opts.no_exe_check && !opts.no_launcher {
+        check_format(executables[0].0, &header, target_launcher)?;
+    }
+    let main_arch = arch::detect(&header);
+    if !opts.no_launcher {
+        check_arch(main_arch, target_launcher)?;
+    }
+    let created_at = created_at(&opts)?;
+
+    let plan = BundlePlan {
+        executables: executables
+            .into_iter()
+            .map(|(source, name)| (archive::Source::File(source), name))
+            .collect(),
+        temp_prefix,
+        data_files,
+        entry_name,
+        arch: main_arch.unwrap_or_default().to_string(),
+        resource_dirs: resources,
+        memory_resources: Vec::new(),
+        output: Some(output),
+        opts: &opts,
+        level,
+        exe_level,
+        threads,
+        exclude,
+        created_at,
+        reused_launcher,
+        staging: None,
+        duplicates: Vec::new(),
+    };
+    create_bundled_app(plan, output, progress)
+}
+
+/// 与 [`bundle`] 相同，但主程序与资源文件（包内路径与内容）直接从内存传入，返回生成的自解压文件。
+/// 除编译C启动器或解压桩外不读写文件系统；主程序在包内的文件名为 `entry_name`
+/// （默认为 `main`，打包给 Windows 时为 `main.exe`），
+/// 依赖磁盘上文件的选项（`extra_exes`、`append_data`、`strip`、`split_size` 与 `dry_run`）
+/// 以及 `format` 为tar包时报错
+pub fn bundle_to_vec(
+    main_exe: &[u8],
+    entries: &[(String, Vec<u8>)],
+    opts: BundleOptions,
+) -> Result<Vec<u8>, BundleError> {
+    let (level, threads, exe_level) = check_options(&opts)?;
+    let conflict = if !opts.extra_exes.is_empty() {
+        Some("--exe")
+    } else if !opts.append_data.is_empty() {
+        Some("--append-data")
+    } else if opts.strip {
+        Some("--strip")
+    } else if opts.split_size.is_some() {
+        Some("--split-size")
+    } else if opts.dry_run {
+        Some("--dry-run")
+    } else if opts.format != Layout::SelfExtract {
+        Some("--format=tarball")
+    } else {
+        None
+    };
+    if let Some(option) = conflict {
+        return Err(BundleError::InvalidOptions(format!(
+            "{} cannot be used when bundling in memory",
+            option
+        )));
+    }
+
+    let exclude = build_exclude_set(&opts.exclude)?;
+    let entry_name = match &opts.entry_name {
+        Some(name) => name.clone(),
+        None => default_entry_name("main".to_string(), &opts),
+    };
+    if !manifest::is_valid_entry_name(&entry_name) {
+        return Err(BundleError::InvalidEntryName(entry_name));
+    }
+    if let Some(entry) = &opts.entry
+        && *entry != entry_name
+    {
+        return Err(BundleError::InvalidOptions(format!(
+            "--entry '{}' does not name a bundled executable",
+            entry
+        )));
+    }
+
+    // 资源路径必须是相对路径；同一路径不能既是文件又是目录，根部的文件也不能与主程序同名
+    let mut files: HashSet<PathBuf> = HashSet::from([PathBuf::from(&entry_name)]);
+    let mut dirs: HashSet<PathBuf> = HashSet::new();
+    let mut memory_resources = Vec::new();
+    for (name, data) in entries {
+        let path = PathBuf::from(name);
+        if name.is_empty() || !path.components().all(|c| matches!(c, Component::Normal(_))) {
+            return Err(BundleError::InvalidOptions(format!(
+                "Invalid resource path '{}': it must be relative and contain no '.' or '..'",
+                name
+            )));
+        }
+        if exclude.is_match(&path) || path.file_name().is_some_and(|name| exclude.is_match(name)) {
+            debug!("Excluding {}", name);
+            continue;
+        }
+        let parents: Vec<&Path> = path
+            .ancestors()
+            .skip(1)
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .collect();
+        if dirs.contains(&path)
+            || files.contains(&path)
+            || parents.iter().any(|dir| files.contains(*dir))
+        {
+            return Err(BundleError::InvalidOptions(format!(
+                "Resource '{}' conflicts with another entry of the same path",
+                name
+            )));
+        }
+        dirs.extend(parents.into_iter().map(Path::to_path_buf));
+        files.insert(path.clone());
+        memory_resources.push((path, &data[..]));
+    }
+
+    let header = &main_exe[..main_exe.len().min(4096)];
+    let reused_launcher = read_reused_launcher(&opts)?;
+    let target_launcher = target_launcher(&opts, reused_launcher.as_deref())?;
+    if !opts.no_exe_check && !opts.no_launcher {
+        check_format(Path::new(&entry_name), header, target_launcher)?;
+    }
+    let main_arch = arch::detect(header);
+    if !opts.no_launcher {
+        check_arch(main_arch, target_launcher)?;
+    }
+
+    let mut plan = BundlePlan {
+        executables: vec![(archive::Source::Memory(main_exe), entry_name.clone())],
+        temp_prefix: resolve_temp_prefix(&opts, None)?,
+        data_files: Vec::new(),
+        entry_name,
+        arch: main_arch.unwrap_or_default().to_string(),
+        resource_dirs: &[],
+        memory_resources,
+        output: None,
+        opts: &opts,
+        level,
+        exe_level,
+        threads,
+        exclude,
+        created_at: created_at(&opts)?,
+        reused_launcher,
+        staging: None,
+        duplicates: Vec::new(),
+    };
+
+    let started = Instant::now();
+    let mut stats = BuildStats::default();
+    check_compiler(&plan)?;
+    if plan.opts.store_aligned || plan.opts.entry_checksums {
+        archive::check_indexed_paths(&plan).map_err(archive::archive_error)?;
+    }
+    if plan.opts.dedup {
+        plan.duplicates = archive::find_duplicates(&plan).map_err(archive::archive_error)?;
+    }
+    let mut output = io::Cursor::new(Vec::new());
+    if opts.pack_launcher {
+        let mut inner = io::Cursor::new(Vec::new());
+        write_bundle(&mut inner, &plan, &mut stats, &mut |_| {})?;
+        write_packed(&mut output, &mut inner, &plan, &mut stats)?;
+    } else {
+        write_bundle(&mut output, &plan, &mut stats, &mut |_| {})?;
+    }
+    let output = output.into_inner();
+
+    stats.output_len = output.len() as u64;
+    stats.total_time = started.elapsed();
+    stats.report();
+    Ok(output)
+}
+
+// 校验与文件系统无关的打包选项，返回资源的压缩等级、zstd线程数与单独压缩可执行文件时的压缩等级
+fn check_options(opts: &BundleOptions) -> Result<(u32, u32, u32), BundleError> {
+    // 校验压缩等级，在开始任何工作前报错
+    let level = match opts.level {
+        Some(level) => check_level(opts.compression, level)?,
+        None => opts.compression.default_level(),
+    };
+    let threads = match opts.threads {
+        Some(_) if opts.compression != Compression::Zstd => {
+            return Err(BundleError::InvalidOptions(format!(
+                "--threads cannot be used with {} compression",
+                opts.compression.name()
+            )));
+        }
+        Some(0) => {
+            return Err(BundleError::InvalidOptions(
+                "--threads must be at least 1".to_string(),
+            ));
+        }
+        Some(threads) => threads,
+        None => std::thread::available_parallelism().map_or(1, |n| n.get() as u32),
+    };
+    // 与资源使用相同的压缩方式时沿用 --level，否则使用该压缩方式的默认等级
+    let exe_level = match opts.exe_compression {
+        Some(compression) if compression == opts.compression => level,
+        Some(compression) => compression.default_level(),
+        None => 0,
+    };
+
+    // C启动器依赖 gcc 与 unix 进程模型，仅支持 Linux 与 macOS
+    if opts.launcher == Launcher::C && !cfg!(any(target_os = "linux", target_os = "macos")) {
+        return Err(BundleError::InvalidOptions(
+            "--launcher=c is only supported on Linux and macOS".to_string(),
+        ));
+    }
+    // C启动器与解压桩由本机的gcc编译，只能运行在本机的平台上
+    let cross = target_os(opts) != TargetOs::NATIVE;
+    if cross && opts.launcher == Launcher::C && opts.reuse_launcher.is_none() {
+        return Err(BundleError::InvalidOptions(
+            "--target-os for another platform requires --launcher=rust".to_string(),
+        ));
+    }
+    if cross && opts.pack_launcher {
+        return Err(BundleError::InvalidOptions(
+            "--pack-launcher cannot be combined with --target-os for another platform".to_string(),
+        ));
+    }
+    if target_os(opts) == Some(TargetOs::Windows) && opts.entry_mode != manifest::DEFAULT_ENTRY_MODE
+    {
+        warn!("--entry-mode has no effect on Windows");
+    }
+    // C启动器只能借助zlib校验crc32
+    if opts.launcher == Launcher::C && opts.checksum != Checksum::Crc32 {
+        return Err(BundleError::InvalidOptions(format!(
+            "--checksum={} requires --launcher=rust",
+            opts.checksum.name()
+        )));
+    }
+    // C启动器调用系统的tar解压，tar不支持brotli
+    if opts.launcher == Launcher::C && opts.compression == Compression::Brotli {
+        return Err(BundleError::InvalidOptions(
+            "--compression=brotli requires --launcher=rust".to_string(),
+        ));
+    }
+    // brotli流不能拼接，无法按块缓存
+    if opts.cache_dir.is_some()
+        && (opts.compression == Compression::Brotli
+            || opts.exe_compression == Some(Compression::Brotli))
+    {
+        warn!(
+            "--cache-dir does not cache brotli-compressed data; it is compressed in full each time"
+        );
+    }
+    // 重复文件由启动器按清单重建，C启动器只调用tar解压
+    if opts.launcher == Launcher::C && opts.dedup {
+        return Err(BundleError::InvalidOptions(
+            "--dedup requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.launcher == Launcher::C && opts.cache {
+        return Err(BundleError::InvalidOptions(
+            "--cache requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.launcher == Launcher::C && opts.passphrase.is_some() {
+        return Err(BundleError::InvalidOptions(
+            "--encrypt requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.launcher == Launcher::C && opts.pre_launch.is_some() {
+        return Err(BundleError::InvalidOptions(
+            "--pre-launch requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.static_launcher && opts.launcher == Launcher::Rust {
+        warn!("--static only affects --launcher=c; the Rust launcher is always statically linked");
+    }
+    if opts.keep_build
+        && (opts.launcher == Launcher::Rust || opts.reuse_launcher.is_some())
+        && !opts.pack_launcher
+    {
+        warn!("--keep-build has no effect: nothing is compiled with this launcher");
+    }
+    if opts.store_aligned {
+        let conflict = if opts.compression != Compression::None {
+            Some("--store-aligned requires --compression=none")
+        } else if opts.passphrase.is_some() {
+            Some("--store-aligned cannot be used with --encrypt")
+        } else if opts.exe_compression.is_some() {
+            Some("--store-aligned cannot be combined with --compress-executable")
+        } else if opts.format != Layout::SelfExtract {
+            Some("--store-aligned requires --format=self-extract")
+        } else if opts.split_size.is_some() {
+            Some("--store-aligned cannot be combined with --split-size")
+        } else if opts.pack_launcher {
+            Some("--store-aligned cannot be combined with --pack-launcher")
+        } else {
+            None
+        };
+        if let Some(message) = conflict {
+            return Err(BundleError::InvalidOptions(message.to_string()));
+        }
+    }
+    if opts.split_size == Some(0) {
+        return Err(BundleError::InvalidOptions(
+            "--split-size must be at least 1".to_string(),
+        ));
+    }
+    if opts.launcher == Launcher::C && opts.exe_compression.is_some() {
+        return Err(BundleError::InvalidOptions(
+            "--compress-executable requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.launcher == Launcher::C && opts.prefer_memory {
+        return Err(BundleError::InvalidOptions(
+            "--prefer-memory requires --launcher=rust".to_string(),
+        ));
+    }
+    if opts.format == Layout::Packed {
+        return Err(BundleError::InvalidOptions(
+            "format 'packed' is produced with --pack-launcher".to_string(),
+        ));
+    }
+    if opts.format == Layout::NoLauncher {
+        return Err(BundleError::InvalidOptions(
+            "format 'no-launcher' is produced with --no-launcher".to_string(),
+        ));
+    }
+    // 解压桩依赖 memfd_create 与 fexecve，且只能读取打包文件本身，不能读取拆分出的其他部分
+    if opts.pack_launcher {
+        let conflict = if !cfg!(target_os = "linux") {
+            Some("--pack-launcher is only supported on Linux")
+        } else if opts.format != Layout::SelfExtract {
+            Some("--pack-launcher requires --format=self-extract")
+        } else if opts.split_size.is_some() {
+            Some("--pack-launcher cannot be combined with --split-size")
+        } else {
+            None
+        };
+        if let Some(message) = conflict {
+            return Err(BundleError::InvalidOptions(message.to_string()));
+        }
+    }
+    // 没有启动器时，与启动器有关的选项都无从生效；宿主程序只能读取单个文件
+    if opts.no_launcher {
+        let conflict = if opts.format != Layout::SelfExtract {
+            Some("--no-launcher requires --format=self-extract")
+        } else if opts.pack_launcher {
+            Some("--no-launcher cannot be combined with --pack-launcher")
+        } else if opts.reuse_launcher.is_some() {
+            Some("--no-launcher cannot be combined with --reuse-launcher")
+        } else if opts.target_os.is_some() {
+            Some("--no-launcher cannot be combined with --target-os")
+        } else if opts.split_size.is_some() {
+            Some("--no-launcher cannot be combined with --split-size")
+        } else {
+            None
+        };
+        if let Some(message) = conflict {
+            return Err(BundleError::InvalidOptions(message.to_string()));
+        }
+    }
+    // 缓存目录需要在多次启动之间保留，不能位于内存中
+    if opts.cache && opts.prefer_memory {
+        return Err(BundleError::InvalidOptions(
+            "--prefer-memory cannot be combined with --cache".to_string(),
+        ));
+    }
+    for (key, value) in &opts.env {
+        manifest::check_env_var(key, value)
+            .map_err(|e| BundleError::InvalidOptions(format!("Invalid --env: {}", e)))?;
+    }
+    // C启动器的清单解析不接受 \u0000
+    if opts
+        .notice
+        .as_deref()
+        .is_some_and(|notice| notice.contains('\0'))
+    {
+        return Err(BundleError::InvalidOptions(
+            "The notice must not contain NUL characters".to_string(),
+        ));
+    }
+    manifest::check_entry_mode(opts.entry_mode)
+        .map_err(|e| BundleError::InvalidOptions(format!("Invalid --entry-mode: {}", e)))?;
+    // 加密使用随机的盐与随机数，每次输出都不相同
+    if opts.reproducible && opts.passphrase.is_some() {
+        return Err(BundleError::InvalidOptions(
+            "--reproducible cannot be used with --encrypt".to_string(),
+        ));
+    }
+    // 清单不加密，逐文件校验和会泄露资源包中的所有路径
+    if opts.entry_checksums && opts.passphrase.is_some() {
+        return Err(BundleError::InvalidOptions(
+            "--entry-checksums cannot be used with --encrypt".to_string(),
+        ));
+    }
+    if opts.passphrase.as_deref() == Some("") {
+        return Err(BundleError::InvalidOptions(
+            "The encryption passphrase must not be empty".to_string(),
+        ));
+    }
+    Ok((level, threads, exe_level))
+}
+
+// 读取可执行文件头，用于识别其格式与架构
+fn read_header(path: &Path) -> Result<Vec<u8>, BundleError> {
+    let mut header = Vec::new();
+    fs::File::open(path)?.take(4096).read_to_end(&mut header)?;
+    Ok(header)
+}
+
+// 主程序必须是启动器能执行的可执行文件或脚本，否则（如误传了资源文件）要到运行时才会失败；
+// `launcher` 为重用或其他平台的启动器，`None` 时为运行sekaipack的平台的启动器
+fn check_format(path: &Path, header: &[u8], launcher: Option<&[u8]>) -> Result<(), BundleError> {
+    let launcher_format = launcher
+        .and_then(arch::format)
+        .unwrap_or(arch::Format::NATIVE);
+    match arch::format(header) {
+        Some(format) if launcher_format.can_run(format) => Ok(()),
+        _ => Err(BundleError::NotExecutable {
+            path: path.to_path_buf(),
+            expected: match launcher_format {
+                arch::Format::Pe => launcher_format.name().to_string(),
+                _ => format!("{} or a #! script", launcher_format.name()),
+            },
+        }),
+    }
+}
+
+// 启动器与主程序运行在同一台机器上，架构不兼容的打包文件在启动时才会失败
+fn check_arch(main_arch: Option<&str>, launcher: Option<&[u8]>) -> Result<(), BundleError> {
+    if let Some(exe_arch) = main_arch {
+        let launcher_arch = launcher.and_then(arch::detect).unwrap_or(env::consts::ARCH);
+        if !arch::is_compatible(launcher_arch, exe_arch) {
+            return Err(BundleError::ArchMismatch {
+                exe: exe_arch.to_string(),
+                launcher: launcher_arch.to_string(),
+            });
+        }
+        debug!("Main executable architecture: {}", exe_arch);
+    }
+    Ok(())
+}
+
+// 记录在清单中的打包时间
+fn created_at(opts: &BundleOptions) -> Result<u64, BundleError> {
+    if opts.reproducible {
+        return source_date_epoch();
+    }
+    Ok(SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0))
+}
+
+// 临时解压目录名的前缀，默认为输出文件名加下划线
+fn resolve_temp_prefix(opts: &BundleOptions, output: Option<&Path>) -> Result<String, BundleError> {
+    let temp_prefix = match (&opts.temp_prefix, output.and_then(Path::file_name)) {
+        (Some(prefix), _) => prefix.clone(),
+        (None, Some(name)) => format!("{}_", name.to_string_lossy()),
+        (None, None) => manifest::DEFAULT_TEMP_PREFIX.to_string(),
+    };
+    if !manifest::is_valid_temp_prefix(&temp_prefix) {
+        return Err(BundleError::InvalidOptions(format!(
+            "Invalid --temp-prefix '{}': it must be non-empty and contain no path separators",
+            temp_prefix
+        )));
+    }
+    Ok(temp_prefix)
+}
+
+// 读入 `--reuse-launcher` 指定的启动器，其类型必须与 `--launcher` 一致
+fn read_reused_launcher(opts: &BundleOptions) -> Result<Option<Vec<u8>>, BundleError> {
+    let Some(path) = &opts.reuse_launcher else {
+        return Ok(None);
+    };
+    let (kind, bytes) = launcher::read_reusable_launcher(path)?;
+    if kind != opts.launcher {
+        return Err(BundleError::InvalidOptions(format!(
+            "'{}' is a {} launcher; pass --launcher={}",
+            path.display(),
+            kind.name(),
+            kind.name()
+        )));
+    }
+    Ok(Some(bytes))
+}
+
+// 用于检查主程序格式与架构的启动器：重用的启动器须与 `--target-os` 的平台一致，
+// 否则为其他平台时取嵌入的该平台的启动器
+fn target_launcher<'a>(
+    opts: &BundleOptions,
+    reused_launcher: Option<&'a [u8]>,
+) -> Result<Option<&'a [u8]>, BundleError> {
+    if let Some(bytes) = reused_launcher {
+        if let Some(os) = opts.target_os
+            && arch::format(bytes) != Some(os.format())
+        {
+            return Err(BundleError::InvalidOptions(format!(
+                "The reused launcher is not a {} launcher (--target-os={})",
+                os.format().name(),
+                os.name()
+            )));
+        }
+        return Ok(Some(bytes));
+    }
+    if opts.launcher == Launcher::Rust && target_os(opts) != TargetOs::NATIVE {
+        return Ok(Some(launcher::rust_launcher(opts.target_os)?));
+    }
+    Ok(None)
+}
+
+// 确定各可执行文件在包内的文件名：主程序默认沿用自身的文件名，附加的可执行文件始终使用自身的文件名
+fn resolve_executables<'a>(
+    main_exe: &'a Path,
+    opts: &'a BundleOptions,
+) -> Result<Vec<(&'a Path, String)>, BundleError> {
+    let main_name = match &opts.entry_name {
+        Some(name) => name.clone(),
+        None => default_entry_name(exe_file_name(main_exe, "use --entry-name")?, opts),
+    };
+    let mut executables = vec![(main_exe, main_name)];
+    for exe in &opts.extra_exes {
+        executables.push((exe.as_path(), exe_file_name(exe, "rename the file")?));
+    }
+
+    for (i, (_, name)) in executables.iter().enumerate() {
+        if !manifest::is_valid_entry_name(name) {
+            return Err(BundleError::InvalidEntryName(name.clone()));
+        }
+        if executables[..i].iter().any(|(_, other)| other == name) {
+            return Err(BundleError::InvalidOptions(format!(
+                "Duplicate executable name '{}'",
+                name
+            )));
+        }
+    }
+    Ok(executables)
+}
+
+// 附加数据文件以各自的文件名保存，不能重复
+fn resolve_data_files(opts: &BundleOptions) -> Result<Vec<(&Path, String)>, BundleError> {
+    let mut data_files: Vec<(&Path, String)> = Vec::new();
+    for path in &opts.append_data {
+        if !path.is_file() {
+            return Err(BundleError::DataNotFound(path.clone()));
+        }
+        let name = exe_file_name(path, "rename the file")?;
+        if !manifest::is_valid_entry_name(&name) {
+            return Err(BundleError::InvalidEntryName(name));
+        }
+        if data_files.iter().any(|(_, other)| *other == name) {
+            return Err(BundleError::InvalidOptions(format!(
+                "Duplicate data file name '{}'",
+                name
+            )));
+        }
+        data_files.push((path.as_path(), name));
+    }
+    Ok(data_files)
+}
+
+// 打包给 Windows 时，没有扩展名的主程序在包内补上 .exe，Windows 通过扩展名识别可执行文件
+fn default_entry_name(name: String, opts: &BundleOptions) -> String {
+    if target_os(opts) == Some(TargetOs::Windows) && Path::new(&name).extension().is_none() {
+        format!("{}.exe", name)
+    } else {
+        name
+    }
+}
+
+// 打包文件运行的平台
+fn target_os(opts: &BundleOptions) -> Option<TargetOs> {
+    opts.target_os.or(TargetOs::NATIVE)
+}
+
+fn exe_file_name(exe: &Path, hint: &str) -> Result<String, BundleError> {
+    match exe.file_name().and_then(|name| name.to_str()) {
+        Some(name) => Ok(name.to_string()),
+        None => Err(BundleError::InvalidOptions(format!(
+            "Cannot derive a file name from '{}', {}",
+            exe.display(),
+            hint
+        ))),
+    }
+}
+
+// 将主程序复制到 `dir` 并去除副本的符号，`strip` 不可用或失败时给出警告并返回 `None`（使用原文件）
+fn strip_executable(main_exe: &Path, dir: &Path) -> Result<Option<PathBuf>, BundleError> {
+    let copy = dir.join(main_exe.file_name().unwrap_or("main".as_ref()));
+    fs::copy(main_exe, &copy)?;
+    info!("Stripping {}...", main_exe.display());
+    let output = Command::new("strip")
+        .arg(&copy)
+        .stdin(Stdio::null())
+        .output();
+    match output {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => {
+            warn!(
+                "strip failed, bundling {} unstripped: {}",
+                main_exe.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            );
+            return Ok(None);
+        }
+        Err(e) => {
+            warn!(
+                "strip is not available ({}), bundling {} unstripped",
+                e,
+                main_exe.display()
+            );
+            return Ok(None);
+        }
+    }
+
+    let before = fs::metadata(main_exe)?.len();
+    let after = fs::metadata(&copy)?.len();
+    info!(
+        "Stripped {}: {} -> {} bytes (saved {} bytes)",
+        main_exe.display(),
+        before,
+        after,
+        before.saturating_sub(after)
+    );
+    Ok(Some(copy))
+}
+
+// 可重现打包的时间戳：按惯例取自 SOURCE_DATE_EPOCH，未设置时为0
+fn source_date_epoch() -> Result<u64, BundleError> {
+    match env::var(SOURCE_DATE_EPOCH_ENV) {
+        Ok(value) => value.trim().parse().map_err(|_| {
+            BundleError::InvalidOptions(format!(
+                "Invalid {} '{}' (expected a Unix timestamp)",
+                SOURCE_DATE_EPOCH_ENV, value
+            ))
+        }),
+        Err(_) => Ok(0),
+    }
+}
+
+fn build_exclude_set(patterns: &[String]) -> Result<GlobSet, BundleError> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = Glob::new(pattern).map_err(|e| {
+            BundleError::InvalidOptions(format!("Invalid --exclude pattern '{}': {}", pattern, e))
+        })?;
+        builder.add(glob);
+    }
+    builder
+        .build()
+        .map_err(|e| BundleError::InvalidOptions(format!("Invalid --exclude patterns: {}", e)))
+}
+
+fn check_level(compression: Compression, level: u32) -> Result<u32, BundleError> {
+    let (min, max) = match compression.level_range() {
+        Some(range) => range,
+        None => {
+            return Err(BundleError::InvalidOptions(format!(
+                "--level cannot be used with {} compression",
+                compression.name()
+            )));
+        }
+    };
+    if !(min..=max).contains(&level) {
+        return Err(BundleError::InvalidOptions(format!(
+            "Invalid {} compression level '{}' (expected {}-{})",
+            compression.name(),
+            level,
+            min,
+            max
+        )));
+    }
+    Ok(level)
+}
+
+fn create_bundled_app(
+    mut plan: BundlePlan,
+    output_file: &Path,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), BundleError> {
+    if plan.opts.dry_run {
+        return print_dry_run(&plan);
+    }
+    let started = Instant::now();
+    let mut stats = BuildStats::default();
+    check_compiler(&plan)?;
+    if plan.opts.store_aligned || plan.opts.entry_checksums {
+        archive::check_indexed_paths(&plan).map_err(archive::archive_error)?;
+    }
+
+    // 先写入输出目录中的临时文件（以读写方式打开，需要回读资源数据计算校验和），
+    // 完成后再重命名为输出文件，读取方只会看到旧文件或完整的新文件；出错时临时文件随之删除
+    let dir = match output_file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut staging = tempfile::Builder::new()
+        .prefix(".sekaipack_output_")
+        .tempfile_in(dir)?;
+    plan.staging = Some(staging.path().to_path_buf());
+    if plan.opts.dedup {
+        plan.duplicates = archive::find_duplicates(&plan).map_err(archive::archive_error)?;
+    }
+
+    match plan.opts.format {
+        Layout::SelfExtract if plan.opts.pack_launcher => {
+            // 先生成完整的自解压文件，再压缩写入输出
+            let mut inner = tempfile::tempfile()?;
+            write_bundle(&mut inner, &plan, &mut stats, progress)?;
+            write_packed(staging.as_file_mut(), &mut inner, &plan, &mut stats)?;
+        }
+        Layout::SelfExtract => write_bundle(staging.as_file_mut(), &plan, &mut stats, progress)?,
+        Layout::Tarball => write_tarball(staging.as_file_mut(), &plan, &mut stats, progress)?,
+        Layout::Packed | Layout::NoLauncher => {
+            unreachable!("rejected when validating the options")
+        }
+    }
+    staging.as_file().sync_all()?;
+
+    // 临时文件创建时只有所有者可读写，自解压文件需要执行权限（--no-exec-bit 时除外）
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = match plan.opts.format {
+            Layout::SelfExtract if !plan.opts.no_exec_bit && !plan.opts.no_launcher => 0o755,
+            _ => 0o644,
+        };
+        fs::set_permissions(staging.path(), fs::Permissions::from_mode(mode))?;
+    }
+
+    // 第一部分容纳不下启动器时无法运行，在写出输出文件之前报错
+    let output_len = staging.as_file().metadata()?.len();
+    let split_size = plan
+        .opts
+        .split_size
+        .filter(|&part_size| output_len > part_size);
+    if let Some(part_size) = split_size
+        && plan.opts.format == Layout::SelfExtract
+    {
+        let launcher_len = Manifest::read_from(staging.as_file_mut())?.resource_offset;
+        if launcher_len > part_size {
+            return Err(BundleError::InvalidOptions(format!(
+                "--split-size must be at least the launcher size ({} bytes) so that the first part can be run",
+                launcher_len
+            )));
+        }
+    }
+
+    staging
+        .persist(output_file)
+        .map_err(|e| BundleError::Io(e.error))?;
+
+    match (plan.opts.split_size, split_size) {
+        (_, Some(part_size)) => split::split_output(output_file, part_size)?,
+        (Some(_), None) => info!("Output fits in --split-size, not splitting"),
+        (None, None) => {}
+    }
+
+    stats.output_len = output_len;
+    stats.total_time = started.elapsed();
+    stats.report();
+    Ok(())
+}
+
+// 需要编译C启动器或解压桩时，在开始写入前确认gcc可用
+fn check_compiler(plan: &BundlePlan) -> Result<(), BundleError> {
+    if (plan.opts.launcher == Launcher::C
+        && plan.reused_launcher.is_none()
+        && !plan.opts.no_launcher)
+        || plan.opts.pack_launcher
+    {
+        launcher::check_c_compiler()?;
+    }
+    Ok(())
+}
+
+// 一次打包的统计，完成后以info级别输出，便于比较不同压缩方式与等级的效果
+#[derive(Default)]
+struct BuildStats {
+    files: usize,
+    unpacked_size: u64,
+    packed_size: u64,
+    output_len: u64,
+    // 编译或嵌入启动器的耗时
+    launcher_time: Duration,
+    // 生成（压缩、加密）资源数据的耗时
+    resources_time: Duration,
+    total_time: Duration,
+}
+
+impl BuildStats {
+    fn report(&self) {
+        info!(
+            "Summary: {} files, {} bytes uncompressed -> {} bytes packed ({:.1}%), output {} bytes",
+            self.files,
+            self.unpacked_size,
+            self.packed_size,
+            self.packed_size as f64 * 100.0 / self.unpacked_size.max(1) as f64,
+            self.output_len
+        );
+        // 其余时间用于校验和、清单、同步到磁盘与拆分
+        let write_time = self
+            .total_time
+            .saturating_sub(self.launcher_time + self.resources_time);
+        info!(
+            "Timing: launcher {:.2}s, compression {:.2}s, write {:.2}s, total {:.2}s",
+            self.launcher_time.as_secs_f64(),
+            self.resources_time.as_secs_f64(),
+            write_time.as_secs_f64(),
+            self.total_time.as_secs_f64()
+        );
+    }
+}
+
+// 列出将要打包的文件（应用排除规则后）及未压缩总大小
+fn print_dry_run(plan: &BundlePlan) -> Result<(), BundleError> {
+    let mut files = Vec::new();
+    for (source, name) in &plan.executables {
+        files.push((name.clone(), source.len()?));
+    }
+    for (source, name) in &plan.data_files {
+        let path = Path::new(manifest::DATA_DIR).join(name);
+        files.push((path.display().to_string(), fs::metadata(source)?.len()));
+    }
+    archive::walk_resources(plan, &mut |entry| {
+        let path = entry.archive_path.display();
+        match entry.kind {
+            archive::EntryKind::Dir => {}
+            archive::EntryKind::File => {
+                files.push((path.to_string(), fs::metadata(&entry.source)?.len()));
+            }
+            archive::EntryKind::Symlink => {
+                let target = fs::read_link(&entry.source)?;
+                files.push((format!("{} -> {}", path, target.display()), 0));
+            }
+        }
+        Ok(())
+    })
+    .map_err(archive::archive_error)?;
+
+    for (path, size) in &files {
+        println!("{:>12}  {}", size, path);
+    }
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    let compression = if plan.opts.compression == Compression::None {
+        "uncompressed".to_string()
+    } else {
+        format!("{}, level {}", plan.opts.compression.name(), plan.level)
+    };
+    println!(
+        "{} files, {} bytes uncompressed ({})",
+        files.len(),
+        total,
+        compression
+    );
+    Ok(())
+}
+
+// 依次写入启动器、资源数据与尾部元数据，资源tar流直接写入输出文件而不经过内存或中间文件
+fn write_bundle<W: Read + Write + Seek>(
+    final_exe: &mut W,
+    plan: &BundlePlan,
+    stats: &mut BuildStats,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), BundleError> {
+    if !plan.opts.no_launcher {
+        write_launcher(final_exe, plan, stats)?;
+    }
+    let mut manifest = write_resources(final_exe, plan, stats, progress)?;
+    if plan.opts.no_launcher {
+        manifest.layout = Layout::NoLauncher;
+    }
+
+    // 在资源数据之后写入清单与尾部
+    let data_end = manifest.resource_end();
+    final_exe.seek(SeekFrom::Start(data_end))?;
+    manifest.write_to(final_exe)?;
+    debug!(
+        "Layout: launcher 0..{}, resources {}..{}, manifest and footer {}..{}",
+        manifest.resource_offset,
+        manifest.resource_offset,
+        data_end,
+        data_end,
+        final_exe.stream_position()?
+    );
+
+    Ok(())
+}
+
+// 启动器、资源数据与清单分别先写入临时文件，再作为独立成员写入tar包
+fn write_tarball(
+    output: &mut fs::File,
+    plan: &BundlePlan,
+    stats: &mut BuildStats,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), BundleError> {
+    let mut launcher_file = tempfile::tempfile()?;
+    write_launcher(&mut launcher_file, plan, stats)?;
+    let mut resources_file = tempfile::tempfile()?;
+    let mut manifest = write_resources(&mut resources_file, plan, stats, progress)?;
+    manifest.layout = Layout::Tarball;
+    let json = manifest.to_json()?;
+
+    let mut builder = tar::Builder::new(BufWriter::new(output));
+    let mut append = |name: &str, mode: u32, len: u64, data: &mut dyn Read| {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(len);
+        header.set_mode(mode);
+        header.set_mtime(manifest.created_at);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data)
+    };
+    let launcher_len = launcher_file.stream_position()?;
+    launcher_file.rewind()?;
+    append(
+        manifest::TARBALL_LAUNCHER,
+        0o755,
+        launcher_len,
+        &mut launcher_file,
+    )?;
+    resources_file.rewind()?;
+    append(
+        manifest::TARBALL_RESOURCES,
+        0o644,
+        manifest.resource_len,
+        &mut resources_file,
+    )?;
+    append(
+        manifest::TARBALL_MANIFEST,
+        0o644,
+        json.len() as u64,
+        &mut &json[..],
+    )?;
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+fn write_launcher<W: Write>(
+    output: &mut W,
+    plan: &BundlePlan,
+    stats: &mut BuildStats,
+) -> Result<(), BundleError> {
+    let started = Instant::now();
+    if let Some(bytes) = &plan.reused_launcher {
+        info!("Reusing launcher ({} bytes)...", bytes.len());
+        output.write_all(bytes)?;
+    } else {
+        match plan.opts.launcher {
+            Launcher::Rust => launcher::embed_rust_launcher(output, plan.opts.target_os)?,
+            Launcher::C => {
+                let temp_dir = build_dir(plan.opts)?;
+                let launcher_path = temp_dir.path().join("launcher");
+                launcher::compile_c_launcher(
+                    temp_dir.path(),
+                    &launcher_path,
+                    plan.opts.static_launcher,
+                    plan.opts.keep_build,
+                )?;
+                io::copy(&mut fs::File::open(&launcher_path)?, output)?;
+            }
+        }
+    }
+    stats.launcher_time = started.elapsed();
+    Ok(())
+}
+
+// 每次编译使用独立的临时目录，出错返回时也会自动删除；`--keep-build` 时保留（编译失败时同样如此）
+fn build_dir(opts: &BundleOptions) -> Result<tempfile::TempDir, BundleError> {
+    let dir = tempfile::Builder::new()
+        .prefix("sekaipack_build_")
+        .disable_cleanup(opts.keep_build)
+        .tempdir()?;
+    if opts.keep_build {
+        info!("Keeping build directory {}", dir.path().display());
+    }
+    Ok(dir)
+}
+
+// `--pack-launcher`：写入解压桩，其后为gzip压缩的完整自解压文件 `inner`，再写入清单与尾部。
+// 清单沿用 `inner` 中的清单，资源数据改为描述压缩后的数据
+fn write_packed<W: Read + Write + Seek, T: Read + Seek>(
+    output: &mut W,
+    inner: &mut T,
+    plan: &BundlePlan,
+    stats: &mut BuildStats,
+) -> Result<(), BundleError> {
+    let started = Instant::now();
+    let temp_dir = build_dir(plan.opts)?;
+    let stub_path = temp_dir.path().join("stub");
+    launcher::compile_stub(
+        temp_dir.path(),
+        &stub_path,
+        plan.opts.static_launcher,
+        plan.opts.keep_build,
+    )?;
+    let resource_offset = io::copy(&mut fs::File::open(&stub_path)?, output)?;
+    stats.launcher_time += started.elapsed();
+
+    let started = Instant::now();
+    let mut manifest = Manifest::read_from(inner)?;
+    let inner_len = inner.seek(SeekFrom::End(0))?;
+    info!(
+        "Compressing the whole bundle (gzip, {} bytes)...",
+        inner_len
+    );
+    inner.rewind()?;
+    // 解压桩只支持gzip，使用最高压缩等级
+    let mut encoder =
+        flate2::write::GzEncoder::new(BufWriter::new(&mut *output), flate2::Compression::best());
+    io::copy(inner, &mut encoder)?;
+    encoder.finish()?.flush()?;
+    let resource_len = output.stream_position()? - resource_offset;
+    stats.resources_time += started.elapsed();
+    debug!(
+        "Packed bundle: {} bytes -> {} bytes ({:.1}%)",
+        inner_len,
+        resource_len,
+        resource_len as f64 * 100.0 / inner_len.max(1) as f64
+    );
+
+    output.seek(SeekFrom::Start(resource_offset))?;
+    let checksum = plan
+        .opts
+        .checksum
+        .compute(BufReader::new((&mut *output).take(resource_len)))?;
+    manifest.layout = Layout::Packed;
+    manifest.compression = Compression::Gzip;
+    manifest.checksum_type = plan.opts.checksum;
+    manifest.checksum = checksum;
+    manifest.encryption = Encryption::None;
+    manifest.kdf_salt.clear();
+    manifest.nonce.clear();
+    manifest.aligned_index.clear();
+    manifest.entry_checksums.clear();
+    manifest.dedup.clear();
+    manifest.exe_compression = None;
+    manifest.exe_section_len = 0;
+    manifest.resource_offset = resource_offset;
+    manifest.resource_len = resource_len;
+    manifest.write_to(output)?;
+    Ok(())
+}
+
+// 从当前位置写入（压缩、加密后的）资源数据并计算校验和，返回描述这段数据的清单
+fn write_resources<W: Read + Write + Seek>(
+    final_exe: &mut W,
+    plan: &BundlePlan,
+    stats: &mut BuildStats,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<Manifest, BundleError> {
+    let opts = plan.opts;
+
+    // 对齐存放时资源从 `ALIGNMENT` 的整数倍处开始，tar头的512字节块才能与之对齐
+    if opts.store_aligned {
+        let position = final_exe.stream_position()?;
+        let padding = position.next_multiple_of(archive::ALIGNMENT) - position;
+        final_exe.write_all(&vec![0; padding as usize])?;
+    }
+
+    // 记录资源偏移
+    let resource_offset = final_exe.stream_position()?;
+
+    // 写入资源数据
+    if opts.compression == Compression::None {
+        info!("Creating resource package (uncompressed)...");
+    } else if opts.compression == Compression::Zstd {
+        info!(
+            "Creating resource package (zstd, level {}, {} threads)...",
+            plan.level, plan.threads
+        );
+    } else {
+        info!(
+            "Creating resource package ({}, level {})...",
+            opts.compression.name(),
+            plan.level
+        );
+    }
+    if let Some(compression) = opts.exe_compression {
+        info!(
+            "Compressing executables separately ({}, level {})...",
+            compression.name(),
+            plan.exe_level
+        );
+    }
+    let started = Instant::now();
+    let (mut encryption, mut kdf_salt, mut nonce) =
+        (Encryption::None, String::new(), String::new());
+    let package = match &opts.passphrase {
+        // 加密需要完整的资源数据，先在内存中生成
+        Some(passphrase) => {
+            let mut package = Vec::new();
+            let written = archive::create_resource_package(plan, &mut package, 0, progress)?;
+            info!("Encrypting resources ({})...", Encryption::Aes256Gcm.name());
+            let sealed = encryption::encrypt(passphrase, &package)?;
+            final_exe.write_all(&sealed.data)?;
+            (encryption, kdf_salt, nonce) = (Encryption::Aes256Gcm, sealed.salt, sealed.nonce);
+            written
+        }
+        None => archive::create_resource_package(
+            plan,
+            BufWriter::new(&mut *final_exe),
+            resource_offset,
+            progress,
+        )?,
+    };
+    let uncompressed = package.unpacked_size;
+    let data_end = final_exe.stream_position()?;
+    let resource_len = data_end - resource_offset;
+    stats.resources_time = started.elapsed();
+    stats.files = package.files;
+    stats.unpacked_size = uncompressed;
+    stats.packed_size = resource_len;
+    debug!(
+        "Resources: {} bytes uncompressed, {} bytes packed ({:.1}%)",
+        uncompressed,
+        resource_len,
+        resource_len as f64 * 100.0 / uncompressed.max(1) as f64
+    );
+
+    // 回读刚写入的资源数据计算校验和
+    info!("Computing {} checksum...", opts.checksum.name());
+    final_exe.seek(SeekFrom::Start(resource_offset))?;
+    let resource_checksum = opts
+        .checksum
+        .compute(BufReader::new((&mut *final_exe).take(resource_len)))?;
+    debug!("Checksum: {} {}", opts.checksum.name(), resource_checksum);
+    let entry_checksums = if opts.entry_checksums {
+        info!(
+            "Computing {} checksum of each file...",
+            opts.checksum.name()
+        );
+        archive::entry_checksums(plan).map_err(archive::archive_error)?
+    } else {
+        String::new()
+    };
+
+    Ok(Manifest {
+        tool_version: env!("CARGO_PKG_VERSION").to_string(),
+        created_at: plan.created_at,
+        entry_name: plan.entry_name.clone(),
+        arch: plan.arch.clone(),
+        launch_args: opts.launch_arg_template.clone().unwrap_or_default(),
+        pre_launch: opts.pre_launch.clone().unwrap_or_default(),
+        env: opts
+            .env
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect(),
+        notice: opts.notice.clone().unwrap_or_default(),
+        compression: opts.compression,
+        checksum_type: opts.checksum,
+        checksum: resource_checksum,
+        cache: opts.cache,
+        cache_hash: opts.cache_hash,
+        encryption,
+        kdf_salt,
+        nonce,
+        layout: Layout::SelfExtract,
+        workdir: opts.workdir,
+        executables: plan
+            .executables
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join("/"),
+        temp_prefix: plan.temp_prefix.clone(),
+        data_files: plan
+            .data_files
+            .iter()
+            .map(|(_, name)| name.as_str())
+            .collect::<Vec<_>>()
+            .join("/"),
+        entry_mode: opts.entry_mode,
+        prefer_memory: opts.prefer_memory,
+        set_library_path: opts.set_library_path,
+        unpacked_size: uncompressed,
+        aligned_index: package
+            .aligned
+            .iter()
+            .map(|(path, offset, len)| format!("{} {} {}\n", offset, len, path.to_string_lossy()))
+            .collect(),
+        entry_checksums,
+        dedup: plan
+            .duplicates
+            .iter()
+            .map(|(path, original)| format!("{}\t{}\n", path.display(), original.display()))
+            .collect(),
+        exe_compression: opts.exe_compression,
+        exe_section_len: package.exe_section_len,
+        resource_offset,
+        resource_len,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_memory(opts: BundleOptions, entries: &[(&str, &[u8])]) -> Result<Vec<u8>, BundleError> {
+        let entries: Vec<(String, Vec<u8>)> = entries
+            .iter()
+            .map(|(name, data)| (name.to_string(), data.to_vec()))
+            .collect();
+        bundle_to_vec(b"#!/bin/sh\n", &entries, opts)
+    }
+
+    // 在 `dir` 中写入主程序 `app`，与 `resources` 一起打包为 `dir/out`
+    fn bundle_in(dir: &Path, resources: &[PathBuf], opts: BundleOptions) -> PathBuf {
+        let main = dir.join("app");
+        fs::write(&main, b"#!/bin/sh\n").unwrap();
+        let output = dir.join("out");
+        bundle(&main, resources, &output, opts).unwrap();
+        output
+    }
+
+    // 写出自解压文件并以 `args` 为参数运行，`stdin` 写入其标准输入
+    #[cfg(unix)]
+    fn run_bundle(bundle: &[u8], args: &[&str], stdin: &[u8]) -> std::process::Output {
+        let dir = tempfile::tempdir().unwrap();
+        let mut command = Command::new(write_executable(dir.path(), bundle));
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let mut child = spawn(&mut command);
+        child.stdin.take().unwrap().write_all(stdin).unwrap();
+        child.wait_with_output().unwrap()
+    }
+
+    // 将自解压文件写为 `dir/bundle` 并设置可执行位
+    #[cfg(unix)]
+    fn write_executable(dir: &Path, bundle: &[u8]) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = dir.join("bundle");
+        fs::write(&path, bundle).unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    // 其他测试线程创建子进程时可能短暂继承写入文件时的描述符，此时重试
+    #[cfg(unix)]
+    fn spawn(command: &mut Command) -> std::process::Child {
+        loop {
+            match command.spawn() {
+                Err(e) if e.raw_os_error() == Some(libc::ETXTBSY) => {
+                    std::thread::sleep(Duration::from_millis(10))
+                }
+                spawned => return spawned.unwrap(),
+            }
+        }
+    }
+
+    #[test]
+    fn store_aligned_rejects_line_breaks() {
+        let opts = BundleOptions {
+            no_launcher: true,
+            store_aligned: true,
+            compression: Compression::None,
+            ..BundleOptions::default()
+        };
+        assert!(in_memory(opts.clone(), &[("res/a", b"a")]).is_ok());
+        assert!(matches!(
+            in_memory(opts, &[("res/a\nb", b"a")]),
+            Err(BundleError::ArchiveEntry { .. })
+        ));
+    }
+
+    #[test]
+    fn entry_checksums_reject_line_breaks() {
+        let opts = BundleOptions {
+            no_launcher: true,
+            entry_checksums: true,
+            ..BundleOptions::default()
+        };
+        assert!(matches!(
+            in_memory(opts, &[("res/a\rb", b"a")]),
+            Err(BundleError::ArchiveEntry { .. })
+        ));
+    }
+
+    #[test]
+    fn dedup_stores_one_copy() {
+        let opts = BundleOptions {
+            no_launcher: true,
+            dedup: true,
+            compression: Compression::None,
+            ..BundleOptions::default()
+        };
+        let contents = b"identical contents stored once".repeat(64);
+        let bundle = in_memory(opts, &[("a/big", &contents), ("b/big", &contents)]).unwrap();
+        let copies = bundle
+            .windows(contents.len())
+            .filter(|window| *window == &contents[..])
+            .count();
+        assert_eq!(copies, 1);
+        let manifest = Manifest::read_from(&mut io::Cursor::new(&bundle)).unwrap();
+        assert_eq!(manifest.duplicates(), [("b/big", "a/big")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dedup_keeps_differing_modes() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("app");
+        fs::write(&main, b"#!/bin/sh\n").unwrap();
+        let res = dir.path().join("res");
+        for (name, mode) in [("a", 0o644), ("b", 0o600)] {
+            let path = res.join(name);
+            fs::create_dir_all(&path).unwrap();
+            fs::write(path.join("big"), b"identical contents").unwrap();
+            fs::set_permissions(path.join("big"), fs::Permissions::from_mode(mode)).unwrap();
+        }
+        let output = dir.path().join("out");
+        let opts = BundleOptions {
+            no_launcher: true,
+            dedup: true,
+            ..BundleOptions::default()
+        };
+        bundle(&main, &[res], &output, opts).unwrap();
+        let manifest = Manifest::read_from(&mut fs::File::open(&output).unwrap()).unwrap();
+        assert!(manifest.duplicates().is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launcher_rejects_corrupted_resources() {
+        for checksum in [Checksum::Crc32, Checksum::Sha256] {
+            let opts = BundleOptions {
+                compression: Compression::None,
+                checksum,
+                ..BundleOptions::default()
+            };
+            let mut bundle = in_memory(opts, &[("res/msg", b"resource contents")]).unwrap();
+            assert!(run_bundle(&bundle, &[], b"").status.success());
+
+            let pos = bundle
+                .windows(17)
+                .position(|window| window == b"resource contents")
+                .unwrap();
+            bundle[pos] ^= 1;
+            let output = run_bundle(&bundle, &[], b"");
+            assert_eq!(output.status.code(), Some(121), "{:?}", checksum);
+            assert!(String::from_utf8_lossy(&output.stderr).contains("checksum"));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn modes_and_mtimes_survive_extract() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("app");
+        fs::write(&main, b"#!/bin/sh\n").unwrap();
+        let res = dir.path().join("res");
+        fs::create_dir(&res).unwrap();
+        let mtime = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        for (name, mode) in [("tool.sh", 0o750), ("config", 0o640)] {
+            let path = res.join(name);
+            fs::write(&path, b"#!/bin/sh\necho tool\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        let output = dir.path().join("out");
+        let opts = BundleOptions {
+            no_launcher: true,
+            ..BundleOptions::default()
+        };
+        bundle(&main, &[res], &output, opts).unwrap();
+
+        let dest = dir.path().join("extracted");
+        extract(&output, &dest, None).unwrap();
+        for (name, mode) in [("tool.sh", 0o750), ("config", 0o640)] {
+            let metadata = fs::metadata(dest.join("res").join(name)).unwrap();
+            assert_eq!(metadata.permissions().mode() & 0o7777, mode, "{}", name);
+            assert_eq!(metadata.modified().unwrap(), mtime, "{}", name);
+        }
+    }
+
+    #[test]
+    fn reproducible_builds_are_identical() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("app");
+        fs::write(&main, b"#!/bin/sh\n").unwrap();
+        let res = dir.path().join("res");
+        fs::create_dir_all(res.join("sub")).unwrap();
+        fs::write(res.join("b"), b"b").unwrap();
+        fs::write(res.join("sub/a"), b"a").unwrap();
+
+        // 输出文件名决定临时目录的前缀，两次写入不同目录中的同名文件
+        let build = |name: &str| {
+            fs::create_dir(dir.path().join(name)).unwrap();
+            let output = dir.path().join(name).join("game");
+            let opts = BundleOptions {
+                reproducible: true,
+                ..BundleOptions::default()
+            };
+            bundle(&main, std::slice::from_ref(&res), &output, opts).unwrap();
+            Checksum::Sha256
+                .compute(fs::File::open(output).unwrap())
+                .unwrap()
+        };
+        let first = build("first");
+        // 修改时间不同的同一份内容
+        let mtime = SystemTime::now() - Duration::from_secs(3600);
+        for path in [res.join("b"), res.join("sub/a")] {
+            fs::File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(mtime)
+                .unwrap();
+        }
+        assert_eq!(build("second"), first);
+    }
+
+    #[test]
+    fn multithreaded_zstd_round_trip() {
+        // 足够大，使编码器把数据分给多个线程
+        let data: Vec<u8> = (0..4_000_000u32)
+            .flat_map(|i| (i / 7 % 251).to_le_bytes())
+            .collect();
+        let opts = BundleOptions {
+            no_launcher: true,
+            compression: Compression::Zstd,
+            threads: Some(4),
+            ..BundleOptions::default()
+        };
+        let bundle = in_memory(opts, &[("res/big", &data)]).unwrap();
+        let manifest = Manifest::read_from(&mut io::Cursor::new(&bundle)).unwrap();
+        let start = manifest.resource_offset as usize;
+        let resources = &bundle[start..start + manifest.resource_len as usize];
+        // 输出仍是启动器可以解压的单个zstd帧
+        assert_eq!(
+            zstd::zstd_safe::find_frame_compressed_size(resources),
+            Ok(resources.len())
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.bundle");
+        fs::write(&path, &bundle).unwrap();
+        assert_eq!(
+            open_bundle(&path, None)
+                .unwrap()
+                .read_entry("res/big")
+                .unwrap(),
+            data
+        );
+    }
+
+    #[test]
+    fn files_and_dirs_as_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let res = dir.path().join("res");
+        fs::create_dir(&res).unwrap();
+        fs::write(res.join("a"), b"a").unwrap();
+        let config = dir.path().join("config.json");
+        fs::write(&config, b"{}").unwrap();
+        let opts = BundleOptions {
+            no_launcher: true,
+            ..BundleOptions::default()
+        };
+        let output = bundle_in(dir.path(), &[res, config], opts);
+
+        let mut files = list(&output, None).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            [
+                (PathBuf::from("app"), 10),
+                (PathBuf::from("config.json"), 2),
+                (PathBuf::from("res"), 0),
+                (PathBuf::from("res/a"), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn resource_dir_named_like_executable() {
+        let dir = tempfile::tempdir().unwrap();
+        let main = dir.path().join("app");
+        fs::write(&main, b"#!/bin/sh\n").unwrap();
+        let res = dir.path().join("res");
+        fs::create_dir_all(res.join("app")).unwrap();
+        let output = dir.path().join("out");
+        let result = bundle(&main, &[res.join("app")], &output, BundleOptions::default());
+        assert!(matches!(result, Err(BundleError::InvalidOptions(_))));
+        assert!(!output.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn bundle_without_resources() {
+        let dir = tempfile::tempdir().unwrap();
+        let output = bundle_in(dir.path(), &[], BundleOptions::default());
+        let report = verify(&output, None).unwrap();
+        assert_eq!((report.files, report.executables), (1, 1));
+        assert_eq!(list(&output, None).unwrap(), [(PathBuf::from("app"), 10)]);
+        assert!(
+            run_bundle(&fs::read(&output).unwrap(), &[], b"")
+                .status
+                .success()
+        );
+    }
+
+    #[test]
+    fn long_and_unicode_names_round_trip() {
+        // 超过ustar的100字节名称限制
+        let long = format!("res/{}file.txt", "deeply/nested/directory/".repeat(8));
+        assert!(long.len() > 100);
+        let cjk = "res/资源/存档データ.dat";
+        let opts = BundleOptions {
+            no_launcher: true,
+            ..BundleOptions::default()
+        };
+        let bundle = in_memory(opts, &[(&long, b"long"), (cjk, b"cjk")]).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.bundle");
+        fs::write(&path, bundle).unwrap();
+        let dest = dir.path().join("extracted");
+        extract(&path, &dest, None).unwrap();
+        assert_eq!(fs::read(dest.join(&long)).unwrap(), b"long");
+        assert_eq!(fs::read(dest.join(cjk)).unwrap(), b"cjk");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinked_resource_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path().join("assets-v2");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a"), b"a").unwrap();
+        let link = dir.path().join("assets");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        let opts = BundleOptions {
+            no_launcher: true,
+            ..BundleOptions::default()
+        };
+        let output = bundle_in(dir.path(), &[link], opts);
+
+        // 链接本身被解引用，内容位于链接的文件名之下
+        let bundle = open_bundle(&output, None).unwrap();
+        assert_eq!(bundle.read_entry("assets/a").unwrap(), b"a");
+        assert!(bundle.entries().all(|e| !e.path.starts_with("assets-v2")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stdio_reaches_child() {
+        for launcher in [Launcher::Rust, Launcher::C] {
+            let opts = BundleOptions {
+                launcher,
+                ..BundleOptions::default()
+            };
+            let bundle = bundle_to_vec(b"#!/bin/sh\ncat\necho done >&2\n", &[], opts).unwrap();
+            let output = run_bundle(&bundle, &[], b"piped input\n");
+            assert!(output.status.success(), "{:?}", launcher);
+            assert_eq!(output.stdout, b"piped input\n", "{:?}", launcher);
+            assert_eq!(output.stderr, b"done\n", "{:?}", launcher);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn group_sigint_reaches_child_once() {
+        use std::io::BufRead;
+        use std::os::unix::process::CommandExt;
+
+        // 主程序统计收到的SIGINT次数：准备好后输出 `ready`，`$STOP` 存在时输出次数并退出
+        let main = b"#!/bin/sh\nn=0\ntrap 'n=$((n+1))' INT\necho ready\n\
+            while [ ! -e \"$STOP\" ]; do :; done\necho $n\n";
+        for launcher in [Launcher::Rust, Launcher::C] {
+            let opts = BundleOptions {
+                launcher,
+                ..BundleOptions::default()
+            };
+            let bundle = bundle_to_vec(main, &[], opts).unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let stop = dir.path().join("stop");
+            // 启动器位于单独的进程组中，向该进程组发送信号不会影响测试进程；
+            // 标准输入输出都不连接终端
+            let mut command = Command::new(write_executable(dir.path(), &bundle));
+            command
+                .env("STOP", &stop)
+                .process_group(0)
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null());
+            let mut child = spawn(&mut command);
+            let pid = child.id() as i32;
+            let mut stdout = io::BufReader::new(child.stdout.take().unwrap());
+            let mut line = String::new();
+            stdout.read_line(&mut line).unwrap();
+            assert_eq!(line, "ready\n", "{:?}", launcher);
+
+            // 启动器暂停时发送，主程序若直接收到这个信号，会在启动器继续运行并转发之前先处理它，
+            // 两次信号不会合并
+            unsafe {
+                libc::kill(pid, libc::SIGSTOP);
+                let mut status = 0;
+                libc::waitpid(pid, &mut status, libc::WUNTRACED);
+                libc::kill(-pid, libc::SIGINT);
+                std::thread::sleep(Duration::from_millis(200));
+                libc::kill(pid, libc::SIGCONT);
+            }
+            std::thread::sleep(Duration::from_millis(200));
+            fs::write(&stop, b"").unwrap();
+            line.clear();
+            stdout.read_to_string(&mut line).unwrap();
+            assert!(child.wait().unwrap().success(), "{:?}", launcher);
+            assert_eq!(line, "1\n", "{:?}", launcher);
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn launcher_version() {
+        for (launcher, name) in [(Launcher::Rust, "rust"), (Launcher::C, "c")] {
+            let opts = BundleOptions {
+                launcher,
+                ..BundleOptions::default()
+            };
+            let bundle = bundle_to_vec(b"#!/bin/sh\nexit 1\n", &[], opts).unwrap();
+            let output = run_bundle(&bundle, &["--version"], b"");
+            assert!(output.status.success(), "{:?}", launcher);
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                format!(
+                    "sekaipack launcher {} ({}, bundle format v{})\n",
+                    env!("CARGO_PKG_VERSION"),
+                    name,
+                    footer::VERSION
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn empty_dirs_survive_extract() {
+        let dir = tempfile::tempdir().unwrap();
+        let res = dir.path().join("res");
+        fs::create_dir_all(res.join("saves")).unwrap();
+        fs::create_dir_all(res.join("cache/empty")).unwrap();
+        let opts = BundleOptions {
+            no_launcher: true,
+            ..BundleOptions::default()
+        };
+        let output = bundle_in(dir.path(), &[res], opts);
+
+        let dest = dir.path().join("extracted");
+        extract(&output, &dest, None).unwrap();
+        assert!(dest.join("res/saves").is_dir());
+        assert!(dest.join("res/cache/empty").is_dir());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn packed_launcher_runs() {
+        let main = b"#!/bin/sh\ncat res/msg\n";
+        let entries = [("res/msg".to_string(), b"packed\n".to_vec())];
+        let plain = bundle_to_vec(main, &entries, BundleOptions::default()).unwrap();
+        let opts = BundleOptions {
+            pack_launcher: true,
+            ..BundleOptions::default()
+        };
+        let packed = bundle_to_vec(main, &entries, opts).unwrap();
+        assert!(packed.len() < plain.len());
+
+        let output = run_bundle(&packed, &[], b"");
+        assert!(output.status.success());
+        assert_eq!(output.stdout, b"packed\n");
+    }
+}
@@ -1,181 +1,927 @@
-use std::env;
+use std::ffi::OsString;
 use std::fs;
-use std::path::Path;
-use std::process::Command;
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use clap::{CommandFactory, Parser, Subcommand};
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use log::{Level, LevelFilter, debug, error, info};
+
+use sekaipack::config::{self, Config};
+use sekaipack::encryption::{self, Encryption};
+use sekaipack::{CacheHash, Checksum, Compression, Launcher, Layout, Progress, TargetOs, WorkDir};
+
+#[derive(Parser)]
+#[command(
+    name = "sekaipack",
+    version,
+    about = "Sekaipack - Package sekai with resources",
+    after_help = "Example: sekaipack test_env/sekai.x86_64 test_env/script test_env/sounds -o example_game --launch-arg-template=\"--path {dir}\"\n\n\
+Bundles exit with the main program's exit code (128 + N when it is killed by\n\
+signal N). The launcher reserves these codes for its own failures:\n  \
+120  creating the extraction directory or extracting the resources failed\n  \
+121  the bundle is invalid or corrupted (footer, manifest, checksum or decryption)\n  \
+122  the main program could not be executed\n  \
+123  the --pre-launch command failed or exited nonzero",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    /// Only print errors
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Print debug output such as every file added; repeat (-vv) for trace
+    /// output
+    #[arg(short, long, global = true, action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    #[command(flatten)]
+    pack: PackArgs,
+}
+
+#[derive(Subcommand)]
+enum Commands {
+    /// List the files packed inside a bundle
+    List {
+        /// Bundled executable to inspect
+        bundle: String,
+        /// Print the manifest and every entry as JSON
+        #[arg(long)]
+        json: bool,
+        /// Only list the files added with --append-data
+        #[arg(long)]
+        data: bool,
+    },
+    /// Check a bundle's footer, checksum and archive without extracting it
+    Verify {
+        /// Bundled executable to verify
+        bundle: String,
+        /// Print the result, checksums and manifest as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Extract the resources of a bundle into a directory
+    Extract {
+        /// Bundled executable to extract
+        bundle: String,
+        /// Destination directory (created if missing)
+        #[arg(required_unless_present = "manifest_only")]
+        dest_dir: Option<String>,
+        /// Only read the manifest, printing it as JSON or writing it to
+        /// manifest.json in the destination directory if one is given
+        #[arg(long)]
+        manifest_only: bool,
+    },
+    /// Replace the resources of a bundle in place, keeping its launcher and
+    /// executables
+    Update {
+        /// Bundled executable to update
+        bundle: String,
+        /// New resource directories
+        resource_dirs: Vec<String>,
+    },
+    /// Time extracting a bundle's resources into a temp directory
+    Bench {
+        /// Bundled executable to benchmark
+        bundle: String,
+        /// Number of extractions to time
+        #[arg(long, default_value_t = 5, value_parser = clap::value_parser!(u32).range(1..))]
+        iterations: u32,
+    },
+    /// Reassemble a bundle split with --split-size
+    Join {
+        /// First part of the split bundle (<output>.000)
+        first_part: String,
+        /// Joined bundle [default: the first part's name without .000]
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+}
+
+#[derive(clap::Args)]
+struct PackArgs {
+    /// Main executable to bundle
+    main_exe: Option<String>,
+
+    /// Resource directories to include
+    resource_dirs: Vec<String>,
+
+    /// Also include every file or directory listed in this file, one path
+    /// per line (blank lines and lines starting with # are ignored;
+    /// relative paths are relative to the list file)
+    #[arg(long, value_name = "LISTFILE")]
+    files_from: Option<String>,
+
+    /// Read packaging options from a TOML file; command line options take
+    /// precedence (defaults to ./sekai-pack.toml when it exists)
+    #[arg(long, value_name = "PATH")]
+    config: Option<String>,
+
+    /// Output file name [default: the main executable's name with its
+    /// extension replaced by .bundle, e.g. sekai.x86_64 -> sekai.bundle]
+    #[arg(
+        short,
+        long,
+        value_parser = clap::builder::NonEmptyStringValueParser::new()
+    )]
+    output: Option<String>,
+
+    /// Directory for the automatically named output (created if missing);
+    /// ignored when -o is given
+    #[arg(long, value_name = "DIR")]
+    output_dir: Option<String>,
+
+    /// Launcher embedded into the bundle (rust or c) [default: rust]
+    #[arg(long, value_parser = parse_launcher)]
+    launcher: Option<Launcher>,
+
+    /// Platform the bundle runs on (linux, windows or macos); launchers for
+    /// other platforms must be built into sekaipack with
+    /// SEKAIPACK_CROSS_TARGETS [default: this platform]
+    #[arg(long, value_parser = parse_target_os)]
+    target_os: Option<TargetOs>,
+
+    /// Output a self-extracting executable, or a tarball holding the
+    /// launcher, resources and manifest as separate members
+    /// [default: self-extract]
+    #[arg(long, value_parser = parse_format)]
+    format: Option<Layout>,
+
+    /// Resource compression (gzip, zstd, brotli or none) [default: gzip]
+    #[arg(long, value_parser = parse_compression)]
+    compression: Option<Compression>,
+
+    /// Compress the executables in their own stream (gzip, zstd, brotli or none),
+    /// separately from the resources [default: compressed with the resources]
+    #[arg(long, value_name = "COMPRESSION", value_parser = parse_compression)]
+    compress_executable: Option<Compression>,
+
+    /// Compression level (gzip 0-9, zstd 1-22, brotli 0-11)
+    #[arg(long)]
+    level: Option<u32>,
+
+    /// Threads used for zstd compression [default: available CPUs]
+    #[arg(long, value_name = "N")]
+    threads: Option<u32>,
+
+    /// Resource checksum algorithm (crc32 or sha256) [default: crc32]
+    #[arg(long, value_parser = parse_checksum)]
+    checksum: Option<Checksum>,
+
+    /// Also record a checksum of every file in the manifest, so `verify` can
+    /// name the corrupted files (not with --encrypt)
+    #[arg(long)]
+    entry_checksums: bool,
+
+    /// Show the files that would be packaged without writing anything
+    #[arg(long)]
+    dry_run: bool,
+
+    /// Keep the temporary directory used to compile the C launcher or the
+    /// --pack-launcher stub (source and gcc intermediates) and print its path
+    #[arg(long)]
+    keep_build: bool,
+
+    /// Overwrite the output if it already exists
+    #[arg(short, long)]
+    force: bool,
+
+    /// Don't show a progress bar while adding files
+    #[arg(long)]
+    no_progress: bool,
+
+    /// Extract to a persistent cache dir and reuse it across launches
+    #[arg(long)]
+    cache: bool,
+
+    /// Hash of the bundle's resource checksum used to name its --cache dir
+    /// (blake3 or sha256), recorded in the manifest for the launcher
+    /// [default: blake3]
+    #[arg(long, value_parser = parse_cache_hash)]
+    cache_hash: Option<CacheHash>,
+
+    /// Encrypt the resources with a passphrase (read from
+    /// SEKAI_PACK_PASSPHRASE or prompted for)
+    #[arg(long)]
+    encrypt: bool,
+
+    /// File name the main executable is stored and launched as
+    /// (defaults to its own file name)
+    #[arg(long, value_name = "NAME")]
+    entry_name: Option<String>,
+
+    /// Additional executable placed next to the main one, under its own file
+    /// name (repeatable)
+    #[arg(long = "exe", value_name = "PATH")]
+    extra_exes: Vec<String>,
+
+    /// Extra file (e.g. a license or build metadata) stored outside the
+    /// resource tree; the launcher passes its extracted directory in
+    /// $SEKAI_PACK_DATA (repeatable)
+    #[arg(long, value_name = "PATH")]
+    append_data: Vec<String>,
+
+    /// Bundled executable the launcher runs, by its name in the bundle
+    /// (defaults to the main executable)
+    #[arg(long, value_name = "NAME")]
+    entry: Option<String>,
+
+    /// Arguments the launcher passes to the main executable before the
+    /// user's own, split on whitespace; {dir} expands to the extraction dir
+    /// (e.g. "--path {dir}"). Defaults to passing nothing
+    #[arg(long, value_name = "TEMPLATE", allow_hyphen_values = true)]
+    launch_arg_template: Option<String>,
+
+    /// Shell command the launcher runs in the extraction dir after extracting
+    /// and before starting the main executable; the dir is also passed in
+    /// $SEKAI_PACK_DIR and a nonzero exit aborts the launch
+    #[arg(long, value_name = "CMD", allow_hyphen_values = true)]
+    pre_launch: Option<String>,
+
+    /// Environment variable the launcher sets for the main executable,
+    /// overriding an inherited variable of the same name; the value may
+    /// contain '=' (repeatable)
+    #[arg(long, value_name = "KEY=VALUE", value_parser = sekaipack::manifest::parse_env_var)]
+    env: Vec<(String, String)>,
+
+    /// Text file (e.g. a NOTICE or license file) stored in the bundle and
+    /// printed when the bundle is run with --about as its first argument
+    #[arg(long, value_name = "FILE")]
+    notice: Option<String>,
+
+    /// Prefix of the launcher's temporary extraction dir, followed by random
+    /// characters [default: the output file name and "_"]
+    #[arg(long, value_name = "PREFIX")]
+    temp_prefix: Option<String>,
+
+    /// Working directory of the main program: the extraction dir (temp) or
+    /// the directory the bundle is run from (invocation) [default: temp]
+    #[arg(long, value_parser = parse_workdir)]
+    workdir: Option<WorkDir>,
+
+    /// Link the C launcher with -static so it does not depend on the
+    /// system's libz.so (falls back to dynamic linking with a warning)
+    #[arg(long = "static")]
+    static_launcher: bool,
+
+    /// Store resources uncompressed with every file's data 4 KiB aligned in
+    /// the bundle, recording offsets in the manifest for mmap access
+    #[arg(long)]
+    store_aligned: bool,
+
+    /// Store the contents of symlinked files and directories instead of
+    /// the links themselves
+    #[arg(long)]
+    follow_symlinks: bool,
+
+    /// Store resource files with identical contents once; the launcher
+    /// recreates the duplicates as hard links
+    #[arg(long)]
+    dedup: bool,
+
+    /// Skip resource paths that do not exist (with a warning) instead of
+    /// failing before anything is built
+    #[arg(long)]
+    ignore_missing: bool,
+
+    /// Let the launcher extract into memory (/dev/shm on Linux) when the
+    /// resources fit the memory budget, falling back to the temp directory
+    #[arg(long)]
+    prefer_memory: bool,
+
+    /// Prepend the extraction directory to the main program's library search
+    /// path (LD_LIBRARY_PATH, DYLD_LIBRARY_PATH or PATH) so shared libraries
+    /// bundled next to it are found
+    #[arg(long)]
+    set_library_path: bool,
+
+    /// Split the output into <output>.000, <output>.001, ... of at most this
+    /// many bytes, plus an <output>.index for `sekaipack join`
+    #[arg(long, value_name = "BYTES")]
+    split_size: Option<u64>,
+
+    /// Produce byte-identical output for identical inputs: zero owners,
+    /// normalize permissions and use SOURCE_DATE_EPOCH (or 0) as every
+    /// timestamp
+    #[arg(long)]
+    reproducible: bool,
+
+    /// Strip symbols from a copy of the main executable before bundling;
+    /// the original is left untouched
+    #[arg(long)]
+    strip: bool,
+
+    /// Leave the execute permission off a self-extracting output (0644
+    /// instead of 0755), for packaging steps that set permissions later
+    #[arg(long)]
+    no_exec_bit: bool,
+
+    /// Bundle the main executable even if it is not an executable or script
+    /// this platform can run (ELF, PE or Mach-O)
+    #[arg(long)]
+    no_exe_check: bool,
+
+    /// Compress the whole bundle, launcher included, behind a small stub that
+    /// decompresses it into memory at startup (smaller output, slower start;
+    /// Linux only, needs gcc and zlib)
+    #[arg(long)]
+    pack_launcher: bool,
+
+    /// Write only the resources, manifest and footer, without a launcher,
+    /// for a host program of your own that reads the footer
+    #[arg(long)]
+    no_launcher: bool,
+
+    /// Permissions the launcher sets on the executable it runs, in octal
+    /// [default: 0755]
+    #[arg(long, value_name = "MODE", value_parser = sekaipack::manifest::parse_entry_mode)]
+    entry_mode: Option<u32>,
+
+    /// Reuse the launcher of a previously built bundle (or a bare launcher)
+    /// instead of embedding or compiling a new one
+    #[arg(long, value_name = "PATH")]
+    reuse_launcher: Option<String>,
+
+    /// Build cache: compress resources in chunks keyed by their content hash
+    /// and reuse the chunks of unchanged files from earlier builds
+    #[arg(long, value_name = "DIR")]
+    cache_dir: Option<String>,
+
+    /// Skip resource files matching a glob pattern (repeatable)
+    #[arg(long, value_name = "GLOB")]
+    exclude: Vec<String>,
+}
+
+fn parse_launcher(name: &str) -> Result<Launcher, String> {
+    Launcher::parse(name).ok_or_else(|| "expected rust or c".to_string())
+}
+
+fn parse_target_os(name: &str) -> Result<TargetOs, String> {
+    TargetOs::parse(name).ok_or_else(|| "expected linux, windows or macos".to_string())
+}
+
+fn parse_format(name: &str) -> Result<Layout, String> {
+    Layout::parse(name).ok_or_else(|| "expected self-extract or tarball".to_string())
+}
+
+fn parse_workdir(name: &str) -> Result<WorkDir, String> {
+    WorkDir::parse(name).ok_or_else(|| "expected invocation or temp".to_string())
+}
+
+fn parse_compression(name: &str) -> Result<Compression, String> {
+    Compression::parse(name).ok_or_else(|| "expected gzip, zstd, brotli or none".to_string())
+}
+
+fn parse_checksum(name: &str) -> Result<Checksum, String> {
+    Checksum::parse(name).ok_or_else(|| "expected crc32 or sha256".to_string())
+}
+
+fn parse_cache_hash(name: &str) -> Result<CacheHash, String> {
+    CacheHash::parse(name).ok_or_else(|| "expected blake3 or sha256".to_string())
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let cli = Cli::parse_from(subcommand_first(std::env::args_os().collect()));
+    init_logger(cli.quiet, cli.verbose);
 
-    if args.len() < 2 {
-        eprintln!("Sekaipack v1.0 - Package sekai with resources");
-        eprintln!(
-            "Usage: {} <main_executable> [resource_dirs...] [-o output]",
-            args[0]
-        );
-        eprintln!(
-            "Example: {} test_env/sekai.x86_64 test_env/script test_env/sounds -o example_game",
-            args[0]
-        );
+    let result = match cli.command {
+        Some(Commands::List { bundle, json, data }) => list_bundle(&bundle, json, data),
+        Some(Commands::Verify { bundle, json }) => verify_bundle(&bundle, json),
+        Some(Commands::Extract {
+            bundle,
+            dest_dir,
+            manifest_only,
+        }) => match dest_dir {
+            _ if manifest_only => extract_manifest(&bundle, dest_dir.as_deref()),
+            Some(dest_dir) => extract_bundle(&bundle, &dest_dir),
+            None => unreachable!("clap requires dest_dir without --manifest-only"),
+        },
+        Some(Commands::Update {
+            bundle,
+            resource_dirs,
+        }) => update_bundle(&bundle, &resource_dirs),
+        Some(Commands::Bench { bundle, iterations }) => bench_bundle(&bundle, iterations),
+        Some(Commands::Join { first_part, output }) => join_bundle(&first_part, output.as_deref()),
+        None => pack(cli.pack),
+    };
+
+    if let Err(e) = result {
+        error!("{}", e);
         std::process::exit(1);
     }
+}
 
-    let main_exe = &args[1];
-    let output: String = if args.contains(&"-o".to_string()) {
-        let pos = args.iter().position(|x| x == "-o").unwrap();
-        args[pos + 1].clone()
-    } else {
-        "example_game".to_string()
-    };
-    let mut resource_dirs = Vec::new();
-
-    // 解析参数
-    let mut i = 2;
-    while i < args.len() {
-        if args[i] == "-o" {
-            i += 2;
-        } else {
-            resource_dirs.push(&args[i]);
-            i += 1;
+// 打包参数里的位置参数 main_exe 会吞掉写在全局选项之后的子命令名（如 `sekaipack -q list
+// bundle`），因此在解析前把第一个非全局选项参数如果是子命令名就移到最前面；全局选项在子命令
+// 之后同样有效
+fn subcommand_first(mut args: Vec<OsString>) -> Vec<OsString> {
+    let command = Cli::command();
+    let position = args.iter().skip(1).position(|arg| {
+        let arg = arg.to_string_lossy();
+        arg == "--" || !is_global_flag(&arg)
+    });
+    if let Some(position) = position.map(|position| position + 1)
+        && position > 1
+    {
+        let name = args[position].to_string_lossy();
+        let is_subcommand = command
+            .get_subcommands()
+            .any(|sub| sub.get_name() == name || sub.get_all_aliases().any(|alias| alias == name));
+        if is_subcommand {
+            let subcommand = args.remove(position);
+            args.insert(1, subcommand);
         }
     }
+    args
+}
 
-    println!("Sekaipack v1.0");
-    println!("Packaging: {} -> {}", main_exe, output);
+// -q/--quiet 与 -v/--verbose（可写成 -vv、-qv 等组合短选项）
+fn is_global_flag(arg: &str) -> bool {
+    match arg.strip_prefix("--") {
+        Some(long) => long == "quiet" || long == "verbose",
+        None => {
+            arg.len() > 1 && arg.starts_with('-') && arg[1..].chars().all(|c| c == 'q' || c == 'v')
+        }
+    }
+}
 
-    // 检查主程序是否存在
-    if !Path::new(main_exe).exists() {
-        eprintln!("Error: Main executable '{}' not found", main_exe);
-        std::process::exit(1);
+// 所有子命令共用的日志级别：-q 只输出错误，默认为 info，-v 为 debug，-vv 为 trace；
+// 未指定 -q/-v 时可通过 RUST_LOG 调整，指定时覆盖 RUST_LOG 中的全局级别
+fn init_logger(quiet: bool, verbose: u8) {
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info"));
+    let level = match verbose {
+        _ if quiet => Some(LevelFilter::Error),
+        0 => None,
+        1 => Some(LevelFilter::Debug),
+        _ => Some(LevelFilter::Trace),
+    };
+    if let Some(level) = level {
+        builder.filter_level(level);
     }
+    builder
+        .format(|buf, record| match record.level() {
+            Level::Info => writeln!(buf, "{}", record.args()),
+            level => writeln!(buf, "{}: {}", level, record.args()),
+        })
+        .init();
+}
 
-    // 开始打包
-    match create_bundled_app(main_exe, &resource_dirs, &output) {
-        Ok(()) => {
-            println!("Successfully created: {}", output);
-        }
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            std::process::exit(1);
+// 读取 --config 指定的配置文件，未指定时使用当前目录中的 sekai-pack.toml（如果存在）
+fn load_config(path: Option<&str>) -> Result<Option<Config>, Box<dyn std::error::Error>> {
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None if Path::new(config::DEFAULT_CONFIG).is_file() => {
+            PathBuf::from(config::DEFAULT_CONFIG)
         }
-    }
+        None => return Ok(None),
+    };
+    Ok(Some(Config::load(&path)?))
 }
 
-fn create_bundled_app(
-    main_exe: &str,
-    resource_dirs: &[&String],
-    output_file: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // 启动器源码（编译时嵌入）
-    const LAUNCHER_C: &str = include_str!("launcher.c");
+fn pack(args: PackArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let config = load_config(args.config.as_deref())?;
+    if args.main_exe.is_none() && config.is_none() {
+        // 既没有参数也没有配置文件时显示帮助
+        Cli::command().print_help()?;
+        std::process::exit(2);
+    }
+    let config = config.unwrap_or_default();
 
-    // 临时目录
-    let temp_dir = "temp_build";
-    fs::create_dir_all(temp_dir)?;
+    // 命令行参数优先于配置文件
+    let main_exe = match args.main_exe {
+        Some(main_exe) => PathBuf::from(main_exe),
+        None => config
+            .main_exe
+            .clone()
+            .ok_or("missing main executable (pass it or set main_exe in the config file)")?,
+    };
+    let mut resource_dirs: Vec<PathBuf> = if args.resource_dirs.is_empty() {
+        config.resource_dirs.clone()
+    } else {
+        args.resource_dirs.iter().map(PathBuf::from).collect()
+    };
+    // 列表中的路径与资源目录一起打包，不存在的路径同样在打包前一并报告
+    let files_from = args
+        .files_from
+        .map(PathBuf::from)
+        .or(config.files_from.clone());
+    if let Some(list) = files_from {
+        resource_dirs.extend(config::read_file_list(&list)?);
+    }
+    let mut opts = config.bundle_options();
+    opts.launcher = args.launcher.unwrap_or(opts.launcher);
+    opts.target_os = args.target_os.or(opts.target_os);
+    opts.format = args.format.unwrap_or(opts.format);
+    opts.store_aligned |= args.store_aligned;
+    // --store-aligned 默认不压缩
+    opts.compression = match args.compression.or(config.compression) {
+        Some(compression) => compression,
+        None if opts.store_aligned => Compression::None,
+        None => opts.compression,
+    };
+    opts.exe_compression = args.compress_executable.or(opts.exe_compression);
+    opts.level = args.level.or(opts.level);
+    opts.threads = args.threads.or(opts.threads);
+    opts.checksum = args.checksum.unwrap_or(opts.checksum);
+    opts.entry_checksums |= args.entry_checksums;
+    opts.cache |= args.cache;
+    opts.cache_hash = args.cache_hash.unwrap_or(opts.cache_hash);
+    if !args.exclude.is_empty() {
+        opts.exclude = args.exclude;
+    }
+    opts.entry_name = args.entry_name.or(opts.entry_name);
+    if !args.extra_exes.is_empty() {
+        opts.extra_exes = args.extra_exes.iter().map(PathBuf::from).collect();
+    }
+    if !args.append_data.is_empty() {
+        opts.append_data = args.append_data.iter().map(PathBuf::from).collect();
+    }
+    opts.entry = args.entry.or(opts.entry);
+    opts.launch_arg_template = args.launch_arg_template.or(opts.launch_arg_template);
+    opts.pre_launch = args.pre_launch.or(opts.pre_launch);
+    if !args.env.is_empty() {
+        opts.env = args.env;
+    }
+    if let Some(path) = args.notice.map(PathBuf::from).or(config.notice.clone()) {
+        opts.notice = Some(config::read_notice(&path)?);
+    }
+    opts.temp_prefix = args.temp_prefix.or(opts.temp_prefix);
+    opts.static_launcher |= args.static_launcher;
+    opts.follow_symlinks |= args.follow_symlinks;
+    opts.dedup |= args.dedup;
+    opts.ignore_missing |= args.ignore_missing;
+    opts.prefer_memory |= args.prefer_memory;
+    opts.set_library_path |= args.set_library_path;
+    opts.split_size = args.split_size.or(opts.split_size);
+    opts.reproducible |= args.reproducible;
+    opts.strip |= args.strip;
+    opts.no_exec_bit |= args.no_exec_bit;
+    opts.no_exe_check |= args.no_exe_check;
+    opts.pack_launcher |= args.pack_launcher;
+    opts.no_launcher |= args.no_launcher;
+    opts.entry_mode = args.entry_mode.unwrap_or(opts.entry_mode);
+    opts.workdir = args.workdir.unwrap_or(opts.workdir);
+    if let Some(path) = args.reuse_launcher {
+        opts.reuse_launcher = Some(PathBuf::from(path));
+    }
+    if let Some(dir) = args.cache_dir {
+        opts.cache_dir = Some(PathBuf::from(dir));
+    }
+    opts.dry_run = args.dry_run;
+    opts.keep_build = args.keep_build;
+    opts.force |= args.force;
 
-    // 写入启动器源码
-    fs::write(format!("{}/launcher.c", temp_dir), LAUNCHER_C)?;
+    let windows = opts.target_os.or(TargetOs::NATIVE) == Some(TargetOs::Windows);
+    let layout = if opts.no_launcher {
+        Layout::NoLauncher
+    } else {
+        opts.format
+    };
+    let output = match args.output.map(PathBuf::from).or(config.output.clone()) {
+        Some(output) => output,
+        None => {
+            let dir = args.output_dir.map(PathBuf::from).or(config.output_dir);
+            let name = default_output_name(&main_exe, layout, windows);
+            match dir {
+                Some(dir) => {
+                    if !args.dry_run {
+                        fs::create_dir_all(&dir)?;
+                    }
+                    dir.join(name)
+                }
+                None => name,
+            }
+        }
+    };
+    // Windows 通过扩展名识别可执行文件
+    let output = if windows && layout == Layout::SelfExtract && output.extension().is_none() {
+        output.with_extension("exe")
+    } else {
+        output
+    };
 
-    // 编译启动器
-    println!("Compiling launcher...");
-    let output = Command::new("gcc")
-        .args(&[
-            "-o",
-            &format!("{}/launcher", temp_dir),
-            &format!("{}/launcher.c", temp_dir),
-            "-lz",
-        ])
-        .output()?;
+    if args.encrypt {
+        opts.passphrase = Some(read_new_passphrase()?);
+    }
 
-    if !output.status.success() {
-        eprintln!("GCC compilation failed:");
-        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-        return Err("Failed to compile launcher".into());
+    info!("Sekaipack v{}", env!("CARGO_PKG_VERSION"));
+    info!("Packaging: {} -> {}", main_exe.display(), output.display());
+    if resource_dirs.is_empty() {
+        info!("No resources given, bundling only the executables");
     }
 
-    // 创建资源包
-    println!("Creating resource package...");
-    let resource_file = format!("{}/resources.tar.gz", temp_dir);
-    create_resource_package(main_exe, resource_dirs, &resource_file)?;
+    // 仅在标准错误为终端时显示进度条；--quiet 或输出调试日志（-v）时不显示，避免与日志交错
+    let show_progress = !args.no_progress
+        && !args.dry_run
+        && log::log_enabled!(Level::Info)
+        && !log::log_enabled!(Level::Debug)
+        && std::io::stderr().is_terminal();
+    let mut bar = None;
+    let mut report = |progress: &Progress| {
+        if show_progress {
+            let bar = bar.get_or_insert_with(|| new_progress_bar(progress.total_bytes));
+            bar.set_position(progress.bytes);
+            bar.set_message(format!("{}/{} files", progress.files, progress.total_files));
+            if progress.files == progress.total_files {
+                bar.finish_and_clear();
+            }
+        }
+    };
 
-    // 读取启动器和资源
-    let launcher_binary = fs::read(&format!("{}/launcher", temp_dir))?;
-    let resource_data = fs::read(&resource_file)?;
+    // 开始打包
+    sekaipack::bundle_with_progress(&main_exe, &resource_dirs, &output, opts, &mut report)?;
+    if !args.dry_run {
+        info!("Successfully created: {}", output.display());
+    }
 
-    // 创建最终的可执行文件
-    println!("Creating final executable...");
-    {
-        use std::io::Write;
-        let mut final_exe = fs::File::create(output_file)?;
+    Ok(())
+}
+
+// 未指定 -o 时按主程序的文件名命名输出：扩展名替换为 .bundle（tar包为 .bundle.tar，
+// 不含启动器时为 .bundle.bin），打包给 Windows 的自解压文件再加上 .exe
+fn default_output_name(main_exe: &Path, format: Layout, windows: bool) -> PathBuf {
+    let mut name = main_exe.file_stem().unwrap_or("bundle".as_ref()).to_owned();
+    name.push(match format {
+        Layout::SelfExtract if windows => ".bundle.exe",
+        Layout::SelfExtract | Layout::Packed => ".bundle",
+        Layout::Tarball => ".bundle.tar",
+        Layout::NoLauncher => ".bundle.bin",
+    });
+    PathBuf::from(name)
+}
 
-        // 写入启动器
-        final_exe.write_all(&launcher_binary)?;
+fn new_progress_bar(total_bytes: u64) -> ProgressBar {
+    let style = ProgressStyle::with_template("{bar:40} {bytes}/{total_bytes} {msg}")
+        .expect("valid progress template");
+    ProgressBar::new(total_bytes)
+        .with_style(style)
+        .with_finish(ProgressFinish::AndClear)
+}
 
-        // 记录资源偏移
-        let resource_offset = launcher_binary.len();
+// 交互输入时要求输入两次，避免口令输错后无法解密
+fn read_new_passphrase() -> Result<String, Box<dyn std::error::Error>> {
+    if std::env::var_os(encryption::PASSPHRASE_ENV).is_some() {
+        return Ok(encryption::read_passphrase("")?);
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        return Err("passphrases do not match".into());
+    }
+    Ok(passphrase)
+}
 
-        // 写入资源数据
-        final_exe.write_all(&resource_data)?;
+// 只有加密的打包文件才需要口令
+fn bundle_passphrase(path: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match sekaipack::manifest(Path::new(path))?.encryption {
+        Encryption::None => Ok(None),
+        Encryption::Aes256Gcm => Ok(Some(encryption::read_passphrase("Passphrase: ")?)),
+    }
+}
 
-        // 写入偏移信息（8字节）
-        final_exe.write_all(&(resource_offset as u64).to_le_bytes())?;
+fn list_bundle(path: &str, json: bool, data: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = bundle_passphrase(path)?;
+    if json || data {
+        let bundle = sekaipack::open_bundle(Path::new(path), passphrase.as_deref())?;
+        let entries: Vec<_> = if data {
+            bundle.data_entries().collect()
+        } else {
+            bundle.entries().collect()
+        };
+        if !json {
+            for entry in entries {
+                println!("{:>12}  {}", entry.size, entry.path.display());
+            }
+            return Ok(());
+        }
+        let output = serde_json::json!({
+            "manifest": bundle.manifest(),
+            "entries": entries,
+        });
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    for (entry_path, size) in sekaipack::list(Path::new(path), passphrase.as_deref())? {
+        println!("{:>12}  {}", size, entry_path.display());
     }
+    Ok(())
+}
 
-    // 设置执行权限
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = fs::metadata(output_file)?.permissions();
-        perms.set_mode(0o755);
-        fs::set_permissions(output_file, perms)?;
+fn verify_bundle(path: &str, json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    if json {
+        return verify_bundle_json(path);
     }
+    let passphrase = bundle_passphrase(path)?;
+    let report = sekaipack::verify(Path::new(path), passphrase.as_deref())?;
+    println!(
+        "OK: {} files, {} bytes ({} executables, {} resource files)",
+        report.files,
+        report.bytes,
+        report.executables,
+        report.files - report.executables
+    );
+    let launcher = match report.launcher {
+        Some(launcher) => format!("{} launcher", launcher.name()),
+        None if report.layout == Layout::NoLauncher => "no launcher".to_string(),
+        None => "unrecognized launcher".to_string(),
+    };
+    println!(
+        "Built by sekaipack {} ({}, format v{})",
+        report.tool_version,
+        launcher,
+        sekaipack::footer::VERSION
+    );
+    Ok(())
+}
 
-    // 清理临时文件
-    fs::remove_dir_all(temp_dir)?;
+// 校验失败时同样输出JSON（`ok` 为 false，附带错误信息），再以非零退出码退出
+fn verify_bundle_json(path: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let bundle = Path::new(path);
+    let result = bundle_passphrase(path)
+        .and_then(|passphrase| Ok(sekaipack::verify(bundle, passphrase.as_deref())?));
+    let manifest = sekaipack::manifest(bundle).ok();
+    let mut output = serde_json::json!({
+        "ok": result.is_ok(),
+        "manifest": manifest,
+    });
+    if let Some(manifest) = &manifest {
+        let computed = match &result {
+            Ok(report) => Some(report.checksum.clone()),
+            Err(_) => sekaipack::resource_checksum(bundle).ok(),
+        };
+        output["checksum_type"] = serde_json::json!(manifest.checksum_type);
+        output["stored_checksum"] = serde_json::json!(manifest.checksum);
+        output["computed_checksum"] = serde_json::json!(computed);
+    }
+    match &result {
+        Ok(report) => {
+            output["entries"] = serde_json::json!(report.entries);
+            output["files"] = serde_json::json!(report.files);
+            output["bytes"] = serde_json::json!(report.bytes);
+            output["executables"] = serde_json::json!(report.executables);
+            output["resource_files"] = serde_json::json!(report.files - report.executables);
+            output["tool_version"] = serde_json::json!(report.tool_version);
+            output["launcher"] = serde_json::json!(report.launcher.map(Launcher::name));
+            output["format_version"] = serde_json::json!(sekaipack::footer::VERSION);
+        }
+        Err(e) => output["error"] = serde_json::json!(e.to_string()),
+    }
+    println!("{}", serde_json::to_string_pretty(&output)?);
+    result.map(|_| ())
+}
 
+fn extract_bundle(path: &str, dest_dir: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = bundle_passphrase(path)?;
+    sekaipack::extract(Path::new(path), Path::new(dest_dir), passphrase.as_deref())?;
+    info!("Extracted {} -> {}", path, dest_dir);
     Ok(())
 }
 
-fn create_resource_package(
-    main_exe: &str,
-    resource_dirs: &[&String],
-    output_file: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // use std::process::Stdio;
-
-    // 创建临时目录结构
-    let temp_structure = "temp_structure";
-    fs::create_dir_all(temp_structure)?;
-
-    // 复制主程序
-    let _main_path = Path::new(main_exe);
-    fs::copy(main_exe, format!("{}/sekai.x86_64", temp_structure))?;
-
-    // 复制资源目录
-    for dir in resource_dirs {
-        let dir_path = Path::new(dir);
-        if dir_path.exists() && dir_path.is_dir() {
-            let output = Command::new("cp")
-                .args(&["-r", dir, &format!("{}/", temp_structure)])
-                .output()?;
-            if !output.status.success() {
-                return Err(format!("Failed to copy directory: {}", dir).into());
-            }
+// 只通过尾部定位并读取清单，不读取资源数据
+fn extract_manifest(path: &str, dest_dir: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let manifest = sekaipack::manifest(Path::new(path))?;
+    let json = serde_json::to_string_pretty(&manifest)?;
+    match dest_dir {
+        Some(dest_dir) => {
+            fs::create_dir_all(dest_dir)?;
+            let dest = Path::new(dest_dir).join(sekaipack::manifest::TARBALL_MANIFEST);
+            fs::write(&dest, json + "\n")?;
+            info!("Extracted manifest of {} -> {}", path, dest.display());
         }
+        None => println!("{}", json),
     }
+    Ok(())
+}
 
-    // 创建tar.gz包
-    let output = Command::new("tar")
-        .args(&["-czf", output_file, "-C", temp_structure, "."])
-        .output()?;
+// 每次解压到新的临时目录并计时，测量后删除；`extract` 与启动器共用 `sekaipack::unpack`
+// 的解压流程，因此在进程内即可测出启动器的解压耗时，不运行打包文件
+fn bench_bundle(path: &str, iterations: u32) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = bundle_passphrase(path)?;
+    let mut times = Vec::new();
+    let mut peak_size = 0;
+    for i in 0..iterations {
+        let temp_dir = tempfile::Builder::new()
+            .prefix("sekaipack_bench_")
+            .tempdir()?;
+        let started = Instant::now();
+        sekaipack::extract(Path::new(path), temp_dir.path(), passphrase.as_deref())?;
+        let elapsed = started.elapsed();
+        peak_size = peak_size.max(dir_size(temp_dir.path())?);
+        debug!("Iteration {}: {:.3}s", i + 1, elapsed.as_secs_f64());
+        times.push(elapsed);
+    }
 
-    if !output.status.success() {
-        eprintln!("Tar creation failed:");
-        eprintln!("{}", String::from_utf8_lossy(&output.stderr));
-        return Err("Failed to create resource package".into());
+    times.sort();
+    let mean = times.iter().sum::<Duration>() / iterations;
+    let middle = times.len() / 2;
+    let median = if times.len() % 2 == 0 {
+        (times[middle - 1] + times[middle]) / 2
+    } else {
+        times[middle]
+    };
+    println!(
+        "Extracted {} times: mean {:.3}s, median {:.3}s, min {:.3}s, max {:.3}s",
+        iterations,
+        mean.as_secs_f64(),
+        median.as_secs_f64(),
+        times[0].as_secs_f64(),
+        times[times.len() - 1].as_secs_f64()
+    );
+    println!("Peak temp size: {} bytes", peak_size);
+    Ok(())
+}
+
+// 目录中所有文件的总大小，不跟随符号链接
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut total = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        total += if metadata.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            metadata.len()
+        };
     }
+    Ok(total)
+}
 
-    // 清理临时目录
-    fs::remove_dir_all(temp_structure)?;
+fn join_bundle(first_part: &str, output: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let output = match output {
+        Some(output) => output,
+        None => first_part.strip_suffix(".000").unwrap_or(first_part),
+    };
+    sekaipack::join(Path::new(first_part), Path::new(output))?;
+    info!("Joined {} -> {}", first_part, output);
+    Ok(())
+}
 
+fn update_bundle(path: &str, resource_dirs: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let passphrase = bundle_passphrase(path)?;
+    let resource_dirs: Vec<PathBuf> = resource_dirs.iter().map(PathBuf::from).collect();
+    info!("Updating resources of {}", path);
+    sekaipack::update(
+        Path::new(path),
+        &resource_dirs,
+        passphrase.as_deref(),
+        &mut |_| {},
+    )?;
+    info!("Successfully updated: {}", path);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<OsString> {
+        args.iter().map(OsString::from).collect()
+    }
+
+    #[test]
+    fn global_flags_before_subcommand() {
+        assert_eq!(
+            subcommand_first(args(&["sekaipack", "-q", "list", "game.bundle"])),
+            args(&["sekaipack", "list", "-q", "game.bundle"])
+        );
+        assert_eq!(
+            subcommand_first(args(&[
+                "sekaipack",
+                "-vv",
+                "--quiet",
+                "verify",
+                "game.bundle"
+            ])),
+            args(&["sekaipack", "verify", "-vv", "--quiet", "game.bundle"])
+        );
+        let cli = Cli::parse_from(subcommand_first(args(&["sekaipack", "-v", "verify", "b"])));
+        assert_eq!(cli.verbose, 1);
+        assert!(matches!(cli.command, Some(Commands::Verify { .. })));
+    }
+
+    #[test]
+    fn pack_arguments_unchanged() {
+        let pack = args(&["sekaipack", "-q", "game.x86_64", "list"]);
+        assert_eq!(subcommand_first(pack.clone()), pack);
+        let pack = args(&["sekaipack", "-o", "list", "game.x86_64"]);
+        assert_eq!(subcommand_first(pack.clone()), pack);
+    }
+
+    #[test]
+    fn dangling_output_flag() {
+        for flag in ["-o", "--output"] {
+            let error =
+                Cli::try_parse_from(subcommand_first(args(&["sekaipack", "app.bin", flag])))
+                    .err()
+                    .unwrap();
+            assert_eq!(error.kind(), clap::error::ErrorKind::InvalidValue);
+            assert!(error.to_string().contains("--output <OUTPUT>"));
+            assert_eq!(error.exit_code(), 2);
+        }
+    }
+}
@@ -0,0 +1,510 @@
+// 打包文件的清单：以JSON形式位于资源数据与尾部之间，描述资源的位置与启动方式
+//
+// C启动器只解析扁平的JSON对象，因此清单中只能出现字符串、整数与布尔值。
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Component, Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::BundleError;
+use crate::checksum::{CacheHash, Checksum};
+use crate::compression::Compression;
+use crate::encryption::Encryption;
+use crate::footer::{FOOTER_LEN, Footer};
+
+// 启动参数模板中代表解压目录的占位符
+pub const DIR_PLACEHOLDER: &str = "{dir}";
+
+/// 启动器执行的可执行文件默认的权限
+pub const DEFAULT_ENTRY_MODE: u32 = 0o755;
+
+/// 启动器自身失败时使用的保留退出码，其余退出码均原样来自主程序（被信号终止时为 128 + 信号值）
+/// 无法创建临时目录、读取或解压资源
+pub const EXIT_EXTRACT_FAILED: i32 = 120;
+/// 打包文件无效或已损坏：尾部、清单或校验和不符，或无法解密
+pub const EXIT_INTEGRITY_FAILED: i32 = 121;
+/// 无法执行主程序
+pub const EXIT_EXEC_FAILED: i32 = 122;
+/// 启动前命令无法执行或以非零状态退出
+pub const EXIT_PRE_LAUNCH_FAILED: i32 = 123;
+
+/// 启动前命令运行时，解压目录的路径所在的环境变量
+pub const PRE_LAUNCH_DIR_ENV: &str = "SEKAI_PACK_DIR";
+
+// 清单大小上限，避免损坏的尾部导致读取大量数据
+const MAX_MANIFEST_LEN: u64 = 1024 * 1024;
+
+/// 旧版清单没有记录解压目录前缀时使用的前缀
+pub const DEFAULT_TEMP_PREFIX: &str = "bundled_app_";
+
+/// `--append-data` 附加的文件在资源包中所在的目录，启动器通过环境变量告知主程序解压后的位置
+pub const DATA_DIR: &str = ".sekai-pack-data";
+pub const DATA_DIR_ENV: &str = "SEKAI_PACK_DATA";
+
+/// `--store-aligned` 时启动器把文件索引写入解压目录中的此文件，并通过环境变量告知主程序
+/// 索引与打包文件的位置，主程序可据此直接映射打包文件中的资源
+pub const ALIGNED_INDEX_FILE: &str = ".sekai-pack-index";
+pub const ALIGNED_INDEX_ENV: &str = "SEKAI_PACK_INDEX";
+pub const BUNDLE_ENV: &str = "SEKAI_PACK_BUNDLE";
+
+/// `--pack-launcher` 的解压桩在内存中启动解压出的打包文件时，通过此环境变量传入原打包文件的路径；
+/// 此时启动器从 `/proc/self/exe` 读取自身
+pub const PACKED_ENV: &str = "SEKAI_PACK_PACKED";
+
+/// tar包布局中启动器、资源数据与清单的成员名
+pub const TARBALL_LAUNCHER: &str = "launcher";
+pub const TARBALL_RESOURCES: &str = "resources.pack";
+pub const TARBALL_MANIFEST: &str = "manifest.json";
+
+/// 打包文件的布局，决定清单中资源偏移的含义
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `[启动器][资源数据][清单][尾部]` 拼接而成的自解压可执行文件，偏移相对于文件开头
+    #[default]
+    SelfExtract,
+    /// 普通tar包，启动器、资源数据与清单（不含尾部）为独立成员，偏移相对于资源数据成员
+    Tarball,
+    /// `--pack-launcher` 生成的 `[解压桩][gzip压缩的自解压文件][清单][尾部]`，
+    /// 资源数据为压缩后的完整自解压文件（含启动器）
+    Packed,
+    /// `--no-launcher` 生成的 `[资源数据][清单][尾部]`，不含启动器，偏移相对于其开头；
+    /// 供能自行读取尾部的宿主程序附加在其后
+    NoLauncher,
+}
+
+impl Layout {
+    /// 解析 `--format` 的取值；`packed` 与 `no-launcher` 只能由同名的选项生成
+    pub fn parse(name: &str) -> Option<Layout> {
+        match name {
+            "self-extract" => Some(Layout::SelfExtract),
+            "tarball" => Some(Layout::Tarball),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Layout::SelfExtract => "self-extract",
+            Layout::Tarball => "tarball",
+            Layout::Packed => "packed",
+            Layout::NoLauncher => "no-launcher",
+        }
+    }
+}
+
+/// 启动器运行主程序时的工作目录
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum WorkDir {
+    /// 运行打包文件时所在的目录（旧版打包文件同样如此）
+    Invocation,
+    /// 资源解压目录
+    #[default]
+    Temp,
+}
+
+impl WorkDir {
+    pub fn parse(name: &str) -> Option<WorkDir> {
+        match name {
+            "invocation" => Some(WorkDir::Invocation),
+            "temp" => Some(WorkDir::Temp),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WorkDir::Invocation => "invocation",
+            WorkDir::Temp => "temp",
+        }
+    }
+}
+
+/// 打包文件的清单
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Manifest {
+    /// 生成打包文件的 sekaipack 版本
+    pub tool_version: String,
+    /// 打包时间（Unix 时间戳，秒）
+    pub created_at: u64,
+    /// 启动器在解压目录中执行的主程序文件名
+    pub entry_name: String,
+    /// 主程序的架构（如 `x86_64`、`aarch64`），启动器据此检查自身能否运行它；无法识别或旧版打包文件中为空
+    #[serde(default)]
+    pub arch: String,
+    /// 以空白分隔的启动参数模板，`{dir}` 替换为解压目录
+    pub launch_args: String,
+    /// 资源数据的压缩方式
+    pub compression: Compression,
+    /// 资源数据的校验和算法
+    pub checksum_type: Checksum,
+    /// 资源数据的校验和（小写十六进制）
+    pub checksum: String,
+    /// 解压到持久缓存目录并在之后的启动中复用
+    pub cache: bool,
+    /// 缓存目录名中资源哈希的算法；旧版打包文件中没有此字段，使用当时的sha256
+    #[serde(default = "default_cache_hash")]
+    pub cache_hash: CacheHash,
+    /// 资源数据的加密方式，校验和针对加密后的数据计算
+    #[serde(default)]
+    pub encryption: Encryption,
+    /// 派生密钥使用的盐（十六进制），未加密时为空
+    #[serde(default)]
+    pub kdf_salt: String,
+    /// 加密使用的随机数（十六进制），未加密时为空
+    #[serde(default)]
+    pub nonce: String,
+    /// 打包文件的布局
+    #[serde(default)]
+    pub layout: Layout,
+    /// 主程序的工作目录；旧版打包文件中没有此字段，使用当时的 `invocation`
+    #[serde(default = "default_workdir")]
+    pub workdir: WorkDir,
+    /// 包内所有可执行文件的文件名，以 `/` 分隔，第一个为主程序；旧版打包文件中为空
+    #[serde(default)]
+    pub executables: String,
+    /// 启动器临时解压目录名的前缀（其后为随机字符）；旧版打包文件中为空
+    #[serde(default)]
+    pub temp_prefix: String,
+    /// `--append-data` 附加的文件名，以 `/` 分隔，文件位于资源包的 [`DATA_DIR`] 中
+    #[serde(default)]
+    pub data_files: String,
+    /// 启动器在解压后为执行的可执行文件设置的权限
+    #[serde(default = "default_entry_mode")]
+    pub entry_mode: u32,
+    /// 解压后、启动主程序前在解压目录中由shell执行的命令，为空时不执行
+    #[serde(default)]
+    pub pre_launch: String,
+    /// 资源不超过内存预算时优先解压到内存文件系统
+    #[serde(default)]
+    pub prefer_memory: bool,
+    /// 启动器把解压目录加到主程序的动态库搜索路径最前面（`LD_LIBRARY_PATH`、`DYLD_LIBRARY_PATH` 或 `PATH`）
+    #[serde(default)]
+    pub set_library_path: bool,
+    /// `--env` 设置的环境变量，每行为 "KEY=VALUE"，覆盖运行环境中的同名变量
+    #[serde(default)]
+    pub env: String,
+    /// 以 `--about` 运行时启动器输出的声明文本，为空时 `--about` 照常传给主程序
+    #[serde(default)]
+    pub notice: String,
+    /// 资源包中所有文件的未压缩总大小；旧版打包文件中为0
+    #[serde(default)]
+    pub unpacked_size: u64,
+    /// `--store-aligned` 时每个文件内容在打包文件中的位置，每行为 "<偏移> <长度> <包内路径>"
+    #[serde(default)]
+    pub aligned_index: String,
+    /// `--entry-checksums` 时每个文件内容的校验和（算法同 `checksum_type`），每行为 "<校验和> <包内路径>"
+    #[serde(default)]
+    pub entry_checksums: String,
+    /// `--dedup` 时未写入资源包的重复文件，每行为 "<包内路径>\t<内容与权限相同的原文件的包内路径>"，
+    /// 解压后以硬链接（或复制）重建
+    #[serde(default)]
+    pub dedup: String,
+    /// 可执行文件单独压缩时该段的压缩方式，`None` 时可执行文件与资源位于同一个tar流中
+    /// （此时不写入清单，C启动器不接受 `null`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exe_compression: Option<Compression>,
+    /// 单独压缩的可执行文件段的长度，该段位于（解密后的）资源数据开头
+    #[serde(default)]
+    pub exe_section_len: u64,
+    /// 资源数据起始偏移
+    pub resource_offset: u64,
+    /// 资源数据长度
+    pub resource_len: u64,
+}
+
+impl Manifest {
+    // 清单紧跟在资源数据之后
+    pub fn resource_end(&self) -> u64 {
+        self.resource_offset + self.resource_len
+    }
+
+    /// 长度为 `payload_len` 的（解密后的）资源数据中各段的压缩方式与范围，
+    /// 依次解压后拼接起来即为完整的tar流
+    pub fn sections(&self, payload_len: u64) -> Vec<(Compression, Range<u64>)> {
+        match self.exe_compression {
+            Some(exe_compression) => {
+                let split = self.exe_section_len.min(payload_len);
+                vec![
+                    (exe_compression, 0..split),
+                    (self.compression, split..payload_len),
+                ]
+            }
+            None => vec![(self.compression, 0..payload_len)],
+        }
+    }
+
+    /// 包内可执行文件的文件名，第一个为主程序；旧版打包文件中为空
+    pub fn executable_names(&self) -> Vec<&str> {
+        self.executables
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// 资源包中位于 `path` 的文件是否为可执行文件；旧版打包文件中资源包根部的文件都是可执行文件
+    pub fn is_executable_entry(&self, path: &Path) -> bool {
+        let names = self.executable_names();
+        path.components().count() == 1
+            && (names.is_empty() || path.to_str().is_some_and(|name| names.contains(&name)))
+    }
+
+    /// 临时解压目录名的前缀，旧版打包文件为 [`DEFAULT_TEMP_PREFIX`]
+    pub fn temp_prefix(&self) -> &str {
+        if self.temp_prefix.is_empty() {
+            DEFAULT_TEMP_PREFIX
+        } else {
+            &self.temp_prefix
+        }
+    }
+
+    /// `--append-data` 附加的文件名
+    pub fn data_file_names(&self) -> Vec<&str> {
+        self.data_files
+            .split('/')
+            .filter(|name| !name.is_empty())
+            .collect()
+    }
+
+    /// 资源包中位于 `path` 的文件是否为 `--append-data` 附加的文件
+    pub fn is_data_entry(&self, path: &Path) -> bool {
+        let mut components = path.components();
+        components.next() == Some(Component::Normal(DATA_DIR.as_ref()))
+            && components.next().is_some_and(|name| {
+                name.as_os_str()
+                    .to_str()
+                    .is_some_and(|name| self.data_file_names().contains(&name))
+            })
+            && components.next().is_none()
+    }
+
+    /// `--env` 设置的环境变量名与值
+    pub fn env_vars(&self) -> Vec<(&str, &str)> {
+        self.env
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .collect()
+    }
+
+    /// 按包内路径（非UTF-8的字节按有损转换）查找文件校验和的表，未记录时为空
+    pub fn entry_checksum_map(&self) -> HashMap<String, &str> {
+        self.entry_checksums
+            .lines()
+            .filter_map(|line| line.split_once(' '))
+            .map(|(checksum, path)| (path.to_string(), checksum))
+            .collect()
+    }
+
+    /// `--dedup` 的重复文件与其原文件的包内路径
+    pub fn duplicates(&self) -> Vec<(&str, &str)> {
+        self.dedup
+            .lines()
+            .filter_map(|line| line.split_once('\t'))
+            .collect()
+    }
+
+    pub fn to_json(&self) -> Result<Vec<u8>, BundleError> {
+        serde_json::to_vec(self).map_err(|e| BundleError::InvalidManifest(e.to_string()))
+    }
+
+    // 写入清单与尾部，应在资源数据之后调用
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<(), BundleError> {
+        // 超过上限的清单启动器无法读取（例如 `--store-aligned` 的文件索引过长）
+        let json = self.to_json()?;
+        if json.len() as u64 > MAX_MANIFEST_LEN {
+            return Err(BundleError::InvalidManifest(format!(
+                "manifest is too large ({} bytes)",
+                json.len()
+            )));
+        }
+        writer.write_all(&json)?;
+        Footer {
+            manifest_offset: self.resource_end(),
+            bundle_len: self.resource_end() + json.len() as u64 + FOOTER_LEN as u64,
+        }
+        .write_to(writer)?;
+        Ok(())
+    }
+
+    // 通过文件末尾的尾部定位并读取清单
+    pub fn read_from<R: Read + Seek>(reader: &mut R) -> Result<Manifest, BundleError> {
+        let (footer, base) = Footer::read_from(reader)?;
+        // 附加在宿主程序之后的打包数据中的偏移不是相对于文件开头，需通过 `footer::locate` 读取
+        if base != 0 {
+            return Err(BundleError::InvalidFooter(format!(
+                "the bundle starts at offset {} of the file instead of its beginning",
+                base
+            )));
+        }
+        // 偏移指向启动器或资源数据（如为0）时，那里不是清单的JSON对象，不必读入整段再解析
+        let mut first = [0u8];
+        reader.seek(SeekFrom::Start(footer.manifest_offset))?;
+        reader.read_exact(&mut first)?;
+        if first != *b"{" {
+            return Err(BundleError::InvalidFooter(format!(
+                "manifest offset {} does not point at the manifest",
+                footer.manifest_offset
+            )));
+        }
+        let manifest_len = footer.manifest_end() - footer.manifest_offset;
+        if manifest_len > MAX_MANIFEST_LEN {
+            return Err(BundleError::InvalidManifest(format!(
+                "manifest is too large ({} bytes)",
+                manifest_len
+            )));
+        }
+
+        let mut json = vec![0u8; manifest_len as usize];
+        reader.seek(SeekFrom::Start(footer.manifest_offset))?;
+        reader.read_exact(&mut json)?;
+        let manifest: Manifest = serde_json::from_slice(&json)
+            .map_err(|e| BundleError::InvalidManifest(e.to_string()))?;
+
+        // 资源数据必须完整位于清单之前
+        let resource_end = manifest.resource_offset.checked_add(manifest.resource_len);
+        if resource_end != Some(footer.manifest_offset) {
+            return Err(BundleError::InvalidManifest(
+                "resource range does not match the manifest offset".to_string(),
+            ));
+        }
+        if !is_valid_entry_name(&manifest.entry_name) {
+            return Err(BundleError::InvalidEntryName(manifest.entry_name));
+        }
+
+        Ok(manifest)
+    }
+}
+
+fn default_cache_hash() -> CacheHash {
+    CacheHash::Sha256
+}
+
+fn default_workdir() -> WorkDir {
+    WorkDir::Invocation
+}
+
+fn default_entry_mode() -> u32 {
+    DEFAULT_ENTRY_MODE
+}
+
+/// 解析八进制的权限（如 `0700`），不能包含 setuid 等特殊位，并且文件所有者必须可执行
+pub fn parse_entry_mode(text: &str) -> Result<u32, String> {
+    let mode = u32::from_str_radix(text, 8)
+        .map_err(|_| format!("'{}' is not an octal mode (e.g. 0700)", text))?;
+    check_entry_mode(mode)?;
+    Ok(mode)
+}
+
+/// 见 [`parse_entry_mode`]
+pub fn check_entry_mode(mode: u32) -> Result<(), String> {
+    if mode > 0o777 {
+        return Err(format!(
+            "{:04o} sets bits outside 0777 (setuid, setgid or sticky)",
+            mode
+        ));
+    }
+    if mode & 0o100 == 0 {
+        return Err(format!(
+            "{:04o} does not let the owner execute the file",
+            mode
+        ));
+    }
+    Ok(())
+}
+
+/// 资源条目的路径只能由普通组成部分构成，不允许绝对路径或 `..`，否则解压时会写到目标目录之外
+pub fn is_contained_path(path: &Path) -> bool {
+    path.components()
+        .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+/// 解压目录前缀拼接随机字符后作为目录名，不能为空或包含路径分隔符
+pub fn is_valid_temp_prefix(prefix: &str) -> bool {
+    !prefix.is_empty() && !prefix.contains(['/', '\\', '\0'])
+}
+
+/// 解析 `--env` 的 `KEY=VALUE`：按第一个 `=` 拆分，值中可以再含有 `=`
+pub fn parse_env_var(var: &str) -> Result<(String, String), String> {
+    let (key, value) = var
+        .split_once('=')
+        .ok_or_else(|| format!("'{}' is not KEY=VALUE", var))?;
+    check_env_var(key, value)?;
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// `--env` 设置的变量名不能为空或含有 `=`，变量名与值都不能含有NUL、换行或回车（清单中每行记录一个变量）；
+/// `SEKAI_PACK_` 开头的变量由启动器自身使用
+pub fn check_env_var(key: &str, value: &str) -> Result<(), String> {
+    if key.is_empty() || key.contains('=') {
+        return Err(format!("invalid variable name '{}'", key));
+    }
+    if key.contains(['\0', '\n', '\r']) || value.contains(['\0', '\n', '\r']) {
+        return Err(format!("'{}' contains a NUL or newline character", key));
+    }
+    if key.starts_with("SEKAI_PACK_") {
+        return Err(format!("'{}' is reserved for the launcher", key));
+    }
+    Ok(())
+}
+
+/// 主程序文件名必须是单个普通路径组成部分，不能包含目录或 `..`
+pub fn is_valid_entry_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && !name.contains(['/', '\\', '\0'])
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// 描述从 `resource_offset` 开始、未压缩的资源数据 `resources` 的最简清单
+    pub(crate) fn test_manifest(resource_offset: u64, resources: &[u8]) -> Manifest {
+        serde_json::from_value(serde_json::json!({
+            "tool_version": "0.0.0",
+            "created_at": 0,
+            "entry_name": "app",
+            "launch_args": "",
+            "compression": "none",
+            "checksum_type": "crc32",
+            "checksum": Checksum::Crc32.compute(resources).unwrap(),
+            "cache": false,
+            "resource_offset": resource_offset,
+            "resource_len": resources.len(),
+        }))
+        .unwrap()
+    }
+
+    /// `[资源数据][清单][尾部]` 形式的打包数据
+    pub(crate) fn test_bundle(resources: &[u8]) -> Vec<u8> {
+        let mut bundle = resources.to_vec();
+        test_manifest(0, resources).write_to(&mut bundle).unwrap();
+        bundle
+    }
+
+    #[test]
+    fn workdir_defaults() {
+        assert_eq!(WorkDir::default(), WorkDir::Temp);
+        // 旧版打包文件没有 `workdir` 字段，在运行打包文件时所在的目录中运行
+        assert_eq!(test_manifest(0, b"").workdir, WorkDir::Invocation);
+    }
+
+    #[test]
+    fn env_var_rejections() {
+        assert_eq!(
+            parse_env_var("GREETING=hello=world"),
+            Ok(("GREETING".to_string(), "hello=world".to_string()))
+        );
+        assert!(parse_env_var("GREETING").is_err());
+        assert!(parse_env_var("=value").is_err());
+        assert!(check_env_var("SEKAI_PACK_DIR", "x").is_err());
+        for value in ["a\0b", "a\nb", "a\r\nb", "a\rb"] {
+            assert_eq!(
+                check_env_var("KEY", value),
+                Err("'KEY' contains a NUL or newline character".to_string())
+            );
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// 按大小拆分输出文件，以及将拆分后的各部分重新拼接为完整的打包文件
+//
+// 拆分后的文件依次命名为 `<输出>.000`、`<输出>.001`……，除最后一个外大小均为拆分大小，
+// 另有 `<输出>.index` 记录部分数、拆分大小、总大小与完整文件的sha256。
+
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use log::info;
+use serde::{Deserialize, Serialize};
+
+use crate::BundleError;
+use crate::checksum::Checksum;
+
+// 拆分索引，与各部分位于同一目录
+#[derive(Serialize, Deserialize)]
+struct SplitIndex {
+    parts: u64,
+    part_size: u64,
+    total_len: u64,
+    sha256: String,
+}
+
+/// 第 `index` 部分的文件名：`<output>.000` 起依次编号
+pub fn part_path(output: &Path, index: u64) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(format!(".{:03}", index));
+    PathBuf::from(name)
+}
+
+fn index_path(output: &Path) -> PathBuf {
+    let mut name = output.as_os_str().to_owned();
+    name.push(".index");
+    PathBuf::from(name)
+}
+
+// 将已完成的输出文件拆分为不超过 `part_size` 字节的部分并写入索引，随后删除原文件；
+// 自解压文件的启动器须完整位于第一部分中（由调用方检查）
+pub(crate) fn split_output(output: &Path, part_size: u64) -> Result<(), BundleError> {
+    let total_len = fs::metadata(output)?.len();
+    let sha256 = Checksum::Sha256.compute(fs::File::open(output)?)?;
+    let parts = total_len.div_ceil(part_size).max(1);
+    info!(
+        "Splitting {} ({} bytes) into {} parts of up to {} bytes...",
+        output.display(),
+        total_len,
+        parts,
+        part_size
+    );
+
+    let mut source = fs::File::open(output)?;
+    for index in 0..parts {
+        let path = part_path(output, index);
+        let mut part = fs::File::create(&path)?;
+        io::copy(&mut (&mut source).take(part_size), &mut part)?;
+        part.sync_all()?;
+    }
+    // 第一部分包含启动器，保留输出文件的权限以便直接运行
+    fs::set_permissions(part_path(output, 0), fs::metadata(output)?.permissions())?;
+
+    // 删除之前拆分留下的多余部分，否则启动器会把它们也拼接进来
+    let mut index = parts;
+    while fs::remove_file(part_path(output, index)).is_ok() {
+        index += 1;
+    }
+
+    let split_index = SplitIndex {
+        parts,
+        part_size,
+        total_len,
+        sha256,
+    };
+    let json = serde_json::to_vec_pretty(&split_index)
+        .map_err(|e| BundleError::InvalidManifest(e.to_string()))?;
+    fs::write(index_path(output), json)?;
+    fs::remove_file(output)?;
+    Ok(())
+}
+
+/// 将 `--split-size` 拆分的各部分按索引拼接为 `output`：`first_part` 为 `<输出>.000`，
+/// 索引 `<输出>.index` 位于同一目录；部分缺失、大小或sha256与索引不符时报错
+pub fn join(first_part: &Path, output: &Path) -> Result<(), BundleError> {
+    let base = first_part
+        .to_str()
+        .and_then(|path| path.strip_suffix(".000"))
+        .map(PathBuf::from)
+        .ok_or_else(|| {
+            BundleError::InvalidOptions(format!(
+                "'{}' is not the first part of a split bundle (expected a name ending in .000)",
+                first_part.display()
+            ))
+        })?;
+    let index_file = index_path(&base);
+    let index: SplitIndex = serde_json::from_slice(&fs::read(&index_file).map_err(|e| {
+        BundleError::InvalidOptions(format!("Cannot read '{}': {}", index_file.display(), e))
+    })?)
+    .map_err(|e| {
+        BundleError::InvalidOptions(format!(
+            "Invalid split index '{}': {}",
+            index_file.display(),
+            e
+        ))
+    })?;
+
+    // 先确认每个部分都存在且大小正确
+    let mut remaining = index.total_len;
+    for part in 0..index.parts {
+        let path = part_path(&base, part);
+        let expected = remaining.min(index.part_size);
+        let len = fs::metadata(&path)
+            .map_err(|e| {
+                BundleError::InvalidOptions(format!("Missing part '{}': {}", path.display(), e))
+            })?
+            .len();
+        if len != expected {
+            return Err(BundleError::InvalidOptions(format!(
+                "Part '{}' is {} bytes, expected {}",
+                path.display(),
+                len,
+                expected
+            )));
+        }
+        remaining -= expected;
+    }
+
+    let dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut joined = tempfile::Builder::new()
+        .prefix(".sekaipack_join_")
+        .tempfile_in(dir)?;
+    for part in 0..index.parts {
+        io::copy(
+            &mut fs::File::open(part_path(&base, part))?,
+            joined.as_file_mut(),
+        )?;
+    }
+    joined.as_file_mut().flush()?;
+    if Checksum::Sha256.compute(fs::File::open(joined.path())?)? != index.sha256 {
+        return Err(BundleError::ChecksumMismatch);
+    }
+    joined.as_file().sync_all()?;
+    fs::set_permissions(joined.path(), fs::metadata(first_part)?.permissions())?;
+    joined
+        .persist(output)
+        .map_err(|e| BundleError::Io(e.error))?;
+    Ok(())
+}
@@ -0,0 +1,181 @@
+// --pack-launcher 的解压桩：把尾部之前的gzip数据（完整的自解压打包文件）解压到内存文件中并直接执行
+// 仅支持Linux（memfd_create 与 fexecve）
+#define _GNU_SOURCE
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <unistd.h>
+#include <fcntl.h>
+#include <stdint.h>
+#include <limits.h>
+#include <errno.h>
+#include <sys/mman.h>
+#include <zlib.h>
+
+// 与 footer.rs 中的定义保持一致
+#define FOOTER_LEN 25
+#define FOOTER_VERSION 7
+#define FOOTER_MAGIC "SEKAIPK1"
+#define MAX_MANIFEST_LEN (1024 * 1024)
+
+// 告知解压出的启动器原打包文件的路径，与 manifest.rs 中的 PACKED_ENV 保持一致
+#define PACKED_ENV "SEKAI_PACK_PACKED"
+
+// 与 manifest.rs 中的退出码保持一致
+#define EXIT_EXTRACT_FAILED 120
+#define EXIT_INTEGRITY_FAILED 121
+#define EXIT_EXEC_FAILED 122
+
+extern char **environ;
+
+// 读取清单中的非负整数字段，没有该字段时返回 -1
+static int manifest_u64(const char *json, const char *key, uint64_t *value) {
+    char pattern[64];
+    snprintf(pattern, sizeof(pattern), "\"%s\":", key);
+    const char *p = strstr(json, pattern);
+    if (!p) {
+        return -1;
+    }
+    char *end;
+    errno = 0;
+    *value = strtoull(p + strlen(pattern), &end, 10);
+    return errno == 0 && end != p + strlen(pattern) ? 0 : -1;
+}
+
+// 将 fd 中 [offset, offset + len) 的gzip数据解压写入 out_fd
+static int inflate_range(int fd, uint64_t offset, uint64_t len, int out_fd) {
+    if (lseek(fd, offset, SEEK_SET) == -1) {
+        return EXIT_EXTRACT_FAILED;
+    }
+    z_stream stream;
+    memset(&stream, 0, sizeof(stream));
+    // 16 + MAX_WBITS：只接受gzip格式
+    if (inflateInit2(&stream, 16 + MAX_WBITS) != Z_OK) {
+        return EXIT_EXTRACT_FAILED;
+    }
+    unsigned char in[65536];
+    unsigned char out[65536];
+    int ret = Z_OK;
+    while (ret != Z_STREAM_END && len > 0) {
+        ssize_t n = read(fd, in, len < sizeof(in) ? len : sizeof(in));
+        if (n <= 0) {
+            inflateEnd(&stream);
+            return EXIT_EXTRACT_FAILED;
+        }
+        len -= n;
+        stream.next_in = in;
+        stream.avail_in = n;
+        do {
+            stream.next_out = out;
+            stream.avail_out = sizeof(out);
+            ret = inflate(&stream, Z_NO_FLUSH);
+            if (ret != Z_OK && ret != Z_STREAM_END && ret != Z_BUF_ERROR) {
+                inflateEnd(&stream);
+                return EXIT_INTEGRITY_FAILED;
+            }
+            size_t have = sizeof(out) - stream.avail_out;
+            if (have > 0 && write(out_fd, out, have) != (ssize_t)have) {
+                inflateEnd(&stream);
+                return EXIT_EXTRACT_FAILED;
+            }
+        } while (stream.avail_out == 0);
+    }
+    inflateEnd(&stream);
+    // gzip尾部的CRC32与长度在 Z_STREAM_END 时已校验
+    return ret == Z_STREAM_END ? 0 : EXIT_INTEGRITY_FAILED;
+}
+
+int main(int argc, char *argv[]) {
+    (void)argc;
+    char exe_path[PATH_MAX];
+    ssize_t path_len = readlink("/proc/self/exe", exe_path, sizeof(exe_path) - 1);
+    int fd = open("/proc/self/exe", O_RDONLY);
+    if (path_len == -1 || fd == -1) {
+        perror("Failed to open executable");
+        return EXIT_EXTRACT_FAILED;
+    }
+    exe_path[path_len] = '\0';
+    // 打包文件在启动后被删除时链接目标带有此后缀，传给启动器的是原路径
+    const char *deleted = " (deleted)";
+    size_t suffix_len = strlen(deleted);
+    if ((size_t)path_len > suffix_len && strcmp(exe_path + path_len - suffix_len, deleted) == 0) {
+        exe_path[path_len - suffix_len] = '\0';
+    }
+
+    // 尾部：清单偏移与总长度（u64 小端）、格式版本与魔数
+    off_t file_size = lseek(fd, 0, SEEK_END);
+    unsigned char footer[FOOTER_LEN];
+    if (file_size < FOOTER_LEN || pread(fd, footer, FOOTER_LEN, file_size - FOOTER_LEN) != FOOTER_LEN
+        || memcmp(footer + 17, FOOTER_MAGIC, 8) != 0 || footer[16] != FOOTER_VERSION) {
+        fprintf(stderr, "not a sekai-pack bundle\n");
+        return EXIT_INTEGRITY_FAILED;
+    }
+    uint64_t manifest_offset = 0, bundle_len = 0;
+    for (int i = 7; i >= 0; i--) {
+        manifest_offset = manifest_offset << 8 | footer[i];
+        bundle_len = bundle_len << 8 | footer[8 + i];
+    }
+    if (bundle_len != (uint64_t)file_size) {
+        fprintf(stderr, "Invalid bundle footer: bundle length %llu does not match the file size (%lld bytes); "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)bundle_len, (long long)file_size);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    uint64_t manifest_end = file_size - FOOTER_LEN;
+    char first;
+    if (manifest_offset >= manifest_end) {
+        fprintf(stderr, "Invalid bundle footer: manifest offset %llu is past the end of the file (%lld bytes); "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)manifest_offset, (long long)file_size);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    if (pread(fd, &first, 1, manifest_offset) != 1 || first != '{') {
+        fprintf(stderr, "Invalid bundle footer: manifest offset %llu does not point at the manifest; "
+                "the bundle is truncated or corrupted\n",
+                (unsigned long long)manifest_offset);
+        return EXIT_INTEGRITY_FAILED;
+    }
+    if (manifest_end - manifest_offset > MAX_MANIFEST_LEN) {
+        fprintf(stderr, "Invalid bundle manifest\n");
+        return EXIT_INTEGRITY_FAILED;
+    }
+    size_t manifest_len = manifest_end - manifest_offset;
+    char *manifest = malloc(manifest_len + 1);
+    if (!manifest || pread(fd, manifest, manifest_len, manifest_offset) != (ssize_t)manifest_len) {
+        perror("Failed to read manifest");
+        return EXIT_EXTRACT_FAILED;
+    }
+    manifest[manifest_len] = '\0';
+
+    // 清单由sekaipack生成，这里只需要压缩数据的位置
+    uint64_t offset, len;
+    if (manifest_u64(manifest, "resource_offset", &offset) == -1
+        || manifest_u64(manifest, "resource_len", &len) == -1
+        || offset > manifest_offset || len != manifest_offset - offset) {
+        fprintf(stderr, "Invalid bundle manifest\n");
+        return EXIT_INTEGRITY_FAILED;
+    }
+    free(manifest);
+
+    int mem_fd = memfd_create("sekai-pack", MFD_CLOEXEC);
+    if (mem_fd == -1) {
+        perror("Failed to create memory file");
+        return EXIT_EXTRACT_FAILED;
+    }
+    int code = inflate_range(fd, offset, len, mem_fd);
+    if (code == EXIT_INTEGRITY_FAILED) {
+        fprintf(stderr, "Failed to decompress the bundle: data is corrupted\n");
+        return code;
+    } else if (code != 0) {
+        fprintf(stderr, "Failed to decompress the bundle: %s\n", strerror(errno));
+        return code;
+    }
+    close(fd);
+
+    if (setenv(PACKED_ENV, exe_path, 1) == -1) {
+        return EXIT_EXEC_FAILED;
+    }
+    fexecve(mem_fd, argv, environ);
+    fprintf(stderr, "Failed to execute the bundle: %s\n", strerror(errno));
+    return EXIT_EXEC_FAILED;
+}
@@ -0,0 +1,112 @@
+// 解压资源数据：启动器、`extract` 子命令与 `bench` 共用同一套解压流程
+//
+// 资源数据由一段或两段（`--compress-executable` 时可执行文件单独一段）压缩的tar流组成，
+// 各段依次解压到同一目录；读取各段的方式（映射、文件流或解密后的数据）由调用方决定。
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+
+use crate::BundleError;
+use crate::compression::Compression;
+use crate::manifest::{self, Manifest};
+
+/// 把资源数据中的一段（见 [`Manifest::sections`]）以 `compression` 解压到 `dir`，
+/// 各段依次调用即可得到完整的资源；`--dedup` 的重复文件在其原文件解压后随即重建。
+/// `verify_entries` 时回读每个写出的文件，与 `--entry-checksums` 记录的校验和比对
+pub fn unpack_section<R: Read>(
+    manifest: &Manifest,
+    compression: Compression,
+    section: R,
+    dir: &Path,
+    verify_entries: bool,
+) -> Result<(), BundleError> {
+    let decoder = compression
+        .decoder(section)
+        .map_err(BundleError::ArchiveFailed)?;
+    let mut archive = tar::Archive::new(decoder);
+    // 恢复打包时记录的权限与修改时间
+    archive.set_preserve_mtime(true);
+    unpack_archive(&mut archive, manifest, dir, verify_entries)
+}
+
+// 依次解压所有条目，路径会落到 `dir` 之外（`..` 或绝对路径）时直接报错而不是写入；
+// tar 的 `unpack` 会静默跳过这类条目。与其相同，目录条目最后按深度从深到浅创建，
+// 以免只读目录的权限妨碍其中文件的解压与重复文件的重建
+fn unpack_archive<R: Read>(
+    archive: &mut tar::Archive<R>,
+    manifest: &Manifest,
+    dir: &Path,
+    verify_entries: bool,
+) -> Result<(), BundleError> {
+    let dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+    let checksums = manifest.entry_checksum_map();
+    let mut duplicates: HashMap<&Path, Vec<&Path>> = HashMap::new();
+    for (path, original) in manifest.duplicates() {
+        duplicates
+            .entry(Path::new(original))
+            .or_default()
+            .push(Path::new(path));
+    }
+
+    let mut directories = Vec::new();
+    for entry in archive.entries().map_err(BundleError::ArchiveFailed)? {
+        let mut entry = entry.map_err(BundleError::ArchiveFailed)?;
+        let path = entry.path().map_err(BundleError::ArchiveFailed)?;
+        if !manifest::is_contained_path(&path) {
+            return Err(BundleError::UnsafeEntry {
+                entry: path.into_owned(),
+                dest: dir,
+            });
+        }
+        if entry.header().entry_type().is_dir() {
+            directories.push(entry);
+            continue;
+        }
+        let path = path.into_owned();
+        entry.unpack_in(&dir).map_err(BundleError::ArchiveFailed)?;
+        if verify_entries
+            && entry.header().entry_type().is_file()
+            && let Some(expected) = checksums.get(path.to_string_lossy().as_ref())
+        {
+            let file = fs::File::open(dir.join(&path)).map_err(BundleError::ArchiveFailed)?;
+            let actual = manifest
+                .checksum_type
+                .compute(io::BufReader::new(file))
+                .map_err(BundleError::ArchiveFailed)?;
+            if actual != *expected {
+                return Err(BundleError::EntryChecksumMismatch(vec![path]));
+            }
+        }
+        for duplicate in duplicates.remove(path.as_path()).unwrap_or_default() {
+            restore_duplicate(&dir, &path, duplicate)?;
+        }
+    }
+    directories.sort_by(|a, b| b.path_bytes().cmp(&a.path_bytes()));
+    for mut entry in directories {
+        entry.unpack_in(&dir).map_err(BundleError::ArchiveFailed)?;
+    }
+    Ok(())
+}
+
+// 以硬链接重建重复文件，无法创建硬链接（如文件系统不支持）时复制原文件
+fn restore_duplicate(dir: &Path, original: &Path, duplicate: &Path) -> Result<(), BundleError> {
+    if !manifest::is_contained_path(duplicate) {
+        return Err(BundleError::UnsafeEntry {
+            entry: duplicate.to_path_buf(),
+            dest: dir.to_path_buf(),
+        });
+    }
+    let (original, duplicate) = (dir.join(original), dir.join(duplicate));
+    let restore = || -> io::Result<()> {
+        if let Some(parent) = duplicate.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if fs::hard_link(&original, &duplicate).is_err() {
+            fs::copy(&original, &duplicate)?;
+        }
+        Ok(())
+    };
+    restore().map_err(BundleError::ArchiveFailed)
+}
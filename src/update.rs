@@ -0,0 +1,126 @@
+// 原地替换已生成的打包文件中的资源：保留启动器与可执行文件，只重新生成资源数据与清单
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::inspect::{open_resources, tar_archive};
+use crate::manifest::{self, Layout, Manifest};
+use crate::{BundleError, BundleOptions, Progress, launcher};
+
+/// 用新的资源目录替换打包文件中的资源，启动器原样保留，可执行文件、附加数据文件、启动方式、压缩与校验方式沿用原清单；
+/// 先写入同一目录中的临时文件，完成后再替换原文件。加密的打包文件需要提供口令，并以同一口令重新加密
+pub fn update(
+    bundle: &Path,
+    resource_dirs: &[PathBuf],
+    passphrase: Option<&str>,
+    progress: &mut dyn FnMut(&Progress),
+) -> Result<(), BundleError> {
+    // 修改前先确认尾部、清单与启动器都有效
+    let manifest = Manifest::read_from(&mut fs::File::open(bundle)?)?;
+    if manifest.layout != Layout::SelfExtract {
+        return Err(BundleError::InvalidOptions(format!(
+            "'{}' is not a self-extracting bundle",
+            bundle.display()
+        )));
+    }
+    let (launcher, _) = launcher::read_reusable_launcher(bundle)?;
+
+    let names = manifest.executable_names();
+    let (_, resources) = open_resources(bundle, passphrase)?;
+    let exe_dir = tempfile::Builder::new()
+        .prefix("sekaipack_update_")
+        .tempdir()?;
+    // 附加数据文件解压到单独的子目录，以免与可执行文件重名
+    let extract_exes = || -> io::Result<(Vec<PathBuf>, Vec<PathBuf>)> {
+        let (mut exes, mut data) = (Vec::new(), Vec::new());
+        let mut archive = tar_archive(&manifest, resources.decode(&manifest)?);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let is_file = entry.header().entry_type().is_file();
+            if is_file && manifest.is_executable_entry(&path) {
+                let dest = exe_dir.path().join(&path);
+                entry.unpack(&dest)?;
+                exes.push(dest);
+            } else if is_file && manifest.is_data_entry(&path) {
+                let dest = exe_dir.path().join(&path);
+                fs::create_dir_all(exe_dir.path().join(manifest::DATA_DIR))?;
+                entry.unpack(&dest)?;
+                data.push(dest);
+            } else {
+                // 跳过条目内容
+                io::copy(&mut entry, &mut io::sink())?;
+            }
+        }
+        Ok((exes, data))
+    };
+    let (mut exes, append_data) = extract_exes().map_err(BundleError::ArchiveFailed)?;
+    if !names.is_empty() {
+        // 按清单中的顺序排列，保证主程序在前
+        if exes.len() != names.len() {
+            return Err(BundleError::InvalidManifest(
+                "executables listed in the manifest are missing from the resources".to_string(),
+            ));
+        }
+        exes.sort_by_key(|exe| {
+            names
+                .iter()
+                .position(|name| exe.file_name() == Some(name.as_ref()))
+        });
+    }
+    if exes.is_empty() {
+        return Err(BundleError::InvalidManifest(
+            "the bundle contains no executables".to_string(),
+        ));
+    }
+    let main_exe = exes.remove(0);
+
+    let opts = BundleOptions {
+        launcher,
+        compression: manifest.compression,
+        checksum: manifest.checksum_type,
+        entry_checksums: !manifest.entry_checksums.is_empty(),
+        cache: manifest.cache,
+        cache_hash: manifest.cache_hash,
+        prefer_memory: manifest.prefer_memory,
+        set_library_path: manifest.set_library_path,
+        store_aligned: !manifest.aligned_index.is_empty(),
+        exe_compression: manifest.exe_compression,
+        extra_exes: exes,
+        append_data,
+        entry: Some(manifest.entry_name.clone()),
+        launch_arg_template: Some(manifest.launch_args.clone()).filter(|args| !args.is_empty()),
+        temp_prefix: Some(manifest.temp_prefix.clone()).filter(|prefix| !prefix.is_empty()),
+        pre_launch: Some(manifest.pre_launch.clone()).filter(|cmd| !cmd.is_empty()),
+        env: manifest
+            .env_vars()
+            .into_iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect(),
+        notice: Some(manifest.notice.clone()).filter(|notice| !notice.is_empty()),
+        passphrase: passphrase.map(str::to_string),
+        entry_mode: manifest.entry_mode,
+        workdir: manifest.workdir,
+        reuse_launcher: Some(bundle.to_path_buf()),
+        // 包内的可执行文件在打包时已检查过（或以 --no-exe-check 跳过）
+        no_exe_check: true,
+        // 输出是刚创建的临时文件
+        force: true,
+        ..BundleOptions::default()
+    };
+
+    let dir = match bundle.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = tempfile::Builder::new()
+        .prefix(".sekaipack_update_")
+        .tempfile_in(dir)?;
+    crate::bundle_with_progress(&main_exe, resource_dirs, temp.path(), opts, progress)?;
+
+    // 保留原文件的权限
+    fs::set_permissions(temp.path(), fs::metadata(bundle)?.permissions())?;
+    temp.persist(bundle).map_err(|e| BundleError::Io(e.error))?;
+    Ok(())
+}